@@ -0,0 +1,26 @@
+use std::path::Path;
+use std::process::Command;
+
+// Record the commit being built, for generation metadata
+fn main() {
+    let revision = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    if let Some(revision) = revision {
+        println!("cargo:rustc-env=KEYWHISKER_GIT_REV={revision}");
+    }
+    // Outside a checkout, as when built from crates.io, there's no revision to follow
+    if !Path::new(".git").is_dir() {
+        println!("cargo:rerun-if-changed=build.rs");
+        return;
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    // Refs move here when git packs them, as `git gc` does
+    if Path::new(".git/packed-refs").exists() {
+        println!("cargo:rerun-if-changed=.git/packed-refs");
+    }
+}
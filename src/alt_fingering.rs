@@ -0,0 +1,150 @@
+use crate::analysis::layout_matrices;
+use crate::fingers::finger_index;
+
+use anyhow::{ensure, Context, Result};
+use keycat::analysis::Nstroke;
+use keycat::{Corpus, NgramType};
+use keymeow::{Keyboard, LayoutData, MetricContext, MetricData};
+use std::collections::HashSet;
+
+/// Pairs of non-combo keys, in both orders, that share a finger but sit in
+/// adjacent columns, so a typist can take one of them with the neighbouring
+/// finger instead. Thumbs are left out, having no neighbour to hand off to.
+pub fn alt_fingered_pairs(keyboard: &Keyboard) -> Vec<(usize, usize)> {
+    let keys: Vec<f32> = keyboard
+        .keys
+        .map
+        .iter()
+        .flatten()
+        .map(|k| k.x + k.w / 2.0)
+        .collect();
+    let fingers: Vec<usize> = keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .map(finger_index)
+        .collect();
+    let mut pairs = vec![];
+    for a in 0..keys.len() {
+        for b in 0..keys.len() {
+            let columns = (keys[a] - keys[b]).abs();
+            let thumb = matches!(fingers[a], 4 | 5);
+            if fingers[a] == fingers[b] && !thumb && (0.5..1.5).contains(&columns) {
+                pairs.push((a, b));
+            }
+        }
+    }
+    pairs
+}
+
+/// Scale the same-finger bigram and skipgram metrics' amounts on alt-fingerable
+/// pairs by `factor`, so they count them at a reduced cost. Other metrics on
+/// those pairs, like stretches, are left as they are.
+pub fn apply(metric_data: &mut MetricData, factor: f32) -> Result<()> {
+    ensure!(
+        (0.0..=1.0).contains(&factor),
+        "the alt-fingering factor must be between 0 and 1"
+    );
+    let pairs: HashSet<(usize, usize)> = alt_fingered_pairs(&metric_data.keyboard)
+        .into_iter()
+        .collect();
+    let same_finger = same_finger_metrics(metric_data);
+    for stroke in &mut metric_data.strokes {
+        let Nstroke::Bistroke([a, b]) = stroke.nstroke else {
+            continue;
+        };
+        if !pairs.contains(&(a, b)) {
+            continue;
+        }
+        for amount in stroke.amounts.iter_mut().filter(|a| same_finger[a.metric]) {
+            amount.amount *= factor;
+        }
+    }
+    Ok(())
+}
+
+/// Which metrics are same-finger bigram or skipgram metrics, like SFB and SFS:
+/// those counting some bigrams, and only ones typed with one finger.
+fn same_finger_metrics(metric_data: &MetricData) -> Vec<bool> {
+    let fingers: Vec<usize> = metric_data
+        .keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .map(finger_index)
+        .collect();
+    let finger = |p: usize| fingers.get(p).copied();
+    let mut counted = vec![false; metric_data.metrics.len()];
+    let mut same_finger: Vec<bool> = metric_data
+        .metrics
+        .iter()
+        .map(|m| matches!(m.ngram_type, NgramType::Bigram | NgramType::Skipgram))
+        .collect();
+    for stroke in &metric_data.strokes {
+        let Nstroke::Bistroke([a, b]) = stroke.nstroke else {
+            continue;
+        };
+        // Combo keys have no finger of their own to tell by
+        let (Some(a), Some(b)) = (finger(a), finger(b)) else {
+            continue;
+        };
+        for amount in stroke.amounts.iter().filter(|a| a.amount != 0.0) {
+            counted[amount.metric] = true;
+            same_finger[amount.metric] &= a == b;
+        }
+    }
+    same_finger
+        .iter()
+        .zip(counted)
+        .map(|(s, c)| *s && c)
+        .collect()
+}
+
+/// Print the bigrams of `layout` that `apply` would count as alt-fingered,
+/// most frequent first, with their share of the corpus' bigrams.
+pub fn report(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+    top: usize,
+) -> Result<()> {
+    let pairs = alt_fingered_pairs(&metric_data.keyboard);
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let corpus = &ctx.analyzer.corpus;
+
+    let total = corpus
+        .bigrams
+        .iter()
+        .map(|c| *c as f64)
+        .sum::<f64>()
+        .max(1.0);
+    let mut bigrams: Vec<(String, f64)> = pairs
+        .iter()
+        .map(|(a, b)| (matrix.0[*a], matrix.0[*b]))
+        .filter(|(a, b)| *a != 0 && *b != 0)
+        .map(|(a, b)| {
+            let name: String = [corpus.uncorpus_unigram(a), corpus.uncorpus_unigram(b)]
+                .iter()
+                .collect();
+            let count = corpus.bigrams[corpus.bigram_idx(a, b)] as f64;
+            (name, count / total * 100.0)
+        })
+        .collect();
+    bigrams.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let sum: f64 = bigrams.iter().map(|(_, p)| p).sum();
+    for (bigram, percentage) in bigrams.iter().take(top) {
+        println!("{bigram:<4}{percentage:>7.3}%");
+    }
+    println!();
+    println!(
+        "{:<4}{sum:>7.3}% of bigrams in {} pairs",
+        "All",
+        bigrams.len()
+    );
+    Ok(())
+}
@@ -1,119 +1,41 @@
-use crate::GenerationStrategy;
-use crate::ddako::simulated_annealing as ddako_sa;
+use crate::blank;
+use crate::cache::{filtered_metric_data, MetricCache};
+use crate::checkpoint::{recorded_runs, Checkpoint};
+use crate::config::Config;
+use crate::evaluation::{Evaluator, Reference};
+use crate::fingers::Hand;
+use crate::history::{self, HistoryEntry};
+use crate::interrupt;
+use crate::keyboard::{hand_positions, keyboard_text, merge_metrics, mirror_positions};
+use crate::layers::{add_layers, layered_layout, layered_stats, Layer};
+use crate::lookup;
+use crate::metadata::{git_revision, iso_now, Provenance, RunMetadata};
+use crate::naming::{home_row_name, Names};
+use crate::optimization::{GenerationResult, GenerationStrategy, OptimizationContext};
+use crate::reporter::ProgressStyle;
+use crate::template;
+use crate::validate;
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
 use keycat::{
-    analysis::{Analyzer, MetricData as KcMetricData, NstrokeData, NstrokeIndex},
-    Corpus, CorpusChar, Layout, NgramType, Swap,
+    analysis::{
+        Analyzer, MetricAmount, MetricData as KcMetricData, Nstroke, NstrokeData, NstrokeIndex,
+    },
+    Corpus, CorpusChar, Layout, NgramType, Swap, Totals,
 };
-use keymeow::{LayoutData, MetricContext, MetricData};
-use linya::Progress;
-use rand::prelude::*;
+use keymeow::{Keyboard, LayoutData, MetricContext, MetricData};
 use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt::Write as StringWrite;
+use std::fs::create_dir_all;
 use std::path::Path;
-use std::{fs::File, io::Write, iter};
-use std::{fs::OpenOptions, io::LineWriter, sync::Mutex};
+use std::{fs::File, fs::OpenOptions, io::Write, iter};
 
 use std::time::Instant;
-use std::time::Duration;
-
-use indexmap::IndexMap;
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction},
-    style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, Borders, Row, Table, TableState},
-    Terminal,
-};
-
-fn print_hashmap(
-    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
-    mut table_state: &mut TableState,
-    map: &indexmap::IndexMap<&str, String>,
-) {
-    if atty::is(atty::Stream::Stdout) {
-        terminal.clear().unwrap();
-        terminal.draw(|f| {
-            let chunks = ratatui::layout::Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Percentage(100)].as_ref())
-                .split(f.area());
-
-            let table = Table::new(
-                map.iter().map(|(key, value)| {
-                    if *key == "Initial Temp Stats" {
-                        Row::new(vec![
-                            Span::styled(key.to_string(), Style::default().fg(Color::Gray)),
-                            Span::styled(value.to_string(), Style::default().fg(Color::White)),
-                        ])
-                    } else {
-                        Row::new(vec![
-                            Span::styled(key.to_string(), Style::default().fg(Color::Yellow)),
-                            Span::styled(value.to_string(), Style::default().fg(Color::White)),
-                        ])
-                    }
-                }),
-                &[
-                    Constraint::Percentage(30),
-                    Constraint::Percentage(70),
-                ]
-            )
-            .header(Row::new(vec![
-                Span::styled("Key", Style::default().add_modifier(Modifier::BOLD)),
-                Span::styled("Value", Style::default().add_modifier(Modifier::BOLD)),
-            ]))
-            .block(Block::default().borders(Borders::ALL).title("Keywhisker"));
-
-            if table_state.selected().is_none() {
-                table_state.select(Some(0));
-            }
-
-            f.render_stateful_widget(table, chunks[0], &mut table_state);
-        })
-        .unwrap();
-    }
-}
-
-fn create_rate_tracker<'a>(
-    mut terminal: &'a mut Terminal<CrosstermBackend<std::io::Stdout>>,
-    mut table_state: &'a mut TableState,
-) -> impl FnMut(&mut IndexMap<&str, String>) + use<'a> {
-    let mut last_print = Instant::now();
-    let mut last_call = Instant::now();
-    let mut calls = 0u64;
-    let mut min_interval = Duration::from_secs(u64::MAX);
-    let mut max_interval = Duration::from_secs(0);
-
-    move |rt_stats: &mut IndexMap<&str, String>| {
-        let now = Instant::now();
-        let interval = now.duration_since(last_call);
-        min_interval = min_interval.min(interval);
-        max_interval = max_interval.max(interval);
-        last_call = now;
-        calls += 1;
-
-        if now.duration_since(last_print) >= Duration::from_secs(3) {
-            let elapsed = now.duration_since(last_print);
-            let rate = calls as f64 / elapsed.as_secs_f64();
-            for (label, stat) in &mut *rt_stats {
-                match *label {
-                    "Evaluation Rate" => *stat = format!("{:.5} swaps/second", rate),
-                    "Min/Max Interval" => *stat = format!("{:?} \t/ {:?}", min_interval, max_interval),
-                    _ => (),
-                }
-            }
-            print_hashmap(&mut terminal, &mut table_state, &rt_stats);
-
-            // Reset stats
-            calls = 0;
-            last_print = now;
-            min_interval = Duration::from_secs(u64::MAX);
-            max_interval = Duration::from_secs(0);
-        }
-    }
-}
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
 
 pub fn kc_metric_data(metric_data: keymeow::MetricData, position_count: usize) -> KcMetricData {
     KcMetricData::from(
@@ -123,13 +45,103 @@ pub fn kc_metric_data(metric_data: keymeow::MetricData, position_count: usize) -
     )
 }
 
+/// Count `amount` of `metric` for `nstroke`, alongside any metrics it already has.
+pub fn add_stroke_amount(
+    metric_data: &mut MetricData,
+    nstroke: Nstroke,
+    metric: usize,
+    amount: f32,
+) {
+    let amount = MetricAmount { metric, amount };
+    match metric_data
+        .strokes
+        .iter_mut()
+        .find(|s| s.nstroke == nstroke)
+    {
+        Some(stroke) => stroke.amounts.push(amount),
+        None => metric_data.strokes.push(NstrokeData {
+            nstroke,
+            amounts: vec![amount],
+        }),
+    }
+}
+
 pub fn get_metric(s: &str, data: &MetricData) -> Result<usize> {
     data.metrics
         .iter()
         .enumerate()
         .find(|(_, m)| m.name == s || m.short == s)
         .map(|(i, _)| i)
-        .context("metric not found")
+        .with_context(|| {
+            let names = data
+                .metrics
+                .iter()
+                .flat_map(|m| [m.name.as_str(), m.short.as_str()]);
+            format!("metric not found{}", lookup::did_you_mean(s, names))
+        })
+}
+
+/// Which metrics a generation scores layouts by.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Ngrams {
+    All,
+    /// Only bigram metrics, so no trigram or skipgram strokes are analyzed
+    Bigram,
+}
+
+impl Ngrams {
+    /// Those of the metrics `names` that aren't scored, warning about them all
+    /// at once. Unknown metrics are kept, to be reported wherever they're
+    /// looked up.
+    pub fn dropped<'a>(
+        self,
+        names: impl IntoIterator<Item = &'a str>,
+        data: &MetricData,
+    ) -> Vec<String> {
+        let mut dropped: Vec<String> = vec![];
+        for name in names {
+            let Ok(metric) = get_metric(name, data) else {
+                continue;
+            };
+            let keeps = match self {
+                Ngrams::All => true,
+                Ngrams::Bigram => matches!(data.metrics[metric].ngram_type, NgramType::Bigram),
+            };
+            if !keeps && !dropped.iter().any(|d| d == name) {
+                dropped.push(name.to_string());
+            }
+        }
+        if !dropped.is_empty() {
+            warn!(
+                "leaving out {}, which aren't bigram metrics",
+                dropped.join(", ")
+            );
+        }
+        dropped
+    }
+}
+
+/// Look up several metrics by name or short name.
+pub fn resolve_metrics(names: &[String], metric_data: &MetricData) -> Result<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| get_metric(name, metric_data).with_context(|| format!("invalid metric {name}")))
+        .collect()
+}
+
+/// Look up the metrics of `name=weight` pairs.
+pub fn resolve_weights(
+    metrics: &[(String, i16)],
+    metric_data: &MetricData,
+) -> Result<Vec<(usize, i16)>> {
+    metrics
+        .iter()
+        .map(|(name, x)| {
+            get_metric(name, metric_data)
+                .with_context(|| format!("invalid metric {name}"))
+                .map(|m| (m, *x))
+        })
+        .collect()
 }
 
 pub fn filter_metrics(md: KcMetricData, metrics: &[usize]) -> KcMetricData {
@@ -151,11 +163,16 @@ pub fn filter_metrics(md: KcMetricData, metrics: &[usize]) -> KcMetricData {
     }
 }
 
-fn layout_from_charset(corpus: &Corpus, metric_data: &MetricData, char_set: &str) -> Layout {
-    let core_matrix: Vec<CorpusChar> = char_set.chars().map(|c| corpus.corpus_char(c)).collect();
+/// A layout of `char_set` in key order, with any remaining keys and combos left empty.
+pub fn layout_from_charset(corpus: &Corpus, metric_data: &MetricData, char_set: &str) -> Layout {
+    let core_matrix: Vec<CorpusChar> = char_set
+        .chars()
+        .map(|c| blank::corpus_char(corpus, c))
+        .collect();
     let matrix = core_matrix
         .iter()
-        .chain(iter::repeat(&0usize).take(
+        .chain(iter::repeat_n(
+            &0usize,
             metric_data.keyboard.keys.map.iter().flatten().count()
                 + metric_data.keyboard.combos.len()
                 - core_matrix.len(),
@@ -165,344 +182,675 @@ fn layout_from_charset(corpus: &Corpus, metric_data: &MetricData, char_set: &str
     Layout(matrix)
 }
 
-pub fn output_table(
-    metric_names: Vec<String>,
-    metric_data: keymeow::MetricData,
+/// A layout of `char_set` on just the keys at `positions`, in order, with
+/// every other key left empty.
+pub fn layout_on_keys(
+    corpus: &Corpus,
+    metric_data: &MetricData,
+    positions: &[usize],
+    char_set: &str,
+) -> Result<Layout> {
+    let count = char_set.chars().count();
+    ensure!(
+        count <= positions.len(),
+        "{count} characters don't fit on {} keys",
+        positions.len()
+    );
+    let mut layout = layout_from_charset(corpus, metric_data, "");
+    for (p, c) in positions.iter().zip(char_set.chars()) {
+        layout.0[*p] = blank::corpus_char(corpus, c);
+    }
+    Ok(layout)
+}
+
+/// A context generating layouts of `char_set` with the metrics weighted as
+/// `(name, weight)` pairs, for the bindings' simpler take on `generate`.
+pub fn weighted_context(
+    metric_data: MetricData,
     corpus: Corpus,
-    count: u64,
+    metrics: &[(String, i16)],
     char_set: &str,
-) -> Result<()> {
-    let metrics: Result<Vec<usize>, _> = metric_names
-        .iter()
-        .map(|s| get_metric(s, &metric_data))
-        .collect();
-    let metrics = metrics.context("invalid metric")?;
+    strategy: GenerationStrategy,
+    cache: Option<&MetricCache>,
+) -> Result<OptimizationContext> {
+    let weights = resolve_weights(metrics, &metric_data)?;
+    let used: Vec<usize> = weights.iter().map(|(m, _)| *m).collect();
     let layout = layout_from_charset(&corpus, &metric_data, char_set);
+    let keyboard = metric_data.keyboard.clone();
+    let data = filtered_metric_data(metric_data, &used, layout.0.len(), cache);
+    Ok(OptimizationContext::new(
+        strategy,
+        layout,
+        Analyzer::from(data, corpus),
+        Evaluator::from(weights),
+        keyboard,
+    ))
+}
 
-    let totals = layout.totals(&corpus);
-
-    let data = filter_metrics(kc_metric_data(metric_data, layout.0.len()), &metrics);
-    let analyzer = Analyzer::from(data, corpus);
-
-    let file = File::create("data/data.csv").context("couldn't create data file")?;
-    let mut writer = LineWriter::new(file);
-
-    for m in &metric_names {
-        write!(writer, "{m},")?;
-    }
-    writeln!(writer)?;
-    let progress = Mutex::new(Progress::new());
-    let bar = progress.lock().unwrap().bar(count.try_into()?, "Analyzing");
-
-    let threads: u64 = 64;
-    std::thread::scope(|s| {
-        for _ in 0..threads {
-            s.spawn(|| {
-                let count = &count.clone();
-                let mut stats = analyzer.calc_stats(&layout);
-                let mut layout = layout.clone();
-                let mut rng = thread_rng();
-                let file = OpenOptions::new()
-                    .create(false)
-                    .append(true)
-                    .open("data/data.csv")
-                    .unwrap();
-                let mut writer = LineWriter::new(file);
-                for _ in 0..count / threads {
-                    layout.0.shuffle(&mut rng);
-                    stats.iter_mut().for_each(|x| *x = 0.0);
-                    analyzer.recalc_stats(&mut stats, &layout);
-                    let mut s = String::new();
-                    for m in &metrics {
-                        let percent = totals.percentage(stats[*m], analyzer.data.metrics[*m]);
-                        s.push_str(&percent.to_string());
-                        s.push(',');
-                    }
-                    s.push('\n');
-                    writer.write_all(&s.into_bytes()).unwrap();
-                    progress.lock().unwrap().inc_and_draw(&bar, 1);
-                }
-            });
+/// The layout or its left-right mirror image, whichever sorts first, so that
+/// mirrored results compare equal.
+fn canonical_layout(layout: &Layout, mirror: Option<&[usize]>) -> Vec<CorpusChar> {
+    match mirror {
+        Some(mirror) => {
+            let mirrored: Vec<CorpusChar> = mirror.iter().map(|p| layout.0[*p]).collect();
+            mirrored.min(layout.0.clone())
         }
-    });
-
-    Ok(())
+        None => layout.0.clone(),
+    }
 }
 
-struct OptimizationContext {
-    layout: Layout,
-    analyzer: Analyzer,
-    possible_swaps: Vec<Swap>,
-    evaluator: Evaluator,
-    pin: usize,
+/// The layout's characters in position order, with blank positions shown as `�`.
+pub fn layout_chars(corpus: &Corpus, layout: &Layout) -> String {
+    layout
+        .0
+        .iter()
+        .map(|c| blank::show(corpus.uncorpus_unigram(*c)))
+        .collect()
 }
 
-pub struct Evaluator {
-    metrics: Vec<(usize, f32)>,
+struct KeptResult {
+    canonical: Vec<CorpusChar>,
+    run: u64,
+    result: GenerationResult,
 }
 
-impl From<Vec<(usize, i16)>> for Evaluator {
-    fn from(metrics: Vec<(usize, i16)>) -> Self {
-        let sum: f32 = metrics.iter().map(|(_, x)| *x as f32).sum();
-        Self {
-            metrics: metrics.iter().map(|(m, x)| (*m, *x as f32 / sum)).collect(),
-        }
+/// Formats a duration as e.g. `1h02m03s`, dropping leading zero units.
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
     }
 }
 
-impl Evaluator {
-    pub fn eval(&self, stats: &[f32]) -> f32 {
-        self.metrics.iter().map(|(m, x)| x * stats[*m]).sum()
-    }
+/// The current UTC time as `YYYYMMDD-HHMMSS`, so names sort chronologically.
+fn timestamp() -> String {
+    let (year, month, day, h, m, s) = utc_now();
+    format!("{year:04}{month:02}{day:02}-{h:02}{m:02}{s:02}")
 }
 
-fn greedy_neighbor_optimization(
-    OptimizationContext {
-        layout,
-        analyzer,
-        possible_swaps,
-        evaluator,
-        pin,
-    }: &OptimizationContext,
-) -> (u32, f32, Vec<f32>, Layout) {
-    let mut rng = thread_rng();
-    let mut layout = layout.clone();
-
-    // Shuffle without moving pinned keys
-    layout.0[*pin..].shuffle(&mut rng);
-
-    let stats = analyzer.calc_stats(&layout);
-    let mut diff = vec![0.0; stats.len()];
-
-    let mut i = 0;
-    loop {
-        let mut best_diff = 0.0;
-        let mut best_swap = &possible_swaps[0];
-        for swap in possible_swaps {
-            evaluator.metrics.iter().for_each(|(index, _)| diff[*index] = 0.0);
-            diff.iter_mut().for_each(|x| *x = 0.0);
-            analyzer.swap_diff(&mut diff, &layout, swap);
-            let score = evaluator.eval(&diff);
-            if score < best_diff {
-                best_swap = swap;
-                best_diff = score;
-            }
-        }
-        if best_diff+0.000001 < 0.0 {
-            layout.swap(best_swap);
-            i += 1;
-        } else {
-            break;
-        }
-    }
-    let stats = analyzer.calc_stats(&layout);
-    let score = evaluator.eval(&stats);
-    (i, score, stats, layout)
+/// The current UTC date and time as year, month, day, hour, minute and second.
+pub fn utc_now() -> (i64, i64, i64, u64, u64, u64) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, time) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, after Howard Hinnant's days_from_civil
+    let z = days as i64 + 719468;
+    let (era, doe) = (z.div_euclid(146097), z.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + i64::from(month <= 2);
+    (year, month, day, time / 3600, time / 60 % 60, time % 60)
 }
 
-fn greedy_naive_optimization(
-    OptimizationContext {
-        layout,
-        analyzer,
-        possible_swaps,
-        evaluator,
-        pin,
-    }: &OptimizationContext,
-) -> (u32, f32, Vec<f32>, Layout) {
-    let mut rng = thread_rng();
-    let mut layout = layout.clone();
-
-    // Shuffle without moving pinned keys
-    layout.0[*pin..].shuffle(&mut rng);
-
-    let stats = analyzer.calc_stats(&layout);
-    let mut diff = vec![0.0; stats.len()];
-
-    let mut swap_i = 0;
-    for i in 0..5000 {
-        let swap = possible_swaps.choose(&mut rng).unwrap();
-        diff.iter_mut().for_each(|x| *x = 0.0);
-        analyzer.swap_diff(&mut diff, &layout, swap);
-        let score = evaluator.eval(&diff);
-        if score < 0.0 {
-            layout.swap(swap);
-            swap_i = i;
-        }
-    }
-    let stats = analyzer.calc_stats(&layout);
-    let score = evaluator.eval(&stats);
-    (swap_i, score, stats, layout)
+/// Everything about a generation besides the keyboard and corpus it runs on,
+/// filled in by the command line and batch manifests alike. Start from
+/// `GenerationOptions::new` and set what differs.
+pub struct GenerationOptions<'a> {
+    /// `(name, weight)` pairs
+    pub metrics: &'a [(String, i16)],
+    pub char_set: &'a str,
+    pub strategy: GenerationStrategy,
+    /// Other keyboards to score on as well, with their weights
+    pub keyboards: Vec<(String, MetricData, f32)>,
+    pub worst_case: bool,
+    pub pin: usize,
+    /// Positions kept in place besides the first `pin`
+    pub pinned: &'a [usize],
+    pub hand: Option<Hand>,
+    pub layers: &'a [Layer],
+    pub runs: u64,
+    pub use_stdout: bool,
+    pub reference: Option<LayoutData>,
+    /// A corpus to re-score the best layouts on, and how much worse they may do there
+    pub held_out: Option<(Corpus, f32)>,
+    pub tiers: &'a [String],
+    pub tier_tolerance: f32,
+    /// `(metric, threshold, weight)` triples
+    pub penalties: &'a [(String, f32, f32)],
+    pub emit_layouts: Option<&'a Path>,
+    pub keep_top: Option<usize>,
+    pub combos: bool,
+    pub swap_bias: f32,
+    pub keep_blanks: bool,
+    pub seed: Option<u64>,
+    pub output_dir: Option<&'a Path>,
+    pub run_name: Option<&'a str>,
+    pub resume: Option<&'a Path>,
+    pub progress_style: ProgressStyle,
+    pub provenance: Provenance,
+    pub cache: Option<MetricCache>,
 }
 
-fn simulated_annealing(
-    OptimizationContext {
-        layout,
-        analyzer,
-        possible_swaps,
-        evaluator,
-        pin,
-    }: &OptimizationContext,
-) -> (u32, f32, Vec<f32>, Layout) {
-    let mut rng = thread_rng();
-    let mut layout = layout.clone();
-
-    // Shuffle without moving pinned keys
-    layout.0[*pin..].shuffle(&mut rng);
-
-    let stats = analyzer.calc_stats(&layout);
-    let mut diff = vec![0.0; stats.len()];
-
-    let mut temp = 0.5;
-    let iterations = 1_000_000;
-    let dec: f32 = temp / iterations as f32;
-    for _ in 0..iterations {
-        temp -= dec;
-        let swap = possible_swaps.choose(&mut rng).unwrap();
-        diff.iter_mut().for_each(|x| *x = 0.0);
-        analyzer.swap_diff(&mut diff, &layout, swap);
-        let score = evaluator.eval(&diff);
-        if score < 0.0 || rng.gen::<f32>() < temp {
-            layout.swap(swap);
+impl<'a> GenerationOptions<'a> {
+    /// One run of `strategy` on `char_set`, weighting `metrics`, with every
+    /// other option at its command line default.
+    pub fn new(
+        metrics: &'a [(String, i16)],
+        char_set: &'a str,
+        strategy: GenerationStrategy,
+    ) -> Self {
+        GenerationOptions {
+            metrics,
+            char_set,
+            strategy,
+            keyboards: vec![],
+            worst_case: false,
+            pin: 0,
+            pinned: &[],
+            hand: None,
+            layers: &[],
+            runs: 1,
+            use_stdout: false,
+            reference: None,
+            held_out: None,
+            tiers: &[],
+            tier_tolerance: 0.001,
+            penalties: &[],
+            emit_layouts: None,
+            keep_top: None,
+            combos: false,
+            swap_bias: 0.0,
+            keep_blanks: false,
+            seed: None,
+            output_dir: None,
+            run_name: None,
+            resume: None,
+            progress_style: ProgressStyle::None,
+            provenance: Provenance::default(),
+            cache: None,
         }
     }
-    let stats = analyzer.calc_stats(&layout);
-    let score = evaluator.eval(&stats);
-    (iterations, score, stats, layout)
-}
-
-fn ddako_simulated_annealing(
-    OptimizationContext {
-        layout,
-        analyzer,
-        possible_swaps,
-        evaluator,
-        pin: _pin,
-    }: &OptimizationContext,
-) -> (u32, f32, Vec<f32>, Layout) {
-    let backend = CrosstermBackend::new(std::io::stdout());
-    let mut terminal = Terminal::new(backend).unwrap();
-
-    let mut table_state = TableState::default();
-    let mut rt = create_rate_tracker(&mut terminal, &mut table_state);
-
-    let mut sa = ddako_sa::SimulatedAnnealing::new(
-        possible_swaps,
-        layout,
-        analyzer,
-        evaluator,
-        0.9,
-        5.0,
-        1.0,
-        10.0,
-        None,
-        &mut rt,
-    );
-
-    sa.optimize(possible_swaps.len())
 }
 
 pub fn output_generation(
-    metrics: &[(String, i16)],
-    metric_data: keymeow::MetricData,
+    mut metric_data: MetricData,
     corpus: Corpus,
-    char_set: &str,
-    strategy: &GenerationStrategy,
-    pin: usize,
-    runs: u64,
-    use_stdout: bool,
+    options: GenerationOptions,
+    config: &Config,
 ) -> Result<()> {
-    let metric_weights: Result<Vec<_>> = metrics
-        .iter()
-        .map(|(name, x)| {
-            let metric =
-                get_metric(name, &metric_data).with_context(|| format!("invalid metric {name}"));
-            match metric {
-                Ok(m) => Ok((m, *x)),
-                Err(e) => Err(e),
+    let GenerationOptions {
+        metrics,
+        char_set,
+        strategy,
+        keyboards,
+        worst_case,
+        pin,
+        pinned,
+        hand,
+        layers,
+        runs,
+        use_stdout,
+        reference,
+        held_out,
+        tiers,
+        tier_tolerance,
+        penalties,
+        emit_layouts,
+        keep_top,
+        combos: optimize_combos,
+        swap_bias,
+        keep_blanks,
+        seed,
+        output_dir,
+        run_name,
+        resume,
+        progress_style,
+        provenance,
+        cache,
+    } = options;
+    // Other keyboards' metrics are merged in from data the cache can't identify
+    let cache = cache.filter(|_| keyboards.is_empty()).map(|cache| {
+        cache
+            .with(format!("combos {optimize_combos}"))
+            .with(format!("layers {layers:?}"))
+    });
+    if optimize_combos && !metric_data.keyboard.combos.is_empty() {
+        crate::combos::add_metric(&mut metric_data)?;
+    }
+    if !layers.is_empty() {
+        ensure!(
+            keyboards.is_empty(),
+            "layers can't be scored on other keyboards"
+        );
+        add_layers(&mut metric_data, layers)?;
+    }
+    let metric_weights = resolve_weights(metrics, &metric_data)?;
+    let mut evaluator = Evaluator::from(metric_weights.clone());
+    // Other keyboards' metrics are merged in, so each layout is analyzed once
+    let mut copies = vec![(
+        metric_weights.iter().map(|(m, _)| *m).collect::<Vec<_>>(),
+        1.0,
+    )];
+    for (name, other, weight) in keyboards {
+        let copy: Result<Vec<usize>> = metrics
+            .iter()
+            .map(|(m, _)| {
+                get_metric(m, &other).with_context(|| format!("{name} has no metric {m}"))
+            })
+            .collect();
+        let copy = copy?;
+        let offset = merge_metrics(&mut metric_data, other, &name)?;
+        copies.push((copy.iter().map(|m| m + offset).collect(), weight));
+    }
+    if !tiers.is_empty() {
+        // Metrics not named in any tier are only used to break the final tie
+        let mut metric_tiers = vec![tiers.len(); metric_weights.len()];
+        for (tier, names) in tiers.iter().enumerate() {
+            for name in names.split(',').map(str::trim) {
+                let m = get_metric(name, &metric_data)
+                    .with_context(|| format!("invalid tier metric {name}"))?;
+                let i = metric_weights
+                    .iter()
+                    .position(|(w, _)| *w == m)
+                    .with_context(|| {
+                        format!("tier metric {name} needs a weight in the metric list")
+                    })?;
+                metric_tiers[i] = tier;
             }
-        })
+        }
+        evaluator = evaluator.with_tiers(metric_tiers, tier_tolerance);
+    }
+    let layout = match hand {
+        Some(hand) => {
+            let keys = hand_positions(&metric_data.keyboard, hand);
+            layout_on_keys(&corpus, &metric_data, &keys, char_set)?
+        }
+        None => layout_from_charset(&corpus, &metric_data, char_set),
+    };
+    let len = layout.0.len();
+    let layout = layered_layout(&corpus, layout, layers)?;
+    let totals = layout.totals(&corpus);
+    let mut used_metrics: Vec<usize> = copies.iter().flat_map(|(c, _)| c.clone()).collect();
+    if copies.len() > 1 {
+        evaluator = evaluator.with_keyboards(copies, worst_case);
+    }
+    for (name, threshold, weight) in penalties {
+        let m = get_metric(name, &metric_data)
+            .with_context(|| format!("invalid penalty metric {name}"))?;
+        used_metrics.push(m);
+        // Thresholds are given as percentages; the evaluator works on raw values
+        let unit = totals.percentage(1.0, metric_data.metrics[m].ngram_type);
+        evaluator = evaluator.with_penalty(m, threshold / unit, *weight);
+    }
+    let reference_layout = match &reference {
+        Some(l) => Some(layered_layout(
+            &corpus,
+            MetricContext::layout_matrix(l, &metric_data.keyboard, &corpus)
+                .with_context(|| format!("layout {} incompatible with keyboard", l.name))?,
+            layers,
+        )?),
+        None => None,
+    };
+    let reference_metrics: Vec<(String, usize, NgramType)> = metrics
+        .iter()
+        .zip(&metric_weights)
+        .map(|((name, _), (m, _))| (name.clone(), *m, metric_data.metrics[*m].ngram_type))
         .collect();
-    let metric_weights = metric_weights?;
-    let evaluator = Evaluator::from(metric_weights.clone());
-    let layout = layout_from_charset(&corpus, &metric_data, char_set);
 
-    let data = filter_metrics(
-        kc_metric_data(metric_data, layout.0.len()),
-        &metric_weights
-            .iter()
-            .map(|(m, _)| *m)
-            .collect::<Vec<usize>>(),
-    );
+    let keyboard = metric_data.keyboard.clone();
+    // Layers are mirrored along with the base
+    let mirror = mirror_positions(&metric_data.keyboard, len).map(|mirror| {
+        (0..=layers.len())
+            .flat_map(|l| mirror.iter().map(move |p| l * len + p))
+            .collect::<Vec<_>>()
+    });
+    // The best layouts are re-scored on the held-out corpus with every metric
+    let validation = held_out.map(|held_out| (metric_data.clone(), held_out));
+    let data = filtered_metric_data(metric_data, &used_metrics, layout.0.len(), cache.as_ref());
+    // One analyzer serves every run
     let analyzer = Analyzer::from(data, corpus);
 
-    // Swap without moving pinned keys
-    let possible_swaps: Vec<Swap> = (0..layout.0.len())
-        .flat_map(|a| (0..layout.0.len()).map(move |b| Swap::new(a, b)))
-        .filter(|Swap { a, b }| a != b && *a > pin && *b > pin)
-        .collect();
+    let reference = match (reference, reference_layout) {
+        (Some(data), Some(matrix)) => Some(Reference::new(
+            data.name,
+            reference_metrics.clone(),
+            matrix.totals(&analyzer.corpus),
+            analyzer.calc_stats(&matrix),
+            &evaluator,
+        )),
+        _ => None,
+    };
 
+    let checkpoint = resume.map(Checkpoint::load).transpose()?;
+    if let Some(checkpoint) = &checkpoint {
+        ensure!(
+            matches!(strategy, GenerationStrategy::DDAKOSimulatedAnnealing),
+            "only ddako-simulated-annealing generations can be resumed"
+        );
+        ensure!(keep_top.is_none(), "--keep-top results aren't checkpointed");
+        ensure!(
+            checkpoint
+                .state
+                .as_ref()
+                .is_none_or(|s| s.layout_len() == layout.0.len()),
+            "checkpoint doesn't match this char set and keyboard"
+        );
+    }
+    let name = match (&checkpoint, run_name) {
+        (Some(checkpoint), _) => checkpoint.name.clone(),
+        (None, Some(name)) => name.to_string(),
+        (None, None) => {
+            let random_string = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+            format!("generate_{}_{:?}_{}", timestamp(), &strategy, random_string)
+        }
+    };
+    // A resumed generation carries on next to its checkpoint
+    let dir = match resume.and_then(Path::parent) {
+        Some(dir) => dir,
+        None => output_dir.unwrap_or(config.generations_dir()),
+    };
+    create_dir_all(dir).context("couldn't create generations directory")?;
+    let checkpoint_path = dir.join(format!("{name}.checkpoint"));
     let output: &mut dyn Write = if use_stdout {
         &mut std::io::stdout().lock()
+    } else if checkpoint.is_some() {
+        // Earlier runs are already in the file
+        &mut OpenOptions::new()
+            .append(true)
+            .open(dir.join(format!("{name}.tsv")))
+            .context("couldn't reopen generation output")?
     } else {
-        let random_string = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
-        let name: String = [format!("generate_{:?}_{}", &strategy, random_string)]
-            .into_iter()
-            .chain([".tsv".to_string()])
-            .collect();
-        &mut File::create_new(Path::new("generations").join(&name))?
+        let path = dir.join(format!("{name}.tsv"));
+        &mut File::create_new(&path)
+            .with_context(|| format!("couldn't create {}", path.display()))?
     };
     let mut s: String = "iteration\tscore\t".into();
     metrics.iter().for_each(|(m, _)| {
         s.push_str(m);
         s.push('\t');
     });
-    s.push_str("layout");
+    metrics.iter().for_each(|(m, _)| {
+        s.push_str(m);
+        s.push_str("_score\t");
+    });
+    s.push_str("name\tlayout");
 
-    writeln!(output, "{}", s)?;
+    if checkpoint.is_none() || use_stdout {
+        writeln!(output, "{}", s)?;
+    }
 
-    let context = OptimizationContext {
-        layout,
-        analyzer,
-        possible_swaps,
-        evaluator,
-        pin,
+    // A resumed generation keeps the metadata, and so the seed, it started with
+    let resumed = resume.and_then(|_| RunMetadata::load(&RunMetadata::path(dir, &name)).ok());
+    let metadata = match resumed {
+        Some(metadata) => metadata,
+        None => RunMetadata {
+            name: name.clone(),
+            started: iso_now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_revision: git_revision(),
+            command: provenance.command,
+            corpus: provenance.corpus,
+            keyboard: provenance.keyboard,
+            char_set: char_set.to_string(),
+            strategy: strategy.to_possible_value().unwrap().get_name().to_string(),
+            metrics: metrics.to_vec(),
+            runs,
+            pin,
+            pinned: pinned.to_vec(),
+            hand: hand.map(|h| h.to_possible_value().unwrap().get_name().to_string()),
+            layers: layers
+                .iter()
+                .map(|l| format!("{}:{}={}", l.name, l.cost, l.chars))
+                .collect(),
+            combos: optimize_combos,
+            tiers: tiers.to_vec(),
+            tier_tolerance,
+            penalties: penalties.to_vec(),
+            swap_bias,
+            keep_blanks,
+            seed: seed.unwrap_or_else(rand::random),
+            annealing: config.annealing.clone(),
+        },
     };
+    if checkpoint.is_none() && !use_stdout {
+        metadata.save(dir)?;
+    }
 
-    let totals = context.layout.totals(&context.analyzer.corpus);
+    let mut context = OptimizationContext::new(strategy, layout, analyzer, evaluator, keyboard)
+        .with_swaps(pin, optimize_combos)
+        .with_pinned(pinned.to_vec())
+        .with_blanks_kept(keep_blanks)
+        .with_annealing(config.annealing.clone())
+        .with_swap_bias(swap_bias)?
+        .with_seed(metadata.seed)
+        .with_progress(progress_style, runs, reference_metrics)?;
+    if let Some(hand) = hand {
+        context = context.with_hand(hand);
+    }
+    if !layers.is_empty() {
+        context = context.with_layers(layers.len());
+    }
+    if let Some(reference) = reference {
+        context = context.with_reference(reference);
+    }
 
-    for _ in 0..runs {
-        let (i, score, stats, result) = match strategy {
-            GenerationStrategy::GreedyDeterministic => greedy_neighbor_optimization(&context),
-            GenerationStrategy::GreedyNaive => greedy_naive_optimization(&context),
-            GenerationStrategy::SimulatedAnnealing => simulated_annealing(&context),
-            GenerationStrategy::DDAKOSimulatedAnnealing => ddako_simulated_annealing(&context),
-        };
-        let chars: String = result
-            .0
-            .iter()
-            .map(|c| context.analyzer.corpus.uncorpus_unigram(*c))
-            .map(|c| match c {
-                '\0' => '�',
-                c => c,
-            })
-            .collect();
+    let totals = context.layout().totals(&context.analyzer().corpus);
+
+    if let Some(dir) = emit_layouts {
+        create_dir_all(dir).context("couldn't create layout directory")?;
+    }
+
+    let mut names = Names::default();
+    let mut write_result = |run: u64, result: &GenerationResult| {
         let mut values = String::new();
         for (m, _) in metric_weights.iter() {
-            values.push_str(&format!(
-                "{}\t",
-                totals.percentage(stats[*m], context.analyzer.data.metrics[*m])
-            ))
+            let percentage =
+                totals.percentage(result.stats[*m], context.analyzer().data.metrics[*m]);
+            let _ = write!(values, "{percentage}\t");
+        }
+        for contribution in context.evaluator().contributions(&result.stats) {
+            let _ = write!(values, "{contribution}\t");
+        }
+
+        let GenerationResult {
+            iterations,
+            score,
+            chars,
+            ..
+        } = result;
+        let base: Vec<char> = chars.chars().take(len).collect();
+        let layout_name = names.unique(home_row_name(context.keyboard(), &base));
+        writeln!(
+            output,
+            "{iterations}\t{score}\t{values}{layout_name}\t{chars}"
+        )?;
+
+        if let Some(dir) = emit_layouts {
+            let file_name = format!("{name}_{run}");
+            // LayoutData has no layers, so only the base goes in
+            let base = Layout(result.layout.0[..len].to_vec());
+            let data = LayoutData::flexible_from_keyboard_layout(
+                context.keyboard(),
+                &base,
+                &context.analyzer().corpus,
+            )
+            .name(layout_name);
+            let file = File::create_new(dir.join(file_name).with_extension("json"))?;
+            serde_json::to_writer_pretty(file, &data)?;
+        }
+        // Rows must be on disk before a checkpoint says their runs are done
+        output.flush()?;
+        anyhow::Ok(())
+    };
+
+    // Best unique results so far, kept sorted by score, when only the top N are written
+    let mut kept: Vec<KeptResult> = vec![];
+    let mut best_run: Option<GenerationResult> = None;
+    // The best few, for the run history
+    let mut top: Vec<(f32, String)> = vec![];
+    let started = Instant::now();
+    let (first_run, mut resume_state) = match checkpoint {
+        Some(checkpoint) => {
+            checkpoint.resume_point(recorded_runs(&dir.join(format!("{name}.tsv")))?)
+        }
+        None => (0, None),
+    };
+    let mut completed_runs = first_run;
+
+    for run in first_run..runs {
+        if interrupt::interrupted() {
+            break;
+        }
+        if context.has_progress_bars() {
+            let best = best_run
+                .as_ref()
+                .map_or("-".to_string(), |r| format!("{:.4}", r.score));
+            let eta = match run - first_run {
+                0 => "-".to_string(),
+                done => format_eta(started.elapsed() / done as u32 * (runs - run) as u32),
+            };
+            context.start_run(format!("Run {}/{runs} (best {best}, ETA {eta})", run + 1));
         }
+        let start = Instant::now();
+        let result = context.optimize(run, Some((&checkpoint_path, &name)), resume_state.take());
+        let GenerationResult {
+            iterations, score, ..
+        } = result;
+        info!(run, iterations, score, elapsed = ?start.elapsed(), "finished run");
+        context.finish_run();
+        if best_run.as_ref().is_none_or(|best| score < best.score) {
+            best_run = Some(result.clone());
+        }
+        completed_runs += 1;
+        top.push((score, result.chars.clone()));
+        top.sort_by(|a, b| a.0.total_cmp(&b.0));
+        top.truncate(history::TOP);
 
-        writeln!(output, "{i}\t{score}\t{values}{chars}")?;
+        match keep_top {
+            Some(n) => {
+                let result = KeptResult {
+                    canonical: canonical_layout(&result.layout, mirror.as_deref()),
+                    run,
+                    result,
+                };
+                match kept.iter().position(|k| k.canonical == result.canonical) {
+                    Some(k) if kept[k].result.score <= score => {}
+                    Some(k) => kept[k] = result,
+                    None => kept.push(result),
+                }
+                kept.sort_by(|a, b| a.result.score.total_cmp(&b.result.score));
+                kept.truncate(n);
+            }
+            None => {
+                write_result(run, &result)?;
+                // A run written since the last checkpoint mustn't be replayed from it
+                if checkpoint_path.exists() && !use_stdout {
+                    let name = name.clone();
+                    let run = run + 1;
+                    Checkpoint {
+                        name,
+                        run,
+                        state: None,
+                    }
+                    .save(&checkpoint_path)?;
+                }
+            }
+        }
+
+        if let (Some(reference), Some(best)) = (context.reference(), &best_run) {
+            info!(
+                "best vs {}: {}",
+                reference.name(),
+                reference.deltas(&best.stats, best.score)
+            );
+        }
     }
 
-    // println!("{:?}", totals.percentage(analyzer.calc_stats(&layout)[metric].into(), analyzer.data.metrics[metric]));
+    for k in &kept {
+        write_result(k.run, &k.result)?;
+    }
+    output.flush()?;
+
+    if let Some(best) = best_run.filter(|_| interrupt::interrupted()) {
+        eprintln!("best layout so far ({}): {}", best.score, best.chars);
+    }
+    if let Some((metric_data, (test, tolerance))) = validation.filter(|_| !top.is_empty()) {
+        let layouts =
+            validate::generated_layouts(context.keyboard(), top.iter().map(|(_, c)| c.as_str()));
+        let output: &mut dyn Write = match use_stdout {
+            true => &mut std::io::stderr(),
+            false => &mut std::io::stdout(),
+        };
+        writeln!(output, "best layouts on the held-out corpus:")?;
+        let train = context.analyzer().corpus.clone();
+        validate::validate(
+            metrics,
+            metric_data,
+            train,
+            test,
+            &layouts,
+            tolerance,
+            output,
+        )?;
+    }
+    if let Some(path) = config.history_file() {
+        let entry = HistoryEntry {
+            metadata,
+            finished: iso_now(),
+            completed_runs,
+            best: top,
+        };
+        if let Err(e) = history::record(&path, &entry) {
+            warn!("couldn't record the run in {}: {e:#}", path.display());
+        }
+    }
+    if checkpoint_path.exists() {
+        match interrupt::interrupted() {
+            true => eprintln!("resume with --resume {}", checkpoint_path.display()),
+            false => {
+                std::fs::remove_file(&checkpoint_path).context("couldn't remove checkpoint")?
+            }
+        }
+    }
 
     Ok(())
 }
 
-pub fn stats(metric_data: MetricData, corpus: Corpus, layouts: Vec<LayoutData>) -> Result<()> {
+#[derive(ValueEnum, Debug, Clone)]
+pub enum StatsFormat {
+    Table,
+    Json,
+    Csv,
+    Markdown,
+}
+
+/// One metric's value for a layout.
+#[derive(Serialize, Clone)]
+pub struct MetricStat {
+    pub name: String,
+    pub raw: f32,
+    pub percentage: f32,
+}
+
+/// A layout's value for every metric of its keyboard, in the keyboard's metric order.
+#[derive(Serialize, Clone)]
+pub struct LayoutStats {
+    pub name: String,
+    pub metrics: Vec<MetricStat>,
+}
+
+impl LayoutStats {
+    pub fn raw(&self) -> Vec<f32> {
+        self.metrics.iter().map(|m| m.raw).collect()
+    }
+
+    pub fn percentages(&self) -> Vec<f32> {
+        self.metrics.iter().map(|m| m.percentage).collect()
+    }
+}
+
+/// Analyze each layout on the keyboard of `metric_data`.
+pub fn layout_stats(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: &[LayoutData],
+) -> Result<Vec<LayoutStats>> {
     let ctx = MetricContext::new(
         layouts
             .first()
@@ -511,54 +859,554 @@ pub fn stats(metric_data: MetricData, corpus: Corpus, layouts: Vec<LayoutData>)
         corpus,
     )
     .context("could not produce metric context")?;
+    context_stats(&ctx, layouts)
+}
+
+/// Like `layout_stats`, reusing a context already built for the keyboard and
+/// corpus. Percentages are of the totals of the context's own layout.
+pub fn context_stats(ctx: &MetricContext, layouts: &[LayoutData]) -> Result<Vec<LayoutStats>> {
     let totals = ctx.layout.totals(&ctx.analyzer.corpus);
 
-    let stat_lists: Vec<Vec<f32>> = layouts
+    Ok(layouts
         .iter()
-        .map(|l| {
-            let matrix = MetricContext::layout_matrix(l, &ctx.keyboard, &ctx.analyzer.corpus)
-                .with_context(|| format!("layout {} incompatible with keyboard", l.name))
-                .unwrap();
-            ctx.analyzer.calc_stats(&matrix)
+        .zip(layout_matrices(ctx, layouts)?)
+        .map(|(l, matrix)| {
+            let stats = ctx.analyzer.calc_stats(&matrix);
+            LayoutStats {
+                name: l.name.clone(),
+                metrics: ctx
+                    .metrics
+                    .iter()
+                    .zip(stats)
+                    .map(|(m, raw)| MetricStat {
+                        name: m.name.clone(),
+                        raw,
+                        percentage: totals.percentage(raw, m.ngram_type),
+                    })
+                    .collect(),
+            }
         })
+        .collect())
+}
+
+/// How `stats` analyzes and prints its layouts.
+pub struct StatsOptions<'a> {
+    pub layers: &'a [Layer],
+    /// Metric weights to break each layout's score down by
+    pub explain: &'a [(String, i16)],
+    pub format: StatsFormat,
+    /// A template rendered once per layout instead of the format
+    pub format_template: Option<&'a str>,
+    pub higher_is_better: &'a [String],
+    /// Whether to show raw weighted counts alongside percentages
+    pub raw: bool,
+}
+
+pub fn stats(
+    mut metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    options: StatsOptions,
+) -> Result<()> {
+    let StatsOptions {
+        layers,
+        explain,
+        format,
+        format_template,
+        higher_is_better,
+        raw,
+    } = options;
+    if !layers.is_empty() {
+        add_layers(&mut metric_data, layers)?;
+    }
+    let ngram_types: Vec<NgramType> = metric_data.metrics.iter().map(|m| m.ngram_type).collect();
+    // What percentages are of: the first layout's totals, layers and all
+    let totals = match layouts.first().filter(|_| raw) {
+        Some(l) => {
+            let base = MetricContext::layout_matrix(l, &metric_data.keyboard, &corpus)
+                .with_context(|| format!("layout {} incompatible with keyboard", l.name))?;
+            let matrix = layered_layout(&corpus, base, layers)?;
+            let totals = matrix.totals(&corpus);
+            check_totals(&corpus, &matrix, &totals);
+            Some(totals)
+        }
+        None => None,
+    };
+    let maximize = resolve_metrics(higher_is_better, &metric_data)?;
+    let template = format_template
+        .map(|t| template::parse(t, &metric_data))
+        .transpose()?;
+    let explain_weights = resolve_weights(explain, &metric_data)?;
+    let results = match layers.is_empty() {
+        true => layout_stats(metric_data, corpus, &layouts)?,
+        false => layered_stats(metric_data, corpus, &layouts, layers)?,
+    };
+
+    if let Some(template) = template {
+        if raw {
+            warn!("--raw counts and totals aren't shown with --format-template");
+        }
+        for l in &results {
+            println!("{}", template::render(&template, &l.name, &l.percentages()));
+        }
+        return Ok(());
+    }
+
+    match format {
+        StatsFormat::Table => {
+            let color = atty::is(atty::Stream::Stdout) && results.len() > 1;
+            let color = color.then_some(&maximize[..]);
+            print_stats_table(&results, color, raw);
+            if let Some(totals) = &totals {
+                println!();
+                print_totals(totals);
+            }
+            if !explain_weights.is_empty() {
+                print_explain(&results, &explain_weights);
+            }
+        }
+        StatsFormat::Json => {
+            let mut output = serde_json::Map::new();
+            for l in &results {
+                let metrics: serde_json::Map<String, serde_json::Value> = l
+                    .metrics
+                    .iter()
+                    .zip(&ngram_types)
+                    .map(|(m, ngram_type)| {
+                        let mut value = serde_json::json!({
+                            "raw": m.raw,
+                            "percentage": m.percentage,
+                        });
+                        // The count the percentage is of
+                        if let Some(totals) = &totals {
+                            value["total"] = type_total(totals, *ngram_type).into();
+                        }
+                        (m.name.clone(), value)
+                    })
+                    .collect();
+                output.insert(l.name.clone(), metrics.into());
+            }
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        StatsFormat::Csv => {
+            let raw_names = results[0].metrics.iter().map(|m| format!("{}_raw", m.name));
+            let header: Vec<String> = iter::once("layout".to_string())
+                .chain(results[0].metrics.iter().map(|m| m.name.clone()))
+                .chain(raw_names.filter(|_| raw))
+                .map(|name| csv_field(&name))
+                .collect();
+            println!("{}", header.join(","));
+            for l in &results {
+                let raw_counts = l.metrics.iter().map(|m| m.raw.to_string());
+                let row: Vec<String> = iter::once(csv_field(&l.name))
+                    .chain(l.metrics.iter().map(|m| m.percentage.to_string()))
+                    .chain(raw_counts.filter(|_| raw))
+                    .collect();
+                println!("{}", row.join(","));
+            }
+            // Each raw column's total, under it
+            if let Some(totals) = &totals {
+                let row: Vec<String> = iter::once("totals".to_string())
+                    .chain(ngram_types.iter().map(|_| String::new()))
+                    .chain(
+                        ngram_types
+                            .iter()
+                            .map(|t| type_total(totals, *t).to_string()),
+                    )
+                    .collect();
+                println!("{}", row.join(","));
+            }
+        }
+        StatsFormat::Markdown => {
+            let md = |s: &str| s.replace('|', "\\|");
+            let header: Vec<String> = results.iter().map(|l| md(&l.name)).collect();
+            println!("| Metric | {} |", header.join(" | "));
+            println!("|:--|{}", "--:|".repeat(results.len()));
+            for (i, m) in results[0].metrics.iter().enumerate() {
+                let cells: Vec<String> = results
+                    .iter()
+                    .map(|l| stat_cell(&l.metrics[i], raw))
+                    .collect();
+                println!("| {} | {} |", md(&m.name), cells.join(" | "));
+            }
+            if let Some(totals) = &totals {
+                println!();
+                print_totals(totals);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print per-metric deltas from the first layout to the second.
+pub fn diff(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    higher_is_better: &[String],
+) -> Result<()> {
+    ensure!(layouts.len() == 2, "--diff needs exactly two layouts");
+    let maximize = resolve_metrics(higher_is_better, &metric_data)?;
+
+    let ctx = MetricContext::new(&layouts[0], metric_data, corpus)
+        .context("could not produce metric context")?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+    let stat_lists: Vec<Vec<f32>> = layout_matrices(&ctx, &layouts)?
+        .iter()
+        .map(|matrix| ctx.analyzer.calc_stats(matrix))
         .collect();
+    let color = atty::is(atty::Stream::Stdout);
+
     let max: usize = ctx.metrics.iter().map(|m| m.name.len()).max().unwrap();
-    let name_lengths: Vec<usize> = layouts.iter().map(|l| l.name.len()).collect();
+    println!(
+        "{}{:>9}{:>9}{:>9}{:>9}",
+        str::repeat(" ", max),
+        layouts[0].name,
+        layouts[1].name,
+        "delta",
+        "rel"
+    );
+    for (i, metric) in ctx.metrics.iter().enumerate() {
+        let a = totals.percentage(stat_lists[0][i], metric.ngram_type);
+        let b = totals.percentage(stat_lists[1][i], metric.ngram_type);
+        let delta = b - a;
+        let relative = match a {
+            0.0 => String::from("-"),
+            a => format!("{:+.1}%", delta / a * 100.0),
+        };
+        let line = format!(
+            "{:max$}{:>8.2}%{:>8.2}%{:>+8.2}%{:>9}",
+            metric.name, a, b, delta, relative
+        );
+        let improved = match maximize.contains(&i) {
+            true => delta > 0.0,
+            false => delta < 0.0,
+        };
+        match color {
+            true if delta == 0.0 => println!("{line}"),
+            true if improved => println!("\x1b[32m{line}\x1b[0m"),
+            true => println!("\x1b[31m{line}\x1b[0m"),
+            false => println!("{line}"),
+        }
+    }
+    Ok(())
+}
 
-    let labels = layouts
+/// Print every metric a keyboard provides, with its short name and ngram type.
+pub fn list_metrics(metric_data: &MetricData) {
+    let width = metric_data
+        .metrics
         .iter()
-        .fold(str::repeat(" ", max + 1), |mut output, l| {
-            let _ = write!(
-                output,
-                "{}{}",
-                l.name,
-                str::repeat(" ", 4 + 7_usize.saturating_sub(l.name.len()))
-            );
-            output
-        });
+        .map(|m| m.name.len())
+        .max()
+        .unwrap_or(0);
+    let short_width = metric_data
+        .metrics
+        .iter()
+        .map(|m| m.short.len())
+        .max()
+        .unwrap_or(0);
+    println!("{:width$}  {:short_width$}  ngram", "name", "short");
+    for m in &metric_data.metrics {
+        println!(
+            "{:width$}  {:short_width$}  {:?}",
+            m.name, m.short, m.ngram_type
+        );
+    }
+}
+
+/// A name for every position: its row and column, counting from one, then
+/// each combo in turn, followed by its character in `chars` if given.
+fn position_names(keyboard: &Keyboard, chars: Option<&[char]>) -> Vec<String> {
+    let keys = keyboard
+        .keys
+        .map
+        .iter()
+        .enumerate()
+        .flat_map(|(r, row)| (0..row.len()).map(move |c| format!("r{}c{}", r + 1, c + 1)));
+    let combos = (1..=keyboard.combos.len()).map(|n| format!("combo {n}"));
+    keys.chain(combos)
+        .enumerate()
+        .map(
+            |(p, name)| match chars.and_then(|c| c.get(p)).filter(|c| **c != blank::NONE) {
+                Some(c) => format!("{name} {c}"),
+                None => name,
+            },
+        )
+        .collect()
+}
+
+/// Print what `metric` covers on `metric_data`'s keyboard: how many strokes of
+/// each length it counts, the `top` ones with the largest amounts, and its total
+/// possible mass, the sum of its amounts as if every stroke were typed once.
+/// Positions are named by row and column, and by their characters in `chars`.
+/// With `grid`, each listed stroke is drawn on the keyboard instead.
+pub fn show_metric(
+    metric_data: &MetricData,
+    metric: usize,
+    top: usize,
+    grid: bool,
+    chars: Option<&[char]>,
+) {
+    let keyboard = &metric_data.keyboard;
+    let names = position_names(keyboard, chars);
+    let positions = names.len();
+    let mut strokes: Vec<(Vec<usize>, f32)> = metric_data
+        .strokes
+        .iter()
+        .filter_map(|s| {
+            let amounts = s.amounts.iter().filter(|a| a.metric == metric);
+            let amount: f32 = amounts.map(|a| a.amount).sum();
+            (amount != 0.0).then(|| (s.nstroke.to_vec(), amount))
+        })
+        .collect();
+    strokes.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+
+    let mut lengths: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut covered = vec![false; positions];
+    for (stroke, _) in &strokes {
+        *lengths.entry(stroke.len()).or_default() += 1;
+        for p in stroke.iter().filter(|p| **p < positions) {
+            covered[*p] = true;
+        }
+    }
+
+    let m = &metric_data.metrics[metric];
+    println!("{} ({}), a {:?} metric", m.name, m.short, m.ngram_type);
+    for (len, count) in &lengths {
+        let kind = match len {
+            1 => "monostrokes",
+            2 => "bistrokes",
+            3 => "tristrokes",
+            _ => "strokes",
+        };
+        println!("  {count} {kind}");
+    }
+    let covered = covered.iter().filter(|c| **c).count();
+    println!("  on {covered} of {positions} positions");
+    let total: f32 = strokes.iter().map(|(_, a)| a).sum();
+    println!("  total possible mass {total:.3}");
+    if let (Some((_, largest)), Some((_, smallest))) = (strokes.first(), strokes.last()) {
+        println!("  amounts from {smallest:.3} to {largest:.3}");
+    }
+
+    if strokes.is_empty() || top == 0 {
+        return;
+    }
+    println!();
+    println!("{:>8}  stroke", "amount");
+    for (stroke, amount) in strokes.iter().take(top) {
+        let keys: Vec<&str> = stroke
+            .iter()
+            .map(|p| names.get(*p).map_or("?", String::as_str))
+            .collect();
+        println!("{amount:>8.3}  {}", keys.join(", "));
+        if grid {
+            // The stroke's keys numbered in order, on the rest of the keyboard
+            let mut labels = vec!["·".to_string(); positions];
+            for (i, p) in stroke.iter().enumerate().filter(|(_, p)| **p < positions) {
+                labels[*p] = (i + 1).to_string();
+            }
+            for line in keyboard_text(keyboard, &labels) {
+                println!("          {line}");
+            }
+            println!();
+        }
+    }
+    if strokes.len() > top {
+        println!("     ...  {} more", strokes.len() - top);
+    }
+}
+
+/// Score every layout that fits the keyboard and print them best first.
+pub fn rank(
+    metrics: &[(String, i16)],
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+) -> Result<()> {
+    let metric_weights = resolve_weights(metrics, &metric_data)?;
+    let evaluator = Evaluator::from(metric_weights.clone());
+
+    let first = layouts
+        .iter()
+        .find(|l| MetricContext::layout_matrix(l, &metric_data.keyboard, &corpus).is_some())
+        .context("no layouts fit this keyboard")?;
+    let ctx = MetricContext::new(first, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+
+    let mut ranking: Vec<(&str, f32, Vec<f32>)> = vec![];
+    for l in &layouts {
+        match MetricContext::layout_matrix(l, &ctx.keyboard, &ctx.analyzer.corpus) {
+            Some(matrix) => {
+                let stats = ctx.analyzer.calc_stats(&matrix);
+                ranking.push((&l.name, evaluator.eval(&stats), stats));
+            }
+            None => warn!("skipping {}: incompatible with keyboard", l.name),
+        }
+    }
+    ranking.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let width = ranking
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let header = metrics.iter().fold(String::new(), |mut output, (name, _)| {
+        let _ = write!(output, "\t{name}");
+        output
+    });
+    println!("{:>4}  {:width$}\tscore{}", "#", "name", header);
+    for (i, (name, score, stats)) in ranking.iter().enumerate() {
+        let percentages = metric_weights
+            .iter()
+            .fold(String::new(), |mut output, (m, _)| {
+                let pc = totals.percentage(stats[*m], ctx.metrics[*m].ngram_type);
+                let _ = write!(output, "\t{pc:.2}%");
+                output
+            });
+        println!("{:>4}  {:width$}\t{:.4}{}", i + 1, name, score, percentages);
+    }
+    Ok(())
+}
+
+/// For each key, how much the score improves by moving its character to the
+/// best other position (swapping with whatever is there), worst keys first.
+pub fn analyze_keys(
+    metrics: &[(String, i16)],
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+) -> Result<()> {
+    let evaluator = Evaluator::from(resolve_weights(metrics, &metric_data)?);
+    let key_count = metric_data.keyboard.keys.map.iter().flatten().count();
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let mut matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let score = evaluator.eval(&ctx.analyzer.calc_stats(&matrix));
+    let corpus = &ctx.analyzer.corpus;
+    let label = |c: CorpusChar| match c {
+        0 => blank::BLANK,
+        c => corpus.uncorpus_unigram(c),
+    };
+
+    let mut regrets: Vec<(usize, usize, f32)> = vec![];
+    for a in 0..key_count {
+        if matrix.0[a] == 0 {
+            continue;
+        }
+        let mut best = (a, score);
+        for b in (0..key_count).filter(|b| *b != a) {
+            let swap = Swap { a, b };
+            matrix.swap(&swap);
+            let swapped = evaluator.eval(&ctx.analyzer.calc_stats(&matrix));
+            matrix.swap(&swap);
+            if swapped < best.1 {
+                best = (b, swapped);
+            }
+        }
+        regrets.push((a, best.0, score - best.1));
+    }
+    regrets.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    println!("score: {score:.4}");
+    for (a, b, regret) in regrets {
+        match regret > 0.0 {
+            true => println!(
+                "{}\t-{:.4}\tswap with {} (position {})",
+                label(matrix.0[a]),
+                regret,
+                label(matrix.0[b]),
+                b
+            ),
+            false => println!("{}\t 0.0000\tbest where it is", label(matrix.0[a])),
+        }
+    }
+    Ok(())
+}
+
+/// `s` as one csv field, quoted if it has to be.
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn layout_matrices(ctx: &MetricContext, layouts: &[LayoutData]) -> Result<Vec<Layout>> {
+    layouts
+        .iter()
+        .map(|l| {
+            MetricContext::layout_matrix(l, &ctx.keyboard, &ctx.analyzer.corpus)
+                .with_context(|| format!("layout {} incompatible with keyboard", l.name))
+        })
+        .collect()
+}
+
+/// A table cell for `stat`, with its raw weighted count after if `raw` is set.
+fn stat_cell(stat: &MetricStat, raw: bool) -> String {
+    match raw {
+        true => format!("{:.2}% ({:.0})", stat.percentage, stat.raw),
+        false => format!("{:.2}%", stat.percentage),
+    }
+}
+
+fn print_stats_table(results: &[LayoutStats], color: Option<&[usize]>, raw: bool) {
+    let metrics = &results[0].metrics;
+    let max: usize = metrics.iter().map(|m| m.name.len()).max().unwrap();
+    // Each column fits its name and cells, with at least four spaces after
+    let widths: Vec<usize> = results
+        .iter()
+        .map(|l| {
+            let cells = l.metrics.iter().map(|m| stat_cell(m, raw).len());
+            cells.chain([l.name.len(), 7]).max().unwrap() + 4
+        })
+        .collect();
+
+    let labels =
+        results
+            .iter()
+            .zip(&widths)
+            .fold(str::repeat(" ", max + 1), |mut output, (l, width)| {
+                let _ = write!(output, "{:width$}", l.name);
+                output
+            });
 
     println!("{labels}");
 
-    for i in 0..ctx.metrics.len() {
-        let name = &ctx.metrics[i].name;
+    for (i, metric) in metrics.iter().enumerate() {
+        let name = &metric.name;
+        // Lower is better unless the metric was listed with --higher-is-better
+        let (best, worst) = match color {
+            Some(maximize) => {
+                let values = results.iter().map(|l| l.metrics[i].raw);
+                let (lo, hi) =
+                    values.fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+                match maximize.contains(&i) {
+                    _ if lo == hi => (None, None),
+                    true => (Some(hi), Some(lo)),
+                    false => (Some(lo), Some(hi)),
+                }
+            }
+            None => (None, None),
+        };
         let percentages: String =
-            stat_lists
+            results
                 .iter()
                 .enumerate()
-                .fold(String::new(), |mut output, (col, s)| {
-                    let pc = totals.percentage(s[i], ctx.metrics[i].ngram_type);
-                    let len = match pc {
-                        x if x < 10. => 5,
-                        x if x < 100. => 6,
-                        _ => 7,
+                .fold(String::new(), |mut output, (col, l)| {
+                    let stat = &l.metrics[i];
+                    let text = stat_cell(stat, raw);
+                    let len = text.len();
+                    let cell = match Some(stat.raw) {
+                        v if v == best => format!("\x1b[32m{text}\x1b[0m"),
+                        v if v == worst => format!("\x1b[31m{text}\x1b[0m"),
+                        _ => text,
                     };
-                    let name_spacing = 4 + 7_usize.saturating_sub(name_lengths[col]);
-                    let _ = write!(
-                        output,
-                        "{:.2}%{}",
-                        pc,
-                        str::repeat(" ", name_lengths[col] + name_spacing - len)
-                    );
+                    let _ = write!(output, "{}{}", cell, str::repeat(" ", widths[col] - len));
                     output
                 });
         println!(
@@ -568,48 +1416,151 @@ pub fn stats(metric_data: MetricData, corpus: Corpus, layouts: Vec<LayoutData>)
             percentages
         )
     }
+}
 
-    Ok(())
+/// Print the corpus totals of each ngram type that percentages are of.
+fn print_totals(totals: &Totals) {
+    println!(
+        "totals: {} chars, {} bigrams, {} skipgrams, {} trigrams",
+        totals.chars, totals.bigrams, totals.skipgrams, totals.trigrams
+    );
 }
 
-pub fn combos(metric_data: MetricData, corpus: Corpus, layout: LayoutData) -> Result<()> {
+/// The count of `ngram_type` that percentages of it are of.
+fn type_total(totals: &Totals, ngram_type: NgramType) -> u64 {
+    match ngram_type {
+        NgramType::Monogram => totals.chars,
+        NgramType::Bigram => totals.bigrams,
+        NgramType::Skipgram => totals.skipgrams,
+        NgramType::Trigram => totals.trigrams,
+    }
+}
+
+/// Warn about any of `totals` that isn't the sum of the corpus counts of every
+/// ngram of the characters on `matrix`.
+fn check_totals(corpus: &Corpus, matrix: &Layout, totals: &Totals) {
+    let mut chars: Vec<CorpusChar> = matrix.0.iter().copied().filter(|c| *c != 0).collect();
+    chars.sort_unstable();
+    chars.dedup();
+    let mut counted = [0u64; 4];
+    for &a in &chars {
+        counted[0] += corpus.chars[a] as u64;
+        for &b in &chars {
+            counted[1] += corpus.bigrams[corpus.bigram_idx(a, b)] as u64;
+            counted[2] += corpus.skipgrams[corpus.bigram_idx(a, b)] as u64;
+            for &c in &chars {
+                counted[3] += corpus.trigrams[corpus.trigram_idx(a, b, c)] as u64;
+            }
+        }
+    }
+    let kinds = ["chars", "bigrams", "skipgrams", "trigrams"];
+    let expected = [
+        totals.chars,
+        totals.bigrams,
+        totals.skipgrams,
+        totals.trigrams,
+    ];
+    for ((kind, total), counted) in kinds.iter().zip(expected).zip(counted) {
+        if total != counted {
+            warn!("the layout's {kind} total {total} isn't the {counted} counted from the corpus");
+        }
+    }
+}
+
+fn print_explain(results: &[LayoutStats], explain_weights: &[(usize, i16)]) {
+    let metrics = &results[0].metrics;
+    let max: usize = metrics.iter().map(|m| m.name.len()).max().unwrap();
+    let evaluator = Evaluator::from(explain_weights.to_vec());
+    for l in results {
+        let stats = l.raw();
+        let score = evaluator.eval(&stats);
+        println!();
+        println!("{} score: {score}", l.name);
+        for ((m, weight), contribution) in
+            explain_weights.iter().zip(evaluator.contributions(&stats))
+        {
+            let name = &metrics[*m].name;
+            let share = if score == 0.0 {
+                0.0
+            } else {
+                100. * contribution / score
+            };
+            println!(
+                "  {}{}(x{weight})\t{contribution:.2}\t{share:.1}%",
+                name,
+                str::repeat(" ", 1 + max - name.len()),
+            );
+        }
+    }
+}
+
+/// A combo and the character it's assigned, if any.
+#[derive(Serialize, Clone)]
+pub struct ComboAssignment {
+    /// The characters of the combo's keys, up to three
+    pub keys: String,
+    pub output: Option<char>,
+    /// How often the output character is typed, as a percentage
+    pub frequency: f32,
+}
+
+pub fn combo_assignments(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+) -> Result<Vec<ComboAssignment>> {
     let mut ctx = MetricContext::new(&layout, metric_data, corpus)
         .context("could not produce metric context")?;
     let totals = ctx.layout.totals(&ctx.analyzer.corpus);
-    // let stats = ctx.analyzer.calc_stats(&ctx.layout);
 
     let kb_size = ctx.keyboard.keys.map.iter().flatten().count();
     ctx.keyboard.process_combo_indexes();
 
+    Ok(ctx
+        .keyboard
+        .combo_indexes
+        .iter()
+        .enumerate()
+        .map(|(idx, combo)| {
+            let keys: String = combo
+                .iter()
+                .take(3)
+                .filter_map(|i| {
+                    let cc = ctx.layout.0[*i];
+                    if cc == 0 {
+                        return None;
+                    }
+                    let c = ctx.analyzer.corpus.uncorpus_unigram(cc);
+                    match c {
+                        ' ' => Some('␣'),
+                        _ => Some(c),
+                    }
+                })
+                .collect();
+            let key = ctx.layout.0[kb_size + idx];
+            ComboAssignment {
+                keys,
+                output: match key {
+                    0 => None,
+                    _ => Some(ctx.analyzer.corpus.uncorpus_unigram(key)),
+                },
+                frequency: totals
+                    .percentage(ctx.analyzer.corpus.chars[key] as f32, NgramType::Bigram),
+            }
+        })
+        .collect())
+}
+
+pub fn combos(metric_data: MetricData, corpus: Corpus, layout: LayoutData) -> Result<()> {
     let mut i = 0;
-    for (idx, combo) in ctx.keyboard.combo_indexes.iter().enumerate() {
-        let combo_text: String = combo
-            .iter()
-            .take(3)
-            .filter_map(|i| {
-                let cc = ctx.layout.0[*i];
-                if cc == 0 {
-                    return None;
-                }
-                let c = ctx.analyzer.corpus.uncorpus_unigram(cc);
-                match c {
-                    ' ' => Some('␣'),
-                    _ => Some(c),
-                }
-            })
-            .collect();
-        let key = ctx.layout.0[kb_size + idx];
-        let output = match key {
-            0 => ' ',
-            _ => ctx.analyzer.corpus.uncorpus_unigram(key),
-        };
-        let spacing = str::repeat(" ", 4 - combo.len());
-        let freq = totals.percentage(ctx.analyzer.corpus.chars[key] as f32, NgramType::Bigram);
-        let freq_text = match output {
-            ' ' => String::from("      "),
-            _ => format!("({:.1}%)", freq),
+    for combo in combo_assignments(metric_data, corpus, layout)? {
+        // Line the outputs up whatever the number of keys
+        let spacing = str::repeat(" ", 4 - combo.keys.chars().count().min(4));
+        let (output, freq_text) = match combo.output {
+            Some(c) => (c, format!("({:.1}%)", combo.frequency)),
+            None => (' ', String::from("      ")),
         };
-        print!("{combo_text}{spacing}{output} {freq_text}\t");
+        print!("{}{spacing}{output} {freq_text}\t", combo.keys);
         i += 1;
         if i % 4 == 0 {
             println!();
@@ -0,0 +1,182 @@
+use crate::{parse_corpus_weight, parse_key_val, AnalysisArgs};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use keywhisker::analysis::{output_generation, GenerationOptions};
+use keywhisker::collect::{output_table, CollectOptions};
+use keywhisker::config::Config;
+use keywhisker::optimization::GenerationStrategy;
+use keywhisker::reporter::ProgressStyle;
+use keywhisker::{blank, corpus, interrupt};
+use km_data::Data as KeymeowData;
+use serde::Deserialize;
+use std::fs::{self, create_dir_all};
+use std::path::Path;
+use tracing::info;
+
+/// A list of experiments to run one after another.
+///
+/// ```toml
+/// [[job]]
+/// name = "sfb-heavy"
+/// kind = "generate"
+/// corpus = ["monkeyracer"]
+/// keyboard = "ansi"
+/// char_set = "abcdefghijklmnopqrstuvwxyz,./;"
+/// metrics = ["sfb=10", "sfs=3"]
+/// strategy = "ddako-simulated-annealing"
+/// runs = 20
+///
+/// [[job]]
+/// name = "baseline"
+/// kind = "collect"
+/// char_set = "abcdefghijklmnopqrstuvwxyz,./;"
+/// metrics = ["sfb", "sfs"]
+/// count = 100000
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(rename = "job")]
+    jobs: Vec<Job>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum JobKind {
+    Generate,
+    Collect,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    /// Also the name of the job's output directory
+    name: String,
+    kind: JobKind,
+    /// As name or name:weight; falls back to the config file
+    #[serde(default)]
+    corpus: Vec<String>,
+    keyboard: Option<String>,
+    /// Falls back to the keyboard's in the config file
+    char_set: Option<String>,
+    /// Weighted as name=weight for generate jobs, plain names for collect jobs
+    metrics: Vec<String>,
+    strategy: Option<String>,
+    #[serde(default = "default_runs")]
+    runs: u64,
+    count: Option<u64>,
+    /// Falls back to the keyboard's pinned positions in the config file
+    pin: Option<usize>,
+    #[serde(default)]
+    combos: bool,
+    /// Share of swaps tried on frequent characters; see run-generation's --swap-bias
+    #[serde(default)]
+    swap_bias: f32,
+}
+
+fn default_runs() -> u64 {
+    1
+}
+
+impl Job {
+    fn analysis_args(&self) -> Result<AnalysisArgs> {
+        let corpus: Result<Vec<_>, _> =
+            self.corpus.iter().map(|c| parse_corpus_weight(c)).collect();
+        Ok(AnalysisArgs {
+            corpus: corpus.map_err(|e| anyhow!("invalid corpus: {e}"))?,
+            keyboard: self.keyboard.clone(),
+            effort: None,
+            travel: false,
+            plugins: vec![],
+            alt_fingering: None,
+            ngram_key: vec![],
+            repeat_key: None,
+        })
+    }
+
+    fn run(
+        &self,
+        data: &KeymeowData,
+        config: &Config,
+        dir: &Path,
+        progress_style: ProgressStyle,
+    ) -> Result<()> {
+        let analysis_args = self.analysis_args()?;
+        let keyboard = analysis_args.keyboard(config);
+        let char_set = &config.char_set(keyboard, self.char_set.as_deref())?;
+        let (pin, pinned) = (self.pin.unwrap_or(0), config.pinned(keyboard, self.pin));
+        let (mut corpus, metric_data) = analysis_args.get(data, config)?;
+        blank::clear_for(&mut corpus, char_set);
+        corpus::check_coverage(&corpus, char_set)?;
+        match self.kind {
+            JobKind::Generate => {
+                let strategy = self
+                    .strategy
+                    .as_deref()
+                    .context("generate jobs need a strategy")?;
+                let strategy = GenerationStrategy::from_str(strategy, true)
+                    .map_err(|e| anyhow!("invalid strategy: {e}"))?;
+                let metrics: Result<Vec<_>, _> = self
+                    .metrics
+                    .iter()
+                    .map(|m| parse_key_val::<String, i16>(m))
+                    .collect();
+                let metrics = metrics.map_err(|e| anyhow!("invalid metric weight: {e}"))?;
+                let options = GenerationOptions {
+                    pin,
+                    pinned: &pinned,
+                    runs: self.runs,
+                    combos: self.combos,
+                    swap_bias: self.swap_bias,
+                    output_dir: Some(dir),
+                    progress_style,
+                    provenance: analysis_args.provenance(config)?,
+                    cache: analysis_args.metric_cache(data, config),
+                    ..GenerationOptions::new(&metrics, char_set, strategy)
+                };
+                output_generation(metric_data, corpus, options, config)
+            }
+            JobKind::Collect => {
+                let count = self.count.context("collect jobs need a count")?;
+                let output = dir.join("data.csv");
+                let cache = analysis_args.metric_cache(data, config);
+                let options = CollectOptions {
+                    pin,
+                    pinned: &pinned,
+                    cache: cache.as_ref(),
+                    ..CollectOptions::new(count, char_set, &output, config.threads())
+                };
+                output_table(self.metrics.clone(), metric_data, corpus, options)
+            }
+        }
+    }
+}
+
+/// Run every job in a manifest, each writing into its own directory under `output_dir`.
+pub fn run(
+    manifest: &Path,
+    output_dir: &Path,
+    data: &KeymeowData,
+    config: &Config,
+    progress_style: ProgressStyle,
+) -> Result<()> {
+    let text = fs::read_to_string(manifest)
+        .with_context(|| format!("couldn't read manifest {}", manifest.display()))?;
+    let manifest: Manifest = toml::from_str(&text)
+        .with_context(|| format!("invalid manifest {}", manifest.display()))?;
+
+    interrupt::install()?;
+    for (i, job) in manifest.jobs.iter().enumerate() {
+        if interrupt::interrupted() {
+            break;
+        }
+        let dir = output_dir.join(&job.name);
+        create_dir_all(&dir).context("couldn't create job directory")?;
+        info!(job = job.name, "starting job");
+        eprintln!("job {}/{}: {}", i + 1, manifest.jobs.len(), job.name);
+        job.run(data, config, &dir, progress_style)
+            .with_context(|| format!("job {} failed", job.name))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::interrupt;
+use crate::optimization::{GenerationStrategy, OptimizationContext};
+use crate::summary::Summary;
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+use rand::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Run each of `strategies` `runs` times on the context `context` makes for
+/// it, or until it's used up `budget`, and print a table of their scores and
+/// wall-clock times. Contexts should be seeded, so that every strategy gets
+/// the same runs.
+pub fn bench_strategies(
+    strategies: &[GenerationStrategy],
+    runs: u64,
+    budget: Option<Duration>,
+    context: impl Fn(GenerationStrategy) -> Result<OptimizationContext>,
+) -> Result<()> {
+    println!(
+        "{:<27}{:>6}{:>12}{:>12}{:>12}{:>12}{:>11}{:>11}",
+        "Strategy", "Runs", "Mean", "Median", "Best", "Std dev", "Per run", "Total"
+    );
+    for strategy in strategies {
+        let context = context(strategy.clone())?;
+        let started = Instant::now();
+        let mut scores = vec![];
+        for run in 0..runs {
+            let spent = budget.is_some_and(|budget| started.elapsed() >= budget);
+            if interrupt::interrupted() || spent {
+                break;
+            }
+            scores.push(context.optimize(run, None, None).score as f64);
+        }
+        if scores.is_empty() {
+            break;
+        }
+        let elapsed = started.elapsed();
+        let summary = Summary::new(&scores);
+        let Summary { mean, std_dev, .. } = summary;
+        let (median, best) = (summary.percentile(50.0), summary.min());
+        let name = strategy.to_possible_value().unwrap();
+        println!(
+            "{:<27}{:>6}{mean:>12.4}{median:>12.4}{best:>12.4}{std_dev:>12.4}{:>10.2}s{:>10.2}s",
+            name.get_name(),
+            scores.len(),
+            elapsed.as_secs_f64() / scores.len() as f64,
+            elapsed.as_secs_f64(),
+        );
+    }
+    Ok(())
+}
+
+/// Call `f` with a counter over and over for about `duration`, returning calls per second.
+fn throughput(duration: Duration, mut f: impl FnMut(usize)) -> f64 {
+    let started = Instant::now();
+    let mut calls = 0;
+    // Checking the clock every call would cost more than the fastest calls
+    while started.elapsed() < duration {
+        for _ in 0..100 {
+            f(calls);
+            calls += 1;
+        }
+    }
+    calls as f64 / started.elapsed().as_secs_f64()
+}
+
+/// Time the hot paths of generation on a shuffled copy of the context's
+/// layout for `duration` each, and print calls per second: the incremental
+/// `swap_diff`, swapping and recomputing stats with `recalc_stats`, and
+/// analyzing and scoring a whole layout.
+pub fn bench_throughput(context: &OptimizationContext, duration: Duration) -> Result<()> {
+    let swaps = context.possible_swaps();
+    ensure!(!swaps.is_empty(), "the layout has no keys to swap");
+    let analyzer = context.analyzer();
+    let evaluator = context.evaluator();
+    let mut layout = context.layout().clone();
+    layout.0.shuffle(&mut thread_rng());
+    let stats = analyzer.calc_stats(&layout);
+    let mut diff = vec![0.0; stats.len()];
+
+    let fast = throughput(duration, |i| {
+        diff.fill(0.0);
+        analyzer.swap_diff(&mut diff, &layout, &swaps[i % swaps.len()]);
+        black_box(evaluator.eval(&diff));
+    });
+    let slow = throughput(duration, |i| {
+        let swap = &swaps[i % swaps.len()];
+        layout.swap(swap);
+        diff.fill(0.0);
+        analyzer.recalc_stats(&mut diff, &layout);
+        black_box(evaluator.eval(&diff));
+        layout.swap(swap);
+    });
+    let full = throughput(duration, |_| {
+        black_box(evaluator.eval(&analyzer.calc_stats(&layout)));
+    });
+
+    for (name, rate) in [
+        ("swap_diff", fast),
+        ("recalc_stats", slow),
+        ("full eval", full),
+    ] {
+        println!("{name:<14}{rate:>14.0}/s{:>10.2}µs", 1_000_000.0 / rate);
+    }
+    println!();
+    println!("swap_diff is {:.1}x as fast as recalc_stats", fast / slow);
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Blank keys: positions with no character on them. Layouts hold them as
+//! corpus char 0, which is `'\0'` when read back; layout strings and char sets
+//! write them as `�`, and generation can add them to char sets with `--blanks`.
+
+use keycat::{Corpus, CorpusChar};
+
+/// How a blank key is written in layout strings
+pub const BLANK: char = '�';
+/// The character a blank key's corpus char stands for
+pub const NONE: char = '\0';
+
+pub fn is_blank(c: char) -> bool {
+    c == BLANK || c == NONE
+}
+
+/// `c` as written in layout strings.
+pub fn show(c: char) -> char {
+    match c {
+        NONE => BLANK,
+        c => c,
+    }
+}
+
+/// `c` as held in character lists, with blanks as `'\0'`.
+pub fn hide(c: char) -> char {
+    match c {
+        BLANK => NONE,
+        c => c,
+    }
+}
+
+/// The corpus char of `c`, or 0 for a blank.
+pub fn corpus_char(corpus: &Corpus, c: char) -> CorpusChar {
+    match is_blank(c) {
+        true => 0,
+        false => corpus.corpus_char(c),
+    }
+}
+
+/// `char_set` followed by `blanks` blank keys.
+pub fn char_set(char_set: &str, blanks: usize) -> String {
+    char_set
+        .chars()
+        .chain(std::iter::repeat_n(BLANK, blanks))
+        .collect()
+}
+
+/// [`clear`] the corpus if `char_set` has any blank keys, leaving it as it was
+/// for analyses that have none.
+pub fn clear_for(corpus: &mut Corpus, char_set: &str) {
+    if char_set.chars().any(is_blank) {
+        clear(corpus);
+    }
+}
+
+/// Clear every ngram count involving char 0, so blank keys count towards no metric.
+pub fn clear(corpus: &mut Corpus) {
+    let len = corpus.char_list.len();
+    corpus.chars[0] = 0;
+    for a in 0..len {
+        for b in 0..len {
+            if a == 0 || b == 0 {
+                let i = corpus.bigram_idx(a, b);
+                corpus.bigrams[i] = 0;
+                corpus.skipgrams[i] = 0;
+            }
+            for i in [
+                corpus.trigram_idx(0, a, b),
+                corpus.trigram_idx(a, 0, b),
+                corpus.trigram_idx(a, b, 0),
+            ] {
+                corpus.trigrams[i] = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::analysis::{get_metric, layout_matrices};
+use crate::heatmap::ngram_frequency;
+
+use anyhow::{Context, Result};
+use keycat::{analysis::Analyzer, Corpus, CorpusChar, Layout};
+use keymeow::{LayoutData, MetricContext, MetricData};
+use std::collections::HashMap;
+
+/// Raw amount of `metric` produced by each ngram on `layout`, largest first.
+pub fn ngram_contributions(
+    analyzer: &Analyzer,
+    layout: &Layout,
+    metric: usize,
+) -> Vec<(Vec<CorpusChar>, f32)> {
+    let ngram_type = analyzer.data.metrics[metric];
+    let mut contributions: HashMap<Vec<CorpusChar>, f32> = HashMap::new();
+    for stroke in &analyzer.data.strokes {
+        for amount in stroke.amounts.iter().filter(|a| a.metric == metric) {
+            let positions = stroke.nstroke.to_vec();
+            let value =
+                amount.amount * ngram_frequency(&analyzer.corpus, layout, ngram_type, &positions);
+            if value != 0.0 {
+                let chars = positions.iter().map(|p| layout.0[*p]).collect();
+                *contributions.entry(chars).or_default() += value;
+            }
+        }
+    }
+    let mut contributions: Vec<_> = contributions.into_iter().collect();
+    contributions.sort_by(|a, b| b.1.total_cmp(&a.1));
+    contributions
+}
+
+/// Print the ngrams contributing most to `metric` for each layout.
+pub fn breakdown(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    metric: &str,
+    top: usize,
+) -> Result<()> {
+    let m = get_metric(metric, &metric_data).with_context(|| format!("invalid metric {metric}"))?;
+    let ctx = MetricContext::new(
+        layouts
+            .first()
+            .context("need at least one layout to break down")?,
+        metric_data,
+        corpus,
+    )
+    .context("could not produce metric context")?;
+    let corpus = &ctx.analyzer.corpus;
+    let ngram_type = ctx.metrics[m].ngram_type;
+
+    for (l, matrix) in layouts.iter().zip(layout_matrices(&ctx, &layouts)?) {
+        let totals = matrix.totals(corpus);
+        let contributions = ngram_contributions(&ctx.analyzer, &matrix, m);
+        let metric_total: f32 = contributions.iter().map(|(_, v)| v).sum();
+
+        println!(
+            "{} {}: {:.2}%",
+            l.name,
+            ctx.metrics[m].name,
+            totals.percentage(metric_total, ngram_type)
+        );
+        for (chars, value) in contributions.iter().take(top) {
+            let ngram: String = chars.iter().map(|c| corpus.uncorpus_unigram(*c)).collect();
+            println!(
+                "  {:?}\t{:.3}%\t({:.1}% of {})",
+                ngram,
+                totals.percentage(*value, ngram_type),
+                value / metric_total.max(f32::EPSILON) * 100.0,
+                ctx.metrics[m].short,
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use crate::analysis::{filter_metrics, kc_metric_data};
+
+use anyhow::{Context, Result};
+use keycat::analysis::MetricData as KcMetricData;
+use keymeow::MetricData;
+use std::cmp::Reverse;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+// Bump when the cached format changes, so old entries are missed instead of misread
+const CACHE_VERSION: u32 = 2;
+// Entries kept before the least recently used are deleted
+const MAX_ENTRIES: usize = 16;
+
+/// Where metric data is cached, and what it was built from: the files read,
+/// by path, size and modification time, and whatever was applied to them.
+/// Metric data from the same sources is the same, so its strokes needn't be
+/// hashed to tell.
+#[derive(Clone)]
+pub struct MetricCache {
+    dir: PathBuf,
+    source: Vec<String>,
+}
+
+impl MetricCache {
+    pub fn new(dir: PathBuf) -> Self {
+        MetricCache {
+            dir,
+            source: vec![],
+        }
+    }
+
+    /// Note a file the metric data was read from.
+    pub fn with_file(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let identity = match fs::metadata(path) {
+            Ok(meta) => format!(
+                "{} {} {:?}",
+                path.display(),
+                meta.len(),
+                meta.modified().ok()
+            ),
+            Err(_) => path.display().to_string(),
+        };
+        self.with(identity)
+    }
+
+    /// Note an option the metric data was built with.
+    pub fn with(mut self, part: impl Display) -> Self {
+        self.source.push(part.to_string());
+        self
+    }
+
+    /// The entry for metrics `used` on `len` positions.
+    fn path(&self, used: &[usize], len: usize) -> PathBuf {
+        let mut hash = Fnv::new();
+        hash.write(&CACHE_VERSION.to_le_bytes());
+        for part in &self.source {
+            hash.write(part.as_bytes());
+            hash.write(&[0]);
+        }
+        for m in used {
+            hash.write(&(*m as u64).to_le_bytes());
+        }
+        hash.write(&(len as u64).to_le_bytes());
+        self.dir
+            .join("metrics")
+            .join(format!("{:016x}.msgpack", hash.0))
+    }
+
+    /// Delete all but the `MAX_ENTRIES` most recently used entries.
+    fn evict(&self) -> Result<()> {
+        let mut entries: Vec<(SystemTime, PathBuf)> = fs::read_dir(self.dir.join("metrics"))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "msgpack"))
+            .filter_map(|p| Some((fs::metadata(&p).ok()?.modified().ok()?, p)))
+            .collect();
+        entries.sort_by_key(|(modified, _)| Reverse(*modified));
+        for (_, path) in entries.iter().skip(MAX_ENTRIES) {
+            debug!(path = %path.display(), "evicting cached metric data");
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is the same on every Rust
+/// release, so entries outlive an upgrade.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<KcMetricData> {
+    let bytes = fs::read(path)?;
+    // Mark it used, for eviction
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())?;
+    Ok(rmp_serde::from_slice(&bytes)?)
+}
+
+/// Write `data` to a temporary file first, so that a crash or a concurrent
+/// run never leaves a half-written entry at `path`.
+fn store(path: &Path, data: &KcMetricData) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap()).context("couldn't create cache directory")?;
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp, rmp_serde::to_vec(data)?)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// The analyzer's metric data for `metric_data` on `len` positions, keeping
+/// only the strokes of `used` metrics. Built once and then read from `cache`,
+/// if given; a cache that can't be read or written is only warned about.
+pub fn filtered_metric_data(
+    metric_data: MetricData,
+    used: &[usize],
+    len: usize,
+    cache: Option<&MetricCache>,
+) -> KcMetricData {
+    let path = cache.map(|cache| cache.path(used, len));
+    if let Some(path) = path.as_ref().filter(|p| p.exists()) {
+        match load(path) {
+            Ok(data) => {
+                debug!(path = %path.display(), "loaded cached metric data");
+                return data;
+            }
+            Err(e) => warn!("ignoring unreadable metric cache {}: {e:#}", path.display()),
+        }
+    }
+    let data = filter_metrics(kc_metric_data(metric_data, len), used);
+    if let (Some(cache), Some(path)) = (cache, path) {
+        if let Err(e) = store(&path, &data).and_then(|_| cache.evict()) {
+            warn!("couldn't cache metric data at {}: {e:#}", path.display());
+        }
+    }
+    data
+}
@@ -0,0 +1,89 @@
+use crate::ddako::simulated_annealing::State;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, rename, File};
+use std::path::Path;
+
+/// A DDAKO generation interrupted partway through one of its runs, or between two.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Name of the generation, shared by its TSV and emitted layouts
+    pub name: String,
+    pub run: u64,
+    /// None when `run` hasn't started yet
+    pub state: Option<State>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("couldn't open checkpoint {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("couldn't parse checkpoint {}", path.display()))
+    }
+
+    /// Write through a temporary file so a crash mid-write keeps the previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        serde_json::to_writer(File::create(&tmp)?, self)?;
+        rename(&tmp, path).context("couldn't replace checkpoint")
+    }
+
+    /// The run to carry on from, and its state, given that `recorded` runs are
+    /// already in the generation's TSV. Runs finished after the checkpoint was
+    /// saved are skipped rather than replayed and written again.
+    pub fn resume_point(self, recorded: u64) -> (u64, Option<State>) {
+        match recorded > self.run {
+            true => (recorded, None),
+            false => (self.run, self.state),
+        }
+    }
+}
+
+/// How many results the generation TSV at `path` holds, one per finished run.
+pub fn recorded_runs(path: &Path) -> Result<u64> {
+    let text = read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    Ok(text.lines().skip(1).filter(|line| !line.is_empty()).count() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_skips_a_recorded_run() {
+        let dir = std::env::temp_dir().join(format!("keywhisker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tsv = dir.join("test.tsv");
+        std::fs::write(
+            &tsv,
+            "iteration\tscore\tname\tlayout\n12\t0.5\tasdf\tasdf\n",
+        )
+        .unwrap();
+
+        // Saved partway through run 0, which then finished and was written
+        let state: State = serde_json::from_value(serde_json::json!({
+            "layout": [0, 1], "fitness": 1.0, "temp": 1.0, "cooling_interval": 1.0,
+            "iteration": 3, "stays": 0, "last_adjustment": 0, "last_improvement_iteration": 0,
+            "recent_acceptances": [], "recent_acceptance_rates": [], "best_layout": [0, 1],
+            "best_fitness": 1.0, "best_stats": [], "seed": 0,
+        }))
+        .unwrap();
+        let path = dir.join("test.checkpoint");
+        let name = "test".to_string();
+        Checkpoint {
+            name,
+            run: 0,
+            state: Some(state),
+        }
+        .save(&path)
+        .unwrap();
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        let (run, state) = checkpoint.resume_point(recorded_runs(&tsv).unwrap());
+        assert_eq!(run, 1);
+        assert!(state.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
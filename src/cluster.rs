@@ -0,0 +1,105 @@
+use crate::blank;
+use crate::fingers::finger_index;
+
+use anyhow::{Context, Result};
+use keymeow::Keyboard;
+use std::fs;
+use std::path::Path;
+
+/// One result row of a `run-generation` tsv.
+pub struct Generation {
+    pub score: f32,
+    pub layout: Vec<char>,
+}
+
+pub fn read_generations(path: &Path) -> Result<Vec<Generation>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .context("empty generations file")?
+        .split('\t')
+        .collect();
+    let score = header
+        .iter()
+        .position(|h| *h == "score")
+        .context("no score column")?;
+    let layout = header
+        .iter()
+        .position(|h| *h == "layout")
+        .context("no layout column")?;
+
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            Ok(Generation {
+                score: fields.get(score).context("missing score")?.parse()?,
+                layout: fields
+                    .get(layout)
+                    .context("missing layout")?
+                    .chars()
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Number of positions holding different characters.
+fn positional_distance(a: &[char], b: &[char]) -> usize {
+    a.iter().zip(b).filter(|(x, y)| x != y).count()
+}
+
+/// Number of characters typed by a different finger.
+fn finger_distance(fingers: &[usize], a: &[char], b: &[char]) -> usize {
+    a.iter()
+        .zip(fingers)
+        .filter(|(c, _)| !blank::is_blank(**c))
+        .filter(|(c, finger)| {
+            b.iter()
+                .position(|x| x == *c)
+                .is_none_or(|i| fingers.get(i) != Some(finger))
+        })
+        .count()
+}
+
+/// Greedily group generations best first, joining each to the first cluster whose
+/// representative is within `threshold`, and print every cluster's representative.
+pub fn cluster(path: &Path, threshold: usize, keyboard: Option<&Keyboard>) -> Result<()> {
+    let mut generations = read_generations(path)?;
+    generations.sort_by(|a, b| a.score.total_cmp(&b.score));
+    let fingers: Option<Vec<usize>> =
+        keyboard.map(|k| k.fingers.map.iter().flatten().map(finger_index).collect());
+    let distance = |a: &[char], b: &[char]| match &fingers {
+        Some(fingers) => finger_distance(fingers, a, b),
+        None => positional_distance(a, b),
+    };
+
+    // (representative, size)
+    let mut clusters: Vec<(&Generation, usize)> = vec![];
+    for generation in &generations {
+        match clusters
+            .iter_mut()
+            .find(|(rep, _)| distance(&rep.layout, &generation.layout) <= threshold)
+        {
+            Some((_, size)) => *size += 1,
+            None => clusters.push((generation, 1)),
+        }
+    }
+
+    println!(
+        "{} layouts in {} clusters",
+        generations.len(),
+        clusters.len()
+    );
+    for (rep, size) in clusters {
+        println!(
+            "{}\t{}\t{}",
+            rep.score,
+            size,
+            String::from_iter(&rep.layout)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use crate::analysis::{get_metric, layout_from_charset};
+use crate::cache::{filtered_metric_data, MetricCache};
+use crate::evaluation::Evaluator;
+use crate::interrupt;
+use crate::refine::{Refine, Refiner};
+use crate::stratify::{StratifiedShuffle, Stratum};
+
+use anyhow::{ensure, Context, Result};
+use keycat::{analysis::Analyzer, Corpus, Layout};
+use linya::Progress;
+use rand::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt::Write as StringWrite;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use tracing::warn;
+
+// Rows each thread writes into one string before handing it to the writer
+const BATCH_ROWS: usize = 256;
+// Duplicate shuffles in a row after which a thread gives up on finding new ones
+const MAX_DUPLICATES: u32 = 10_000;
+// Separately locked parts of the set of seen shuffles, so threads rarely wait on each other
+const SEEN_SHARDS: usize = 64;
+
+fn layout_hash(layout: &Layout) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    layout.0.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How `output_table` collects its rows. Start from `CollectOptions::new`
+/// and set what differs.
+pub struct CollectOptions<'a> {
+    pub count: u64,
+    pub char_set: &'a str,
+    /// A CSV file, or `-` for stdout
+    pub output: &'a Path,
+    pub threads: u64,
+    /// Add rows to an existing file, up to `count` in all
+    pub append: bool,
+    /// Skip shuffles already analyzed in this collection
+    pub unique: bool,
+    /// Positions kept in place, from the first
+    pub pin: usize,
+    /// Positions kept in place besides the first `pin`
+    pub pinned: &'a [usize],
+    pub strata: &'a [Stratum],
+    /// A local search improving each shuffle for the given weights first
+    pub refine: Option<(Refine, &'a [(String, i16)])>,
+    pub cache: Option<&'a MetricCache>,
+}
+
+impl<'a> CollectOptions<'a> {
+    /// `count` plain shuffles of `char_set` into a new `output`, on `threads` threads.
+    pub fn new(count: u64, char_set: &'a str, output: &'a Path, threads: u64) -> Self {
+        CollectOptions {
+            count,
+            char_set,
+            output,
+            threads,
+            append: false,
+            unique: false,
+            pin: 0,
+            pinned: &[],
+            strata: &[],
+            refine: None,
+            cache: None,
+        }
+    }
+}
+
+/// Analyze `count` random shuffles of the char set as `options` say, writing
+/// the percentage of each metric as a CSV row. Fewer are written if the run is
+/// interrupted, or if `unique` runs out of new shuffles.
+pub fn output_table(
+    metric_names: Vec<String>,
+    metric_data: keymeow::MetricData,
+    corpus: Corpus,
+    options: CollectOptions,
+) -> Result<()> {
+    let CollectOptions {
+        count,
+        char_set,
+        output,
+        threads,
+        append,
+        unique,
+        pin,
+        pinned,
+        strata,
+        refine,
+        cache,
+    } = options;
+    ensure!(threads > 0, "need at least one thread");
+    let metrics: Result<Vec<usize>, _> = metric_names
+        .iter()
+        .map(|s| get_metric(s, &metric_data))
+        .collect();
+    let metrics = metrics.context("invalid metric")?;
+    let weights: Result<Vec<(usize, i16)>> = refine
+        .map_or(&[][..], |(_, weights)| weights)
+        .iter()
+        .map(|(name, weight)| Ok((get_metric(name, &metric_data)?, *weight)))
+        .collect();
+    let weights = weights.context("invalid refine weight")?;
+    let layout = layout_from_charset(&corpus, &metric_data, char_set);
+    let shuffler =
+        StratifiedShuffle::new(&corpus, &metric_data.keyboard, &layout, pin, pinned, strata)?;
+
+    let totals = layout.totals(&corpus);
+
+    let used: Vec<usize> = metrics
+        .iter()
+        .chain(weights.iter().map(|(m, _)| m))
+        .copied()
+        .collect();
+    let data = filtered_metric_data(metric_data, &used, layout.0.len(), cache);
+    let analyzer = Analyzer::from(data, corpus);
+    let refiner = refine
+        .map(|(search, _)| Refiner::new(search, &analyzer, Evaluator::from(weights), &shuffler));
+
+    let header: String = metric_names.iter().map(|m| format!("{m},")).collect();
+    let appending = append && output.exists();
+    let mut count = count;
+    if appending {
+        let existing = existing_rows(output, &header)?;
+        if existing >= count {
+            eprintln!("{} already has {existing} rows", output.display());
+            return Ok(());
+        }
+        count -= existing;
+    }
+
+    let mut writer: Box<dyn Write> = if output == Path::new("-") {
+        ensure!(!append, "can't append to stdout");
+        Box::new(std::io::stdout().lock())
+    } else if appending {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(output)
+            .context("couldn't open data file")?;
+        Box::new(LineWriter::new(file))
+    } else {
+        if let Some(parent) = output.parent() {
+            create_dir_all(parent).context("couldn't create data directory")?;
+        }
+        let file = File::create(output).context("couldn't create data file")?;
+        Box::new(LineWriter::new(file))
+    };
+
+    if !appending {
+        writeln!(writer, "{header}")?;
+    }
+    let progress = Mutex::new(Progress::new());
+    let bar = progress.lock().unwrap().bar(count.try_into()?, "Analyzing");
+
+    // Rows are funneled to this thread so only one writer touches the output
+    let (tx, rx) = mpsc::channel::<String>();
+    let seen = unique.then(|| {
+        (0..SEEN_SHARDS)
+            .map(|_| Mutex::new(HashSet::new()))
+            .collect::<Vec<_>>()
+    });
+    // Set by the first thread to give up on new shuffles, which stops the rest
+    let exhausted = AtomicBool::new(false);
+    let mut written = 0;
+    std::thread::scope(|s| -> Result<()> {
+        let (analyzer, layout, totals, metrics) = (&analyzer, &layout, &totals, &metrics);
+        let (shuffler, refiner, seen) = (&shuffler, refiner.as_ref(), seen.as_deref());
+        let (progress, bar, exhausted) = (&progress, &bar, &exhausted);
+        for thread in 0..threads {
+            // Spread the remainder so the threads share `count` rows between them
+            let rows = count / threads + u64::from(thread < count % threads);
+            let tx = tx.clone();
+            s.spawn(move || {
+                let mut stats = analyzer.calc_stats(layout);
+                let mut layout = layout.clone();
+                let mut rng = thread_rng();
+                let mut batch = String::new();
+                let mut batched = 0;
+                let (mut produced, mut duplicates) = (0, 0);
+                while produced < rows {
+                    if interrupt::interrupted() || exhausted.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    shuffler.shuffle(&mut layout, &mut rng);
+                    if let Some(seen) = seen {
+                        let hash = layout_hash(&layout);
+                        let shard = &seen[hash as usize % SEEN_SHARDS];
+                        if !shard.lock().unwrap().insert(hash) {
+                            duplicates += 1;
+                            if duplicates == MAX_DUPLICATES {
+                                exhausted.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                            continue;
+                        }
+                        duplicates = 0;
+                    }
+                    produced += 1;
+                    stats.fill(0.0);
+                    analyzer.recalc_stats(&mut stats, &layout);
+                    if let Some(refiner) = refiner {
+                        refiner.run(&mut layout, &mut stats, &mut rng);
+                    }
+                    for m in metrics {
+                        let percent = totals.percentage(stats[*m], analyzer.data.metrics[*m]);
+                        let _ = write!(batch, "{percent},");
+                    }
+                    batch.push('\n');
+                    batched += 1;
+                    if batched == BATCH_ROWS {
+                        let capacity = batch.capacity();
+                        let full = std::mem::replace(&mut batch, String::with_capacity(capacity));
+                        if tx.send(full).is_err() {
+                            break;
+                        }
+                        progress.lock().unwrap().inc_and_draw(bar, batched);
+                        batched = 0;
+                    }
+                }
+                // What's left when the rows run out or the run is interrupted
+                if !batch.is_empty() && tx.send(batch).is_ok() {
+                    progress.lock().unwrap().inc_and_draw(bar, batched);
+                }
+            });
+        }
+        drop(tx);
+        for rows in rx {
+            written += rows.lines().count();
+            writer.write_all(rows.as_bytes())?;
+        }
+        Ok(())
+    })?;
+    writer.flush()?;
+    if exhausted.into_inner() {
+        warn!(
+            "gave up after {MAX_DUPLICATES} duplicate layouts in a row, with {written} of \
+             {count} rows written; the char set may not have that many arrangements"
+        );
+    }
+
+    Ok(())
+}
+
+/// Number of complete rows already in a collect CSV, after checking its header
+/// matches. A partial last row left by an interrupted write is cut off.
+fn existing_rows(path: &Path, header: &str) -> Result<u64> {
+    let contents = std::fs::read_to_string(path).context("couldn't read data file")?;
+    let complete = contents.rfind('\n').map_or(0, |i| i + 1);
+    let mut lines = contents[..complete].lines();
+    ensure!(
+        lines.next() == Some(header),
+        "{} was collected with different metrics (expected header {header})",
+        path.display()
+    );
+    if complete < contents.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    Ok(lines.count() as u64)
+}
@@ -0,0 +1,208 @@
+use crate::analysis::add_stroke_amount;
+use crate::blank;
+
+use anyhow::{ensure, Context, Result};
+use keycat::analysis::Nstroke;
+use keycat::{Corpus, CorpusChar, NgramType};
+use keymeow::{LayoutData, Metric, MetricContext, MetricData};
+
+/// Add the "combo" monogram metric, counting each character typed through a
+/// combo, so generations filling combo slots can weigh chording against rolling.
+pub fn add_metric(metric_data: &mut MetricData) -> Result<()> {
+    ensure!(
+        !metric_data.metrics.iter().any(|m| m.short == "combo"),
+        "the combo metric is already defined"
+    );
+    let metric = metric_data.metrics.len();
+    metric_data.metrics.push(Metric {
+        name: "Combo presses".to_string(),
+        short: "combo".to_string(),
+        ngram_type: NgramType::Monogram,
+    });
+    let keys = metric_data.keyboard.keys.map.iter().flatten().count();
+    for pos in keys..keys + metric_data.keyboard.combos.len() {
+        add_stroke_amount(metric_data, Nstroke::Monostroke(pos), metric, 1.0);
+    }
+    Ok(())
+}
+
+/// How often the chars on `keys` are typed next to each other, which a combo on
+/// those keys risks misfiring on, in percent of bigrams.
+pub fn roll_conflict(ctx: &MetricContext, keys: &[usize]) -> f32 {
+    let corpus = &ctx.analyzer.corpus;
+    let totals = ctx.layout.totals(corpus);
+    let chars: Vec<CorpusChar> = keys.iter().map(|k| ctx.layout.0[*k]).collect();
+    let mut count = 0.0;
+    for a in &chars {
+        for b in chars.iter().filter(|b| *b != a) {
+            if *a != 0 && *b != 0 {
+                count += corpus.bigrams[corpus.bigram_idx(*a, *b)] as f32;
+            }
+        }
+    }
+    totals.percentage(count, NgramType::Bigram)
+}
+
+fn combo_label(ctx: &MetricContext, keys: &[usize]) -> String {
+    keys.iter()
+        .map(|k| match ctx.layout.0[*k] {
+            0 => blank::BLANK,
+            c => match ctx.analyzer.corpus.uncorpus_unigram(c) {
+                ' ' => '␣',
+                c => c,
+            },
+        })
+        .collect()
+}
+
+/// Rank combos by how often their keys are rolled as normal bigrams, and list
+/// the other combos sharing any of their keys.
+pub fn misfires(metric_data: MetricData, corpus: Corpus, layout: LayoutData) -> Result<()> {
+    let mut ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let kb_size = ctx.keyboard.keys.map.iter().flatten().count();
+    ctx.keyboard.process_combo_indexes();
+    let combos = &ctx.keyboard.combo_indexes;
+
+    let mut rows: Vec<(usize, f32)> = combos
+        .iter()
+        .enumerate()
+        .map(|(idx, keys)| (idx, roll_conflict(&ctx, keys)))
+        .collect();
+    rows.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    // (combo, output, misfire, overlaps)
+    let rows: Vec<(String, String, f32, String)> = rows
+        .into_iter()
+        .map(|(idx, conflict)| {
+            let keys = &combos[idx];
+            let output = match ctx.layout.0.get(kb_size + idx) {
+                Some(0) | None => String::from("-"),
+                Some(c) => format!("{:?}", ctx.analyzer.corpus.uncorpus_unigram(*c)),
+            };
+            let overlaps: Vec<String> = combos
+                .iter()
+                .enumerate()
+                .filter(|(other, other_keys)| {
+                    *other != idx && other_keys.iter().any(|k| keys.contains(k))
+                })
+                .map(|(_, other_keys)| combo_label(&ctx, other_keys))
+                .collect();
+            (
+                combo_label(&ctx, keys),
+                output,
+                conflict,
+                overlaps.join(" "),
+            )
+        })
+        .collect();
+
+    let width = column_width(rows.iter().map(|r| r.0.as_str()), "combo");
+    let output_width = column_width(rows.iter().map(|r| r.1.as_str()), "output");
+    println!(
+        "{:width$}  {:output_width$}  {:>8}  overlaps",
+        "combo", "output", "misfire"
+    );
+    for (keys, output, conflict, overlaps) in rows {
+        println!(
+            "{keys:width$}  {output:output_width$}  {:>8}  {overlaps}",
+            format!("{conflict:.3}%")
+        );
+    }
+    Ok(())
+}
+
+/// The width of a column of `values` headed by `header`, in characters.
+fn column_width<'a>(values: impl Iterator<Item = &'a str>, header: &'a str) -> usize {
+    values
+        .chain([header])
+        .map(|v| v.chars().count())
+        .max()
+        .unwrap_or(0)
+}
+
+fn char_frequency(corpus: &Corpus, c: CorpusChar) -> f32 {
+    let total: f32 = corpus.chars.iter().map(|c| *c as f32).sum::<f32>().max(1.0);
+    100.0 * corpus.chars[c] as f32 / total
+}
+
+/// Suggest characters for unassigned combo slots, searching every pairing of
+/// a slot with a character missing from the layout for those with the most net
+/// benefit: the share of characters the combo would type, less how often its keys
+/// are rolled and so risk misfiring. Each slot and character is suggested once,
+/// best first, for the `top` best pairs.
+pub fn suggest(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+    top: usize,
+) -> Result<()> {
+    let mut ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let kb_size = ctx.keyboard.keys.map.iter().flatten().count();
+    ctx.keyboard.process_combo_indexes();
+
+    let slots: Vec<(usize, f32)> = ctx
+        .keyboard
+        .combo_indexes
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| ctx.layout.0.get(kb_size + idx) == Some(&0))
+        .map(|(idx, keys)| (idx, roll_conflict(&ctx, keys)))
+        .collect();
+    if slots.is_empty() {
+        println!("no unassigned combo slots");
+        return Ok(());
+    }
+
+    let corpus = &ctx.analyzer.corpus;
+    let candidates: Vec<(CorpusChar, f32)> = (1..corpus.char_list.len())
+        .filter(|c| !ctx.layout.0.contains(c))
+        .map(|c| (c, char_frequency(corpus, c)))
+        .filter(|(_, freq)| *freq > 0.0)
+        .collect();
+    // (slot, char, saved, misfire) for every pairing, best net benefit first
+    let mut pairs: Vec<(usize, CorpusChar, f32, f32)> = slots
+        .iter()
+        .flat_map(|(idx, conflict)| {
+            candidates
+                .iter()
+                .map(|(c, freq)| (*idx, *c, *freq, *conflict))
+        })
+        .collect();
+    pairs.sort_by(|a, b| (b.2 - b.3).total_cmp(&(a.2 - a.3)));
+
+    let (mut used_slots, mut used_chars) = (vec![], vec![]);
+    let mut suggestions = vec![];
+    for (idx, c, saved, conflict) in pairs {
+        if suggestions.len() == top {
+            break;
+        }
+        if !used_slots.contains(&idx) && !used_chars.contains(&c) {
+            used_slots.push(idx);
+            used_chars.push(c);
+            suggestions.push((idx, c, saved, conflict));
+        }
+    }
+
+    let labels: Vec<String> = suggestions
+        .iter()
+        .map(|(idx, ..)| combo_label(&ctx, &ctx.keyboard.combo_indexes[*idx]))
+        .collect();
+    let width = column_width(labels.iter().map(String::as_str), "combo");
+    println!(
+        "{:width$}  {:6}{:>9}{:>9}{:>9}",
+        "combo", "char", "saved", "misfire", "net"
+    );
+    let percent = |x: f32| format!("{x:.3}%");
+    for (keys, (_, c, saved, conflict)) in labels.iter().zip(suggestions) {
+        println!(
+            "{keys:width$}  {:6}{:>9}{:>9}{:>9}",
+            format!("{:?}", corpus.uncorpus_unigram(c)),
+            percent(saved),
+            percent(conflict),
+            percent(saved - conflict)
+        );
+    }
+    Ok(())
+}
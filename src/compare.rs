@@ -0,0 +1,192 @@
+use crate::analysis::layout_matrices;
+use crate::explore::{finger_style, keyboard_lines};
+use crate::heatmap::key_labels;
+
+use anyhow::{ensure, Context, Result};
+use keycat::Layout;
+use keymeow::{LayoutData, MetricContext};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Direction},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::iter::once;
+
+struct Comparison {
+    ctx: MetricContext,
+    names: Vec<String>,
+    matrices: Vec<Layout>,
+    percentages: Vec<Vec<f32>>,
+    shown: Vec<bool>,
+    focus: usize,
+    scroll: usize,
+}
+
+impl Comparison {
+    fn visible(&self) -> Vec<usize> {
+        (0..self.names.len()).filter(|i| self.shown[*i]).collect()
+    }
+
+    /// Handle a key press, returning false to quit.
+    fn handle(&mut self, code: KeyCode) -> bool {
+        let last_metric = self.ctx.metrics.len().saturating_sub(1);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') => self.focus = self.focus.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.focus = (self.focus + 1).min(self.names.len() - 1)
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll = (self.scroll + 1).min(last_metric),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::PageDown => self.scroll = (self.scroll + 10).min(last_metric),
+            // Keep at least one layout on screen
+            KeyCode::Char(' ') if !self.shown[self.focus] || self.visible().len() > 1 => {
+                self.shown[self.focus] = !self.shown[self.focus]
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let rows = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Percentage(40),
+                Constraint::Min(0),
+            ])
+            .split(f.area());
+
+        let toggles: Vec<Span> = self
+            .names
+            .iter()
+            .enumerate()
+            .flat_map(|(i, name)| {
+                let mut style = match self.shown[i] {
+                    true => Style::default().fg(Color::Yellow),
+                    false => Style::default().fg(Color::DarkGray),
+                };
+                if i == self.focus {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                [Span::styled(format!(" {name} "), style), Span::raw(" ")]
+            })
+            .collect();
+        let header = Paragraph::new(Line::from(toggles)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("h/l choose, space show/hide, j/k scroll metrics, q quit"),
+        );
+        f.render_widget(header, rows[0]);
+
+        let visible = self.visible();
+        let columns = ratatui::layout::Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![
+                Constraint::Ratio(1, visible.len() as u32);
+                visible.len()
+            ])
+            .split(rows[1]);
+        for (i, area) in visible.iter().zip(columns.iter()) {
+            let labels = key_labels(&self.ctx.analyzer.corpus, &self.matrices[*i].0);
+            let grid = Paragraph::new(keyboard_lines(&self.ctx.keyboard, &labels, |i| {
+                finger_style(&self.ctx.keyboard, i)
+            }))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.names[*i].as_str()),
+            );
+            f.render_widget(grid, *area);
+        }
+
+        let metrics = self.ctx.metrics.iter().enumerate().skip(self.scroll);
+        let table_rows = metrics.map(|(m, metric)| {
+            let values: Vec<f32> = visible.iter().map(|i| self.percentages[*i][m]).collect();
+            let best = values.iter().copied().fold(f32::INFINITY, f32::min);
+            let worst = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            // Lowest in green, highest in red, unless they're all the same
+            let cells = values.iter().map(|v| {
+                let style = match *v {
+                    _ if best == worst => Style::default(),
+                    v if v == best => Style::default().fg(Color::Green),
+                    v if v == worst => Style::default().fg(Color::Red),
+                    _ => Style::default(),
+                };
+                Span::styled(format!("{v:.2}%"), style)
+            });
+            let name = Span::styled(metric.name.clone(), Style::default().fg(Color::Yellow));
+            Row::new(once(name).chain(cells).collect::<Vec<_>>())
+        });
+        let widths: Vec<Constraint> = once(Constraint::Length(24))
+            .chain(visible.iter().map(|_| Constraint::Length(12)))
+            .collect();
+        let header = Row::new(
+            once("Metric")
+                .chain(visible.iter().map(|i| self.names[*i].as_str()))
+                .map(|s| Span::styled(s, Style::default().add_modifier(Modifier::BOLD)))
+                .collect::<Vec<_>>(),
+        );
+        let table = Table::new(table_rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title("Stats"));
+        f.render_widget(table, rows[2]);
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, comparison: &mut Comparison) -> Result<()> {
+    loop {
+        terminal.draw(|f| comparison.draw(f))?;
+        if let Event::Key(key) = event::read()? {
+            // Raw mode delivers Ctrl-C as a key rather than a signal
+            let interrupt =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press && (interrupt || !comparison.handle(key.code)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Show several layouts side by side with a metric table, toggling which are shown.
+pub fn compare(
+    metric_data: keymeow::MetricData,
+    corpus: keycat::Corpus,
+    layouts: Vec<LayoutData>,
+) -> Result<()> {
+    ensure!(!layouts.is_empty(), "need at least one layout to compare");
+    let ctx = MetricContext::new(&layouts[0], metric_data, corpus)
+        .context("could not produce metric context")?;
+    let matrices = layout_matrices(&ctx, &layouts)?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+    let percentages = matrices
+        .iter()
+        .map(|matrix| {
+            let stats = ctx.analyzer.calc_stats(matrix);
+            ctx.metrics
+                .iter()
+                .enumerate()
+                .map(|(i, m)| totals.percentage(stats[i], m.ngram_type))
+                .collect()
+        })
+        .collect();
+    let mut comparison = Comparison {
+        names: layouts.into_iter().map(|l| l.name).collect(),
+        shown: vec![true; matrices.len()],
+        matrices,
+        percentages,
+        ctx,
+        focus: 0,
+        scroll: 0,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut comparison);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,52 @@
+use crate::Cli;
+
+use clap::builder::PossibleValuesParser;
+use clap::{Command, CommandFactory};
+use clap_complete::{generate, Shell};
+use km_data::Data as KeymeowData;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn sorted_names(map: &HashMap<String, PathBuf>) -> Vec<String> {
+    let mut names: Vec<String> = map.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+/// Offer `values` when completing any argument named in `candidates`, recursively.
+fn with_candidates(mut cmd: Command, candidates: &[(&str, &[String])]) -> Command {
+    for (id, values) in candidates {
+        if cmd.get_arguments().any(|a| a.get_id() == *id) {
+            let values = values.to_vec();
+            cmd = cmd.mut_arg(*id, |a| a.value_parser(PossibleValuesParser::new(values)));
+        }
+    }
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        cmd = cmd.mut_subcommand(name, |s| with_candidates(s, candidates));
+    }
+    cmd
+}
+
+/// Print a completion script for `shell`, including the corpora, keyboards and
+/// layouts currently in the data directory.
+pub fn print_completions(shell: Shell, keymeow: &KeymeowData) {
+    let corpora = sorted_names(&keymeow.corpora);
+    let keyboards = sorted_names(&keymeow.keyboards);
+    let layouts = sorted_names(&keymeow.layouts);
+    let mut cmd = with_candidates(
+        Cli::command(),
+        &[
+            ("corpus", &corpora),
+            ("keyboard", &keyboards),
+            ("layout", &layouts),
+            ("layouts", &layouts),
+            ("reference", &layouts),
+        ],
+    );
+    let name = cmd.get_name().to_string();
+    generate(shell, &mut cmd, name, &mut std::io::stdout());
+}
@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Per-user defaults, read from `~/.config/keywhisker/config.toml` or `--config`.
+/// Command-line flags take precedence over anything set here.
+///
+/// ```toml
+/// data_dir = "/srv/keymeow"
+/// corpus = ["monkeyracer"]
+/// keyboard = "ansi"
+/// collect_output = "data/data.csv"
+/// generations_dir = "generations"
+/// threads = 16
+/// cache_dir = "/tmp/keywhisker"
+/// history_file = "generations/history.jsonl"
+///
+/// [annealing]
+/// cooling_rate = 0.9
+///
+/// [keyboards.ansi]
+/// pin = [0, 1]
+/// char_set = "etaoinshrdlcumwfgypbvk,.jxqz"
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where to load keymeow data from instead of downloading it
+    pub data_dir: Option<PathBuf>,
+    /// Corpora to analyze with, as name or name:weight
+    pub corpus: Vec<String>,
+    pub keyboard: Option<String>,
+    /// Where `collect` writes when no output is given
+    pub collect_output: Option<PathBuf>,
+    /// Where `run-generation` writes its tsv files
+    pub generations_dir: Option<PathBuf>,
+    /// Worker threads for `collect`
+    pub threads: Option<u64>,
+    /// Where analyzer metric data is cached between runs [default: ~/.cache/keywhisker]
+    pub cache_dir: Option<PathBuf>,
+    /// Where every generation run is recorded [default: ~/.local/share/keywhisker/history.jsonl]
+    pub history_file: Option<PathBuf>,
+    pub annealing: Annealing,
+    /// Defaults for generating on each keyboard, by the name it's given with -k
+    pub keyboards: HashMap<String, KeyboardDefaults>,
+}
+
+/// What `run-generation` and `collect` use on a keyboard when not told otherwise.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardDefaults {
+    /// Positions to keep in place, like thumb keys, unless --pin is given
+    pub pin: Vec<usize>,
+    /// Used with --keyboard-chars instead of a char set
+    pub char_set: Option<String>,
+}
+
+/// Parameters of the DDAKO simulated annealing strategy.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Annealing {
+    pub cooling_rate: f32,
+    pub cooling_interval: f32,
+    pub cooling_interval_min: f32,
+    pub cooling_interval_max: f32,
+    pub max_iterations: Option<u32>,
+}
+
+impl Default for Annealing {
+    fn default() -> Self {
+        Annealing {
+            cooling_rate: 0.9,
+            cooling_interval: 5.0,
+            cooling_interval_min: 1.0,
+            cooling_interval_max: 10.0,
+            max_iterations: None,
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("keywhisker").join("config.toml"))
+}
+
+impl Config {
+    /// Load `path`, or the default config file if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("couldn't read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Worker threads for `collect`, defaulting to the available parallelism.
+    pub fn threads(&self) -> u64 {
+        self.threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get() as u64))
+    }
+
+    /// The cache directory, if one is configured or there's a home directory to put it in.
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.cache_dir {
+            return Some(dir.clone());
+        }
+        let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(cache_dir.join("keywhisker"))
+    }
+
+    /// The run history file, if one is configured or there's a home directory to put it in.
+    pub fn history_file(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.history_file {
+            return Some(path.clone());
+        }
+        let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?)
+                .join(".local")
+                .join("share"),
+        };
+        Some(data_dir.join("keywhisker").join("history.jsonl"))
+    }
+
+    /// `keyboard`'s default pinned positions, unless a `pin` count is given instead.
+    pub fn pinned(&self, keyboard: Option<&str>, pin: Option<usize>) -> Vec<usize> {
+        match (pin, keyboard.and_then(|k| self.keyboards.get(k))) {
+            (None, Some(defaults)) => defaults.pin.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// `char_set` if given, or else `keyboard`'s default char set.
+    pub fn char_set(&self, keyboard: Option<&str>, char_set: Option<&str>) -> Result<String> {
+        if let Some(char_set) = char_set {
+            return Ok(char_set.to_string());
+        }
+        let keyboard = keyboard.context("no keyboard to find a default char set for")?;
+        self.keyboards
+            .get(keyboard)
+            .and_then(|k| k.char_set.clone())
+            .with_context(|| format!("no default char set for {keyboard} in the config file"))
+    }
+
+    pub fn generations_dir(&self) -> &Path {
+        self.generations_dir
+            .as_deref()
+            .unwrap_or(Path::new("generations"))
+    }
+}
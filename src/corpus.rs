@@ -0,0 +1,330 @@
+use crate::blank;
+use crate::lookup::ensure_file_name;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use keycat::{Corpus, CorpusChar};
+use km_data::Data as KeymeowData;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+// Each input is rescaled so its ngram counts sum to `weight * SCALE`
+const SCALE: f64 = 1_000_000_000.0;
+
+fn total(counts: &[u32]) -> f64 {
+    counts.iter().map(|c| *c as f64).sum::<f64>().max(1.0)
+}
+
+fn add_scaled(slot: &mut u32, count: u32, factor: f64) {
+    *slot += (count as f64 * factor).round() as u32;
+}
+
+/// Blend corpora into one, normalizing each to its own totals before weighting.
+///
+/// Metric stats are linear in ngram counts, so analyzing the blend gives the same
+/// result as analyzing each corpus separately and combining the scores by weight.
+/// The blend's counts are scaled to sum to a billion of each ngram type, so they
+/// aren't the number of times anything was seen; see [`is_scaled`].
+pub fn merge(corpora: &[(Corpus, f32)]) -> Result<Corpus> {
+    for (_, weight) in corpora {
+        ensure!(
+            *weight >= 0.0,
+            "corpus weights can't be negative, got {weight}"
+        );
+    }
+    let weight_sum: f32 = corpora.iter().map(|(_, w)| w).sum();
+    ensure!(
+        weight_sum > 0.0,
+        "corpus weights must sum to a positive number"
+    );
+
+    let mut char_list: Vec<Vec<char>> = vec![];
+    for (corpus, _) in corpora {
+        for chars in &corpus.char_list {
+            match chars.first() {
+                None | Some('\0') => {}
+                Some(c) if char_list.iter().any(|l| l.first() == Some(c)) => {}
+                Some(_) => char_list.push(chars.clone()),
+            }
+        }
+    }
+    let mut merged = Corpus::with_char_list(char_list);
+
+    for (corpus, weight) in corpora {
+        let weight = (weight / weight_sum) as f64;
+        let map: Vec<CorpusChar> = corpus
+            .char_list
+            .iter()
+            .map(|chars| match chars.first() {
+                None | Some('\0') => 0,
+                Some(c) => merged.corpus_char(*c),
+            })
+            .collect();
+
+        let factor = weight * SCALE / total(&corpus.chars);
+        for (a, ma) in map.iter().enumerate() {
+            add_scaled(&mut merged.chars[*ma], corpus.chars[a], factor);
+        }
+
+        let bigram_factor = weight * SCALE / total(&corpus.bigrams);
+        let skipgram_factor = weight * SCALE / total(&corpus.skipgrams);
+        for (a, ma) in map.iter().enumerate() {
+            for (b, mb) in map.iter().enumerate() {
+                let i = corpus.bigram_idx(a, b);
+                let j = merged.bigram_idx(*ma, *mb);
+                add_scaled(&mut merged.bigrams[j], corpus.bigrams[i], bigram_factor);
+                add_scaled(
+                    &mut merged.skipgrams[j],
+                    corpus.skipgrams[i],
+                    skipgram_factor,
+                );
+            }
+        }
+
+        let trigram_factor = weight * SCALE / total(&corpus.trigrams);
+        for (a, ma) in map.iter().enumerate() {
+            for (b, mb) in map.iter().enumerate() {
+                for (c, mc) in map.iter().enumerate() {
+                    let i = corpus.trigram_idx(a, b, c);
+                    let j = merged.trigram_idx(*ma, *mb, *mc);
+                    add_scaled(&mut merged.trigrams[j], corpus.trigrams[i], trigram_factor);
+                }
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Whether `corpus` looks like a blend from [`merge`], its counts summing to the
+/// scale blends are written at rather than to how much text was counted.
+pub fn is_scaled(corpus: &Corpus) -> bool {
+    let near_scale = |counts: &[u32]| (total(counts) - SCALE).abs() < SCALE * 1e-3;
+    near_scale(&corpus.chars) && near_scale(&corpus.bigrams) && near_scale(&corpus.trigrams)
+}
+
+/// Unshifted/shifted symbol pairs on a US ANSI keyboard
+pub const US_SHIFT_PAIRS: &str = "`~1!2@3#4$5%6^7&8*9(0)-_=+[{]}\\|;:'\",<.>/?";
+
+pub struct BuildOptions {
+    pub fold_case: bool,
+    pub strip_punctuation: bool,
+    pub whitelist: Option<String>,
+    /// Symbol pairs that share a key, e.g. `1!2@`; letters are paired with their
+    /// capitals. Shifted characters count as presses of their base key.
+    pub shift_pairs: Option<String>,
+}
+
+impl BuildOptions {
+    fn normalize(&self, text: &str) -> String {
+        let text = match self.fold_case {
+            true => text.to_lowercase(),
+            false => text.to_owned(),
+        };
+        match self.strip_punctuation {
+            true => text.chars().filter(|c| !c.is_ascii_punctuation()).collect(),
+            false => text,
+        }
+    }
+}
+
+/// Tokenize text files into a corpus. Characters outside the whitelist (or control
+/// characters, without one) are left out of the char list and break up ngrams.
+pub fn build(files: &[PathBuf], options: &BuildOptions) -> Result<Corpus> {
+    let texts: Result<Vec<String>> = files
+        .iter()
+        .map(|f| {
+            fs::read_to_string(f)
+                .with_context(|| format!("couldn't read {}", f.display()))
+                .map(|text| options.normalize(&text))
+        })
+        .collect();
+    let texts = texts?;
+
+    let chars: BTreeSet<char> = match &options.whitelist {
+        Some(whitelist) => options.normalize(whitelist).chars().collect(),
+        None => texts
+            .iter()
+            .flat_map(|t| t.chars())
+            .filter(|c| !c.is_control())
+            .collect(),
+    };
+    ensure!(!chars.is_empty(), "no characters to build a corpus from");
+
+    let char_list = match &options.shift_pairs {
+        Some(pairs) => shift_groups(chars, pairs)?,
+        None => chars.into_iter().map(|c| vec![c]).collect(),
+    };
+    let mut corpus = Corpus::with_char_list(char_list);
+    for text in &texts {
+        corpus.add_str(text);
+    }
+    Ok(corpus)
+}
+
+/// Group each character with its shifted counterpart, base character first.
+fn shift_groups(chars: BTreeSet<char>, pairs: &str) -> Result<Vec<Vec<char>>> {
+    let pairs: Vec<char> = pairs.chars().collect();
+    ensure!(
+        pairs.len().is_multiple_of(2),
+        "shift pairs must come in twos, e.g. 1!2@"
+    );
+    let unshift = |c: char| -> char {
+        if let Some(pair) = pairs.chunks(2).find(|p| p[1] == c) {
+            return pair[0];
+        }
+        let mut lower = c.to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(l), None) => l,
+            _ => c,
+        }
+    };
+
+    let mut groups: BTreeMap<char, Vec<char>> = BTreeMap::new();
+    for c in chars {
+        let base = unshift(c);
+        let group = groups.entry(base).or_insert_with(|| vec![base]);
+        if !group.contains(&c) {
+            group.push(c);
+        }
+    }
+    Ok(groups.into_values().collect())
+}
+
+/// Store a corpus alongside the downloaded ones so it can be loaded by name.
+pub fn save(data: &KeymeowData, name: &str, corpus: &Corpus) -> Result<PathBuf> {
+    let path = corpus_path(&data.dir, name)?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, rmp_serde::to_vec(corpus)?)
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Where a corpus called `name` is saved under the data directory `dir`.
+fn corpus_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    ensure_file_name("corpus", name)?;
+    Ok(dir.join("corpora").join(format!("{name}.corpus")))
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum NgramKind {
+    Char,
+    Bigram,
+    Skipgram,
+    Trigram,
+}
+
+/// The `n` most frequent ngrams of a kind, with their share of all such ngrams.
+pub fn top_ngrams(corpus: &Corpus, kind: NgramKind, n: usize) -> Vec<(String, f64)> {
+    let len = corpus.char_list.len();
+    let counts = match kind {
+        NgramKind::Char => &corpus.chars,
+        NgramKind::Bigram => &corpus.bigrams,
+        NgramKind::Skipgram => &corpus.skipgrams,
+        NgramKind::Trigram => &corpus.trigrams,
+    };
+    // Ngrams touching char 0 (anything outside the char list) aren't meaningful
+    let ngrams: Vec<(Vec<CorpusChar>, usize)> = match kind {
+        NgramKind::Char => (1..len).map(|a| (vec![a], a)).collect(),
+        NgramKind::Bigram | NgramKind::Skipgram => (1..len)
+            .flat_map(|a| (1..len).map(move |b| (a, b)))
+            .map(|(a, b)| (vec![a, b], corpus.bigram_idx(a, b)))
+            .collect(),
+        NgramKind::Trigram => (1..len)
+            .flat_map(|a| (1..len).flat_map(move |b| (1..len).map(move |c| (a, b, c))))
+            .map(|(a, b, c)| (vec![a, b, c], corpus.trigram_idx(a, b, c)))
+            .collect(),
+    };
+
+    let mut ngrams: Vec<(Vec<CorpusChar>, u32)> = ngrams
+        .into_iter()
+        .map(|(chars, i)| (chars, counts[i]))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    ngrams.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let total = total(counts);
+    ngrams
+        .into_iter()
+        .take(n)
+        .map(|(chars, count)| {
+            let ngram = chars.iter().map(|c| corpus.uncorpus_unigram(*c)).collect();
+            (ngram, count as f64 / total * 100.0)
+        })
+        .collect()
+}
+
+/// Check that every character of `char_set` is in the corpus, returning the
+/// percentage of the corpus' characters that the set covers. Blank keys are skipped.
+pub fn coverage(corpus: &Corpus, char_set: &str) -> Result<f64> {
+    let keys: Vec<char> = char_set.chars().filter(|c| !blank::is_blank(*c)).collect();
+    let missing: String = keys
+        .iter()
+        .filter(|c| !corpus.char_map.contains_key(c))
+        .collect();
+    if !missing.is_empty() {
+        bail!("characters not in the corpus: {missing:?}");
+    }
+
+    let covered: BTreeSet<CorpusChar> = keys.iter().map(|c| corpus.corpus_char(*c)).collect();
+    let covered: f64 = covered.iter().map(|c| corpus.chars[*c] as f64).sum();
+    Ok(covered / total(&corpus.chars[1..]) * 100.0)
+}
+
+/// Run [`coverage`] and report the result on stderr.
+pub fn check_coverage(corpus: &Corpus, char_set: &str) -> Result<()> {
+    let coverage = coverage(corpus, char_set)?;
+    eprintln!("char set covers {coverage:.2}% of the corpus");
+    if coverage < 90.0 {
+        warn!("over 10% of the corpus falls outside the char set");
+    }
+    Ok(())
+}
+
+/// The smallest trigram count to keep so that kept trigrams make up `percent` of all.
+pub fn coverage_min_count(corpus: &Corpus, percent: f64) -> u32 {
+    let mut counts: Vec<u32> = corpus.trigrams.iter().copied().filter(|c| *c > 0).collect();
+    counts.sort_unstable_by_key(|c| std::cmp::Reverse(*c));
+    let target = total(&corpus.trigrams) * percent / 100.0;
+    let mut sum = 0.0;
+    for count in counts {
+        sum += count as f64;
+        if sum >= target {
+            return count;
+        }
+    }
+    0
+}
+
+/// Drop trigrams seen fewer than `min_count` times, returning how many were dropped.
+pub fn prune(corpus: &mut Corpus, min_count: u32) -> usize {
+    let mut dropped = 0;
+    for count in corpus
+        .trigrams
+        .iter_mut()
+        .filter(|c| **c > 0 && **c < min_count)
+    {
+        *count = 0;
+        dropped += 1;
+    }
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpus_names_stay_in_the_corpora_directory() {
+        let dir = Path::new("data");
+        assert_eq!(
+            corpus_path(dir, "blend").unwrap(),
+            dir.join("corpora").join("blend.corpus")
+        );
+        for name in ["../blend", "..", "/tmp/blend", "a/b", ""] {
+            assert!(corpus_path(dir, name).is_err(), "{name:?} was accepted");
+        }
+    }
+}
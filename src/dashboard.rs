@@ -0,0 +1,486 @@
+use crate::analysis::layout_chars;
+use crate::ddako::simulated_annealing::Snapshot;
+use crate::evaluation::Reference;
+use crate::explore::{finger_style, keyboard_lines};
+use crate::heatmap::key_labels;
+use crate::interrupt;
+use crate::reporter::Reporter;
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use keycat::{Corpus, Layout, NgramType, Totals};
+use keymeow::{Keyboard, LayoutData};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    crossterm::terminal::{disable_raw_mode, enable_raw_mode},
+    layout::{Constraint, Direction},
+    style::{Color, Modifier, Style},
+    symbols::Marker,
+    text::Span,
+    widgets::{
+        Axis, Block, Borders, Chart, Dataset, Gauge, GraphType, Paragraph, Row, Sparkline, Table,
+    },
+    Frame, Terminal,
+};
+use std::fs::File;
+use std::io::Stdout;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Chart points kept before the history is thinned out
+const MAX_HISTORY: usize = 2000;
+const REDRAW_INTERVAL: Duration = Duration::from_secs(3);
+const KEY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Live view of a DDAKO run: a score chart, the best layout so far, per-metric
+/// trends and the optimizer's raw stats.
+pub struct Dashboard<'a> {
+    keyboard: &'a Keyboard,
+    corpus: &'a Corpus,
+    totals: &'a Totals,
+    metrics: &'a [(String, usize, NgramType)],
+    run: u64,
+    runs: u64,
+    current: Vec<(f64, f64)>,
+    best: Vec<(f64, f64)>,
+    // Hundredths of a percent, since sparklines only take integers
+    metric_history: Vec<Vec<u64>>,
+    best_layout: Vec<usize>,
+    stays: f64,
+    // The optimizer's raw stats, by label
+    stats: IndexMap<&'static str, String>,
+    reference: Option<&'a Reference>,
+    // Where `s` saves the best layout, and the name it's saved under
+    save_to: Option<(&'a Path, &'a str)>,
+    paused: bool,
+    message: String,
+}
+
+impl<'a> Dashboard<'a> {
+    pub fn new(
+        keyboard: &'a Keyboard,
+        corpus: &'a Corpus,
+        totals: &'a Totals,
+        metrics: &'a [(String, usize, NgramType)],
+        run: u64,
+        runs: u64,
+    ) -> Self {
+        Dashboard {
+            keyboard,
+            corpus,
+            totals,
+            metrics,
+            run,
+            runs,
+            current: vec![],
+            best: vec![],
+            metric_history: vec![vec![]; metrics.len()],
+            best_layout: vec![],
+            stays: 0.0,
+            stats: [
+                "Evaluation Rate",
+                "Min/Max Interval",
+                "Iteration",
+                "Stays",
+                "Temp",
+                "Cooling Interval",
+                "Acceptance Rate",
+                "Current",
+                "Best",
+            ]
+            .into_iter()
+            .map(|label| (label, String::new()))
+            .collect(),
+            reference: None,
+            save_to: None,
+            paused: false,
+            message: String::new(),
+        }
+    }
+
+    /// Let `s` write the best layout into `dir`, named after the generation.
+    pub fn save_to(mut self, dir: &'a Path, name: &'a str) -> Self {
+        self.save_to = Some((dir, name));
+        self
+    }
+
+    /// Show the best layout's deltas from `reference`.
+    pub fn compare_to(mut self, reference: &'a Reference) -> Self {
+        self.stats.insert("vs Reference", String::new());
+        self.reference = Some(reference);
+        self
+    }
+
+    pub fn set_stat(&mut self, label: &'static str, value: String) {
+        self.stats.insert(label, value);
+    }
+
+    pub fn render(&self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
+        if atty::is(atty::Stream::Stdout) {
+            terminal.clear().unwrap();
+            terminal.draw(|f| self.draw(f)).unwrap();
+        }
+    }
+
+    /// React to keys pressed since the last call: `p` pauses until pressed
+    /// again, `s` saves the best layout so far and `q` stops the generation,
+    /// keeping its results.
+    pub fn handle_keys(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
+        loop {
+            let timeout = match self.paused {
+                true => Duration::from_millis(250),
+                false => Duration::ZERO,
+            };
+            if !event::poll(timeout).unwrap_or(false) {
+                match self.paused {
+                    true => continue,
+                    false => return,
+                }
+            }
+            let Ok(Event::Key(key)) = event::read() else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            // Raw mode delivers Ctrl-C as a key rather than a signal
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                _ if ctrl_c => self.stop(),
+                KeyCode::Char('q') => self.stop(),
+                KeyCode::Char('p') => {
+                    self.paused = !self.paused;
+                    self.message = match self.paused {
+                        true => "paused, p to resume".to_string(),
+                        false => String::new(),
+                    };
+                }
+                KeyCode::Char('s') => {
+                    self.message = match self.save_best() {
+                        Ok(path) => format!("wrote {}", path.display()),
+                        Err(e) => format!("save failed: {e}"),
+                    }
+                }
+                _ => {}
+            }
+            self.render(terminal);
+        }
+    }
+
+    fn stop(&mut self) {
+        interrupt::request();
+        self.paused = false;
+        self.message = "stopping".to_string();
+    }
+
+    fn save_best(&self) -> Result<PathBuf> {
+        let (dir, name) = self.save_to.context("nowhere to save layouts")?;
+        let name = format!("{name}_{}_best", self.run);
+        let data = LayoutData::flexible_from_keyboard_layout(
+            self.keyboard,
+            &Layout(self.best_layout.clone()),
+            self.corpus,
+        )
+        .name(name.clone());
+        let path = dir.join(name).with_extension("json");
+        serde_json::to_writer_pretty(File::create(&path)?, &data)?;
+        Ok(path)
+    }
+
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        if self.best.len() >= MAX_HISTORY {
+            // Halve the resolution so long runs stay bounded
+            let thin = |v: &mut Vec<(f64, f64)>| *v = v.iter().step_by(2).copied().collect();
+            thin(&mut self.current);
+            thin(&mut self.best);
+            for history in &mut self.metric_history {
+                *history = history.iter().step_by(2).copied().collect();
+            }
+        }
+        let x = snapshot.iteration as f64;
+        self.current.push((x, snapshot.current as f64));
+        self.best.push((x, snapshot.best as f64));
+        for ((_, m, ngram_type), history) in self.metrics.iter().zip(&mut self.metric_history) {
+            let pc = self.totals.percentage(snapshot.best_stats[*m], *ngram_type);
+            history.push((pc * 100.0).max(0.0) as u64);
+        }
+        self.best_layout = snapshot.best_layout.to_vec();
+        self.stays = snapshot.stays as f64 / snapshot.stopping_point.max(1) as f64;
+
+        let layout_text = |layout: &[usize], score: f32| {
+            format!(
+                "{score}\t({})",
+                layout_chars(self.corpus, &Layout(layout.to_vec()))
+            )
+        };
+        let stats = [
+            (
+                "Iteration",
+                format!(
+                    "{} ({} since improvement)",
+                    snapshot.iteration, snapshot.since_improvement
+                ),
+            ),
+            (
+                "Stays",
+                format!("{}/{}", snapshot.stays, snapshot.stopping_point),
+            ),
+            ("Temp", snapshot.temperature.to_string()),
+            ("Cooling Interval", snapshot.cooling_interval.to_string()),
+            ("Acceptance Rate", snapshot.acceptance_rate.to_string()),
+            (
+                "Current",
+                layout_text(snapshot.current_layout, snapshot.current),
+            ),
+            ("Best", layout_text(snapshot.best_layout, snapshot.best)),
+        ];
+        for (label, value) in stats {
+            self.stats.insert(label, value);
+        }
+        if let Some(reference) = self.reference {
+            let deltas = reference.deltas(snapshot.best_stats, snapshot.best);
+            self.stats.insert("vs Reference", deltas);
+        }
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let rows = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Percentage(45),
+                Constraint::Min(0),
+            ])
+            .split(f.area());
+        let middle = ratatui::layout::Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+            .split(rows[1]);
+        let bottom = ratatui::layout::Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows[2]);
+
+        let done = (self.run as f64 + self.stays.min(1.0)) / self.runs.max(1) as f64;
+        let title = match self.message.as_str() {
+            "" => "Progress (p pause, s save best, q stop)".to_string(),
+            message => format!("Progress ({message})"),
+        };
+        let gauge = Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(done.clamp(0.0, 1.0))
+            .label(format!(
+                "run {}/{}, {:.0}% of stopping point",
+                self.run + 1,
+                self.runs,
+                100.0 * self.stays
+            ));
+        f.render_widget(gauge, rows[0]);
+
+        f.render_widget(self.score_chart(), middle[0]);
+
+        let labels = key_labels(self.corpus, &self.best_layout);
+        let grid = Paragraph::new(keyboard_lines(self.keyboard, &labels, |i| {
+            finger_style(self.keyboard, i)
+        }))
+        .block(Block::default().borders(Borders::ALL).title("Best layout"));
+        f.render_widget(grid, middle[1]);
+
+        self.draw_sparklines(f, bottom[0]);
+
+        let table = Table::new(
+            self.stats.iter().map(|(key, value)| {
+                Row::new(vec![
+                    Span::styled(key.to_string(), Style::default().fg(Color::Yellow)),
+                    Span::styled(value.to_string(), Style::default().fg(Color::White)),
+                ])
+            }),
+            [Constraint::Percentage(30), Constraint::Percentage(70)],
+        )
+        .header(Row::new(vec![
+            Span::styled("Key", Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled("Value", Style::default().add_modifier(Modifier::BOLD)),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Keywhisker"));
+        f.render_widget(table, bottom[1]);
+    }
+
+    fn score_chart(&self) -> Chart<'_> {
+        let (x_min, x_max) = match (self.best.first(), self.best.last()) {
+            (Some((a, _)), Some((b, _))) => (*a, b.max(a + 1.0)),
+            _ => (0.0, 1.0),
+        };
+        let scores = self.current.iter().chain(&self.best).map(|(_, y)| *y);
+        let y_min = scores.clone().fold(f64::INFINITY, f64::min);
+        let y_max = scores.fold(f64::NEG_INFINITY, f64::max);
+        let (y_min, y_max) = match y_min.is_finite() {
+            true => (y_min, y_max.max(y_min + f64::EPSILON)),
+            false => (0.0, 1.0),
+        };
+        let datasets = vec![
+            Dataset::default()
+                .name("current")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::DarkGray))
+                .data(&self.current),
+            Dataset::default()
+                .name("best")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Yellow))
+                .data(&self.best),
+        ];
+        Chart::new(datasets)
+            .block(Block::default().borders(Borders::ALL).title("Score"))
+            .x_axis(
+                Axis::default()
+                    .bounds([x_min, x_max])
+                    .labels([format!("{x_min:.0}"), format!("{x_max:.0}")]),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([y_min, y_max])
+                    .labels([format!("{y_min:.4}"), format!("{y_max:.4}")]),
+            )
+    }
+
+    fn draw_sparklines(&self, f: &mut Frame, area: ratatui::layout::Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Metrics (best)");
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        let rows = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(2); self.metrics.len()])
+            .split(inner);
+        for (((name, _, _), history), row) in self
+            .metrics
+            .iter()
+            .zip(&self.metric_history)
+            .zip(rows.iter())
+        {
+            let latest = history.last().map_or(0.0, |v| *v as f64 / 100.0);
+            let columns = ratatui::layout::Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(20), Constraint::Min(0)])
+                .split(*row);
+            f.render_widget(Paragraph::new(format!("{name} {latest:.2}%")), columns[0]);
+            // Only the most recent points that fit are drawn
+            let width = columns[1].width as usize;
+            let recent = &history[history.len().saturating_sub(width)..];
+            f.render_widget(
+                Sparkline::default()
+                    .data(recent)
+                    .style(Style::default().fg(Color::Cyan)),
+                columns[1],
+            );
+        }
+    }
+}
+
+/// Evaluations per second, and the spread of time between them, since the last report.
+struct EvaluationRate {
+    since: Instant,
+    last_call: Instant,
+    calls: u64,
+    min_interval: Duration,
+    max_interval: Duration,
+}
+
+impl EvaluationRate {
+    fn new() -> Self {
+        EvaluationRate {
+            since: Instant::now(),
+            last_call: Instant::now(),
+            calls: 0,
+            min_interval: Duration::from_secs(u64::MAX),
+            max_interval: Duration::from_secs(0),
+        }
+    }
+
+    fn tick(&mut self) -> Instant {
+        let now = Instant::now();
+        let interval = now.duration_since(self.last_call);
+        self.min_interval = self.min_interval.min(interval);
+        self.max_interval = self.max_interval.max(interval);
+        self.last_call = now;
+        self.calls += 1;
+        now
+    }
+
+    /// Write the rate into the dashboard and start measuring afresh.
+    fn report(&mut self, dashboard: &mut Dashboard) {
+        let rate = self.calls as f64 / self.since.elapsed().as_secs_f64();
+        dashboard.set_stat("Evaluation Rate", format!("{:.5} swaps/second", rate));
+        dashboard.set_stat(
+            "Min/Max Interval",
+            format!("{:?} \t/ {:?}", self.min_interval, self.max_interval),
+        );
+        *self = EvaluationRate::new();
+    }
+}
+
+/// The full-screen dashboard, which also takes keyboard controls when stdout is a terminal.
+pub struct TuiReporter<'a> {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    dashboard: Dashboard<'a>,
+    rate: EvaluationRate,
+    interactive: bool,
+    last_draw: Instant,
+    last_poll: Instant,
+}
+
+impl<'a> TuiReporter<'a> {
+    pub fn new(dashboard: Dashboard<'a>) -> Self {
+        let backend = CrosstermBackend::new(std::io::stdout());
+        // Keys are only read in raw mode
+        let interactive = atty::is(atty::Stream::Stdout) && enable_raw_mode().is_ok();
+        TuiReporter {
+            terminal: Terminal::new(backend).unwrap(),
+            dashboard,
+            rate: EvaluationRate::new(),
+            interactive,
+            last_draw: Instant::now(),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl Reporter for TuiReporter<'_> {
+    fn on_evaluation(&mut self) {
+        let now = self.rate.tick();
+        if self.interactive && now.duration_since(self.last_poll) >= KEY_POLL_INTERVAL {
+            self.last_poll = now;
+            self.dashboard.handle_keys(&mut self.terminal);
+        }
+        if now.duration_since(self.last_draw) >= REDRAW_INTERVAL {
+            self.last_draw = now;
+            self.rate.report(&mut self.dashboard);
+            self.dashboard.render(&mut self.terminal);
+        }
+    }
+
+    fn on_iteration(&mut self, snapshot: &Snapshot) {
+        self.dashboard.record(snapshot);
+        self.on_evaluation();
+    }
+
+    fn on_temperature_change(&mut self, temperature: f32, cooling_interval: f32) {
+        self.dashboard.set_stat("Temp", temperature.to_string());
+        self.dashboard
+            .set_stat("Cooling Interval", cooling_interval.to_string());
+    }
+}
+
+impl Drop for TuiReporter<'_> {
+    fn drop(&mut self) {
+        if self.interactive {
+            let _ = disable_raw_mode();
+        }
+    }
+}
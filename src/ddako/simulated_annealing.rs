@@ -1,7 +1,7 @@
 // Copyright (c) 2025 antlers <antlers@illucid.net>
 //
 // Adapted from https://github.com/sekaha/DDAKO
-// 
+//
 // This program is free software: you can redistribute it and/or modify
 // it under the terms of the GNU Affero General Public License as published by
 // the Free Software Foundation, either version 3 of the License, or
@@ -17,17 +17,82 @@
 
 use core::clone::Clone;
 use rand::prelude::*;
+use rand::rngs::StdRng;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::f32::consts::E;
-
-use crate::analysis::Evaluator;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use crate::config::Annealing;
+use crate::evaluation::Evaluator;
+use crate::interrupt;
+use crate::optimization::GenerationResult;
+use crate::reporter::Reporter;
+use crate::sampling::SwapSampler;
 use keycat::analysis::Analyzer;
 use keycat::{Layout, Swap};
 
-use indexmap::IndexMap;
+use tracing::{debug, info, warn};
+
+#[cfg(not(target_arch = "wasm32"))]
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where an optimization stands, handed to the reporter once per iteration.
+pub struct Snapshot<'s> {
+    pub iteration: u32,
+    pub since_improvement: u32,
+    pub current: f32,
+    pub current_layout: &'s [usize],
+    pub best: f32,
+    pub best_layout: &'s [usize],
+    pub best_stats: &'s [f32],
+    pub stays: usize,
+    pub stopping_point: usize,
+    pub temperature: f32,
+    pub cooling_interval: f32,
+    /// Share of recent candidate swaps that were accepted
+    pub acceptance_rate: f32,
+}
+
+/// A new best layout, handed to the reporter as soon as it's found.
+pub struct Improvement<'s> {
+    pub iteration: u32,
+    pub score: f32,
+    pub layout: &'s [usize],
+    pub stats: &'s [f32],
+}
+
+/// Everything `optimize` needs to pick up where it left off.
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    layout: Vec<usize>,
+    fitness: f32,
+    temp: f32,
+    cooling_interval: f32,
+    iteration: u32,
+    stays: usize,
+    last_adjustment: u32,
+    last_improvement_iteration: u32,
+    recent_acceptances: Vec<bool>,
+    recent_acceptance_rates: Vec<f32>,
+    best_layout: Vec<usize>,
+    best_fitness: f32,
+    best_stats: Vec<f32>,
+    // The RNG is reseeded from this at every checkpoint, so it captures its state
+    seed: u64,
+}
+
+impl State {
+    pub fn layout_len(&self) -> usize {
+        self.layout.len()
+    }
+}
 
 pub struct SimulatedAnnealing<'a> {
     possible_swaps: Vec<Swap>,
+    sampler: SwapSampler,
     layout: Layout,
     analyzer: &'a Analyzer,
     stats: Vec<f32>,
@@ -41,64 +106,72 @@ pub struct SimulatedAnnealing<'a> {
     fitness: f32,
     temp: Option<f32>,
     stopping_point: Option<usize>,
-    rate_tracker: &'a mut dyn FnMut(&mut IndexMap<&'a str, String>),
-    rt_stats: IndexMap<&'a str, String>,
+    reporter: &'a mut dyn Reporter,
+    resume: Option<State>,
+    seed: Option<u64>,
+    checkpoint: Option<&'a mut dyn FnMut(State) -> anyhow::Result<()>>,
 }
 
 impl<'a> SimulatedAnnealing<'a> {
     pub fn new(
-        possible_swaps: &Vec<Swap>,
+        possible_swaps: &[Swap],
         layout: &Layout,
         analyzer: &'a Analyzer,
         evaluator: &'a Evaluator,
-        cooling_rate: f32,
-        cooling_interval: f32,
-        cooling_interval_min: f32,
-        cooling_interval_max: f32,
-        max_iterations: Option<u32>,
-        rate_tracker: &'a mut dyn FnMut(&mut IndexMap<&'a str, String>),
+        annealing: &Annealing,
+        swap_bias: f32,
+        reporter: &'a mut dyn Reporter,
     ) -> Self {
         let stats = analyzer.calc_stats(layout);
         let initial_fitness = evaluator.eval(&stats);
         let len = stats.len();
 
-        let empty_str = String::from("");
-        let rt_stats: IndexMap<&str, String> = IndexMap::from([
-            ("Initial Temp Stats",  empty_str.clone()),
-            ("Evaluation Rate",     empty_str.clone()),
-            ("Min/Max Interval",    empty_str.clone()),
-            // -----
-            ("Iteration",           empty_str.clone()),
-            ("Stays",               empty_str.clone()),
-            ("Temp",                empty_str.clone()),
-            ("Cooling Interval",    empty_str.clone()),
-            ("Acceptance Rate",     empty_str.clone()),
-            ("Current",             empty_str.clone()),
-            ("Best",                empty_str.clone()),
-        ]);
-
         SimulatedAnnealing {
             possible_swaps: possible_swaps.to_vec(),
+            sampler: SwapSampler::new(possible_swaps, &analyzer.corpus, swap_bias),
             layout: layout.clone(),
             analyzer,
             stats,
             diff: vec![0.0; len],
             evaluator,
-            cooling_rate,
-            cooling_interval,
-            cooling_interval_min,
-            cooling_interval_max,
-            max_iterations,
+            cooling_rate: annealing.cooling_rate,
+            cooling_interval: annealing.cooling_interval,
+            cooling_interval_min: annealing.cooling_interval_min,
+            cooling_interval_max: annealing.cooling_interval_max,
+            max_iterations: annealing.max_iterations,
             fitness: initial_fitness,
             temp: None,
             stopping_point: None,
-            rate_tracker,
-            rt_stats,
+            reporter,
+            resume: None,
+            seed: None,
+            checkpoint: None,
         }
     }
 
+    /// Continue from a checkpointed state instead of starting fresh.
+    pub fn with_resume(mut self, state: State) -> Self {
+        self.resume = Some(state);
+        self
+    }
+
+    /// Seed the run's randomness instead of drawing a random seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Hand the optimizer's state to `save` every minute, and when interrupted.
+    pub fn with_checkpoints(
+        mut self,
+        save: &'a mut dyn FnMut(State) -> anyhow::Result<()>,
+    ) -> Self {
+        self.checkpoint = Some(save);
+        self
+    }
+
     fn _evaluate_swap(&mut self, swap: &Swap) -> f32 {
-        self.diff.iter_mut().for_each(|x| *x = 0.0);
+        self.diff.fill(0.0);
         self.analyzer.swap_diff(&mut self.diff, &self.layout, swap);
 
         let score = self.evaluator.eval(&self.diff);
@@ -109,7 +182,7 @@ impl<'a> SimulatedAnnealing<'a> {
     fn evaluate_swap_slowly(&mut self, swap: &Swap) -> f32 {
         // but correctly :c
         self.layout.swap(swap);
-        self.diff.iter_mut().for_each(|x| *x = 0.0);
+        self.diff.fill(0.0);
         self.analyzer.recalc_stats(&mut self.diff, &self.layout);
 
         let score = self.evaluator.eval(&self.diff);
@@ -126,25 +199,28 @@ impl<'a> SimulatedAnnealing<'a> {
     fn get_initial_temperature(&mut self, acceptance_ratio: f32, epsilon: f32) -> f32 {
         let mut tn = self.fitness;
         let mut acceptance_probability = 0.0;
+        let mut energies = Vec::with_capacity(self.possible_swaps.len());
 
-        while (acceptance_probability - acceptance_ratio).abs() > epsilon {
-            let mut energies = Vec::new();
+        while (acceptance_probability - acceptance_ratio).abs() > epsilon
+            && !interrupt::interrupted()
+        {
+            energies.clear();
 
-            for new_swap in &self.possible_swaps.clone() {
-                let new_fitness = self.evaluate_swap_slowly(new_swap);
+            for i in 0..self.possible_swaps.len() {
+                let new_swap = self.possible_swaps[i].clone();
+                let new_fitness = self.evaluate_swap_slowly(&new_swap);
                 let delta = new_fitness - self.fitness;
 
                 if delta > 0.001 {
                     energies.push(new_fitness);
                 }
 
-                (self.rate_tracker)(&mut self.rt_stats);
+                self.reporter.on_evaluation();
             }
 
             let sum_exp: f32 = energies.iter().map(|e| E.powf(-*e / tn)).sum();
 
-            acceptance_probability =
-                sum_exp / (energies.len() as f32 * E.powf(-self.fitness / tn));
+            acceptance_probability = sum_exp / (energies.len() as f32 * E.powf(-self.fitness / tn));
 
             if !energies.is_empty() {
                 tn *= acceptance_probability.ln() / acceptance_ratio.ln();
@@ -152,18 +228,15 @@ impl<'a> SimulatedAnnealing<'a> {
                 tn *= 2.0;
             }
 
-            for (label, stat) in &mut self.rt_stats {
-                match *label {
-                    "Initial Temp Stats" => *stat =
-                        format!("sum: {}, acc_prob: {}, tn: {}, test: {}",
-                                sum_exp,
-                                acceptance_probability,
-                                tn,
-                                (acceptance_probability - acceptance_ratio).abs()),
-                    _ => ()
-                };
-            };
-            (self.rate_tracker)(&mut self.rt_stats);
+            debug!(
+                sum_exp,
+                acceptance_probability,
+                tn,
+                test = (acceptance_probability - acceptance_ratio).abs(),
+                "searching for initial temperature"
+            );
+            self.reporter
+                .on_temperature_change(tn, self.cooling_interval);
         }
 
         tn
@@ -175,21 +248,36 @@ impl<'a> SimulatedAnnealing<'a> {
         ((possible_swaps * (possible_swaps.ln() + euler_mascheroni) + 0.5).ceil()) as usize
     }
 
-    pub fn optimize(
-        &mut self,
-        layout_size: usize,
-    ) -> (u32, f32, Vec<f32>, Layout) {
-        let mut rng = rand::thread_rng();
+    pub fn optimize(&mut self, layout_size: usize) -> GenerationResult {
+        let resume = self.resume.take();
+        if let Some(state) = &resume {
+            self.layout = Layout(state.layout.clone());
+            self.stats = self.analyzer.calc_stats(&self.layout);
+            self.fitness = state.fitness;
+            self.temp = Some(state.temp);
+            self.cooling_interval = state.cooling_interval;
+            info!(iteration = state.iteration, "resuming from checkpoint");
+        }
+        let mut seed = match &resume {
+            Some(state) => state.seed,
+            None => self.seed.unwrap_or_else(random),
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
 
         if self.temp.is_none() {
             self.temp = Some(self.get_initial_temperature(0.8, 0.01));
+            debug!(temp = self.temp, "initial temperature");
         }
         if self.stopping_point.is_none() {
             self.stopping_point = Some(self.get_stopping_point(layout_size));
         }
 
+        self.sampler
+            .update(&self.layout, self.analyzer, self.evaluator);
+
         let mut best_layout = self.layout.0.clone();
         let mut best_fitness = self.fitness;
+        let mut best_stats = self.stats.clone();
         let mut stays = 0;
         let mut iteration: u32 = 0;
         let mut last_adjustment = 0;
@@ -200,7 +288,58 @@ impl<'a> SimulatedAnnealing<'a> {
 
         let mut last_improvement_iteration = 0;
 
+        if let Some(state) = resume {
+            best_layout = state.best_layout;
+            best_fitness = state.best_fitness;
+            best_stats = state.best_stats;
+            stays = state.stays;
+            iteration = state.iteration;
+            last_adjustment = state.last_adjustment;
+            recent_acceptances = state.recent_acceptances;
+            recent_acceptance_rates = state.recent_acceptance_rates;
+            last_improvement_iteration = state.last_improvement_iteration;
+        }
+        // The clock is only read when checkpointing, and never on wasm, where
+        // `Instant` panics and there is nowhere to save checkpoints anyway
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut last_checkpoint = self.checkpoint.is_some().then(Instant::now);
+
         while stays < self.stopping_point.unwrap() {
+            let interrupted = interrupt::interrupted();
+            #[cfg(not(target_arch = "wasm32"))]
+            let due = last_checkpoint.is_some_and(|t| t.elapsed() >= CHECKPOINT_INTERVAL);
+            #[cfg(target_arch = "wasm32")]
+            let due = false;
+            if let Some(save) = self.checkpoint.as_mut().filter(|_| interrupted || due) {
+                seed = rng.gen();
+                rng = StdRng::seed_from_u64(seed);
+                let state = State {
+                    layout: self.layout.0.clone(),
+                    fitness: self.fitness,
+                    temp: self.temp.unwrap(),
+                    cooling_interval: self.cooling_interval,
+                    iteration,
+                    stays,
+                    last_adjustment,
+                    last_improvement_iteration,
+                    recent_acceptances: recent_acceptances.clone(),
+                    recent_acceptance_rates: recent_acceptance_rates.clone(),
+                    best_layout: best_layout.clone(),
+                    best_fitness,
+                    best_stats: best_stats.clone(),
+                    seed,
+                };
+                if let Err(e) = save(state) {
+                    warn!("couldn't save checkpoint: {e:#}");
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    last_checkpoint = Some(Instant::now());
+                }
+            }
+            if interrupted {
+                break;
+            }
             if let Some(max_iter) = self.max_iterations {
                 if iteration >= max_iter {
                     break;
@@ -208,13 +347,13 @@ impl<'a> SimulatedAnnealing<'a> {
             }
 
             for _ in 0..layout_size {
-                (self.rate_tracker)(&mut self.rt_stats);
-                let new_swap = self.possible_swaps.choose(&mut rng).unwrap().clone();
+                self.reporter.on_evaluation();
+                let new_swap = self.sampler.choose(&self.possible_swaps, &mut rng).clone();
                 let new_fitness = self.evaluate_swap_slowly(&new_swap);
                 let delta = new_fitness - self.fitness;
 
                 let mut accepted = false;
-                if delta < 0.0 {
+                if self.evaluator.compare(&self.diff, &self.stats) == Ordering::Less {
                     recent_acceptances.push(true);
                     accepted = true;
                     stays = 0;
@@ -232,17 +371,27 @@ impl<'a> SimulatedAnnealing<'a> {
 
                 if accepted {
                     self.layout.swap(&new_swap);
-                    self.stats.iter_mut().for_each(|x| *x = 0.0);
+                    self.sampler
+                        .update(&self.layout, self.analyzer, self.evaluator);
+                    self.stats.fill(0.0);
                     self.analyzer.recalc_stats(&mut self.stats, &self.layout);
 
                     // assert(new_fitness > 0.001)
 
                     self.fitness = new_fitness;
 
-                    if self.fitness < best_fitness {
+                    if self.evaluator.compare(&self.stats, &best_stats) == Ordering::Less {
+                        info!(iteration, fitness = self.fitness, "new best");
                         last_improvement_iteration = iteration;
-                        best_layout = self.layout.0.clone();
+                        best_layout.clone_from(&self.layout.0);
                         best_fitness = self.fitness;
+                        best_stats.clone_from(&self.stats);
+                        self.reporter.on_improvement(&Improvement {
+                            iteration,
+                            score: best_fitness,
+                            layout: &best_layout,
+                            stats: &best_stats,
+                        });
                     }
                 }
             }
@@ -255,37 +404,34 @@ impl<'a> SimulatedAnnealing<'a> {
             }
 
             let time_since_improvement = iteration - last_improvement_iteration;
-            let format_layout = |l: &Vec<usize>, f: f32| {
-                format!("{}\t({})",
-                        f,
-                        String::from_iter(
-                          l.iter()
-                              .map(|c| self.analyzer.corpus.uncorpus_unigram(*c))
-                              .map(|c| match c {
-                                  '\0' => '�',
-                                  c => c,
-                              })))
-            };
-
-            // Rate tracker
-            for (label, stat) in &mut self.rt_stats {
-                match *label {
-                    "Iteration"        => *stat = format!("{} ({} since improvement)", iteration, time_since_improvement),
-                    "Stays"            => *stat = format!("{}/{}", stays, self.stopping_point.unwrap()),
-                    "Temp"             => *stat = format!("{}", self.temp.unwrap()),
-                    "Cooling Interval" => *stat = format!("{}", self.cooling_interval),
-                    "Acceptance Rate"  => *stat = format!("{}", acceptance_rate),
-                    "Current"          => *stat = format_layout(&self.layout.0, self.fitness),
-                    "Best"             => *stat = format_layout(&best_layout, best_fitness),
-                    _ => {}
-                };
-            };
-            (self.rate_tracker)(&mut self.rt_stats);
+            self.reporter.on_iteration(&Snapshot {
+                iteration,
+                since_improvement: time_since_improvement,
+                current: self.fitness,
+                current_layout: &self.layout.0,
+                best: best_fitness,
+                best_layout: &best_layout,
+                best_stats: &best_stats,
+                stays,
+                stopping_point: self.stopping_point.unwrap(),
+                temperature: self.temp.unwrap(),
+                cooling_interval: self.cooling_interval,
+                acceptance_rate,
+            });
 
             // Cooling & Interval adjustment
-            if iteration > 0 && (iteration - last_adjustment) % self.cooling_interval as u32 == 0 {
+            if iteration > 0
+                && (iteration - last_adjustment).is_multiple_of(self.cooling_interval as u32)
+            {
                 last_adjustment = iteration;
                 self.temp = Some(self.temp.unwrap() * self.cooling_rate);
+                debug!(
+                    iteration,
+                    temp = self.temp,
+                    acceptance_rate,
+                    cooling_interval = self.cooling_interval,
+                    "cooled"
+                );
 
                 if acceptance_rate > 0.1 || self.cooling_interval > time_since_improvement as f32 {
                     self.cooling_interval =
@@ -294,12 +440,17 @@ impl<'a> SimulatedAnnealing<'a> {
                     self.cooling_interval =
                         (self.cooling_interval * 0.9).max(self.cooling_interval_min);
                 }
+                self.reporter
+                    .on_temperature_change(self.temp.unwrap(), self.cooling_interval);
             }
             iteration += 1;
         }
 
-        let layout = Layout(best_layout);
-        self.stats = self.analyzer.calc_stats(&layout);
-        (iteration, best_fitness, self.stats.clone(), layout)
+        GenerationResult::new(
+            iteration,
+            Layout(best_layout),
+            self.analyzer,
+            self.evaluator,
+        )
     }
 }
@@ -0,0 +1,53 @@
+use crate::analysis::add_stroke_amount;
+
+use anyhow::{ensure, Context, Result};
+use keycat::analysis::Nstroke;
+use keycat::NgramType;
+use keymeow::{Metric, MetricData};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Per-key effort, one row per keyboard row in the keyboard's key order, e.g.
+///
+/// ```toml
+/// effort = [
+///     [3.0, 2.4, 2.0, 2.2, 3.2, 3.2, 2.2, 2.0, 2.4, 3.0],
+///     [1.6, 1.3, 1.1, 1.0, 2.9, 2.9, 1.0, 1.1, 1.3, 1.6],
+///     [3.2, 2.6, 2.3, 1.6, 3.0, 3.0, 1.6, 2.3, 2.6, 3.2],
+/// ]
+/// ```
+#[derive(Deserialize)]
+pub struct EffortGrid {
+    pub effort: Vec<Vec<f32>>,
+}
+
+impl EffortGrid {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid effort grid {}", path.display()))
+    }
+}
+
+/// Add an "Effort" monogram metric scoring each key press by the grid, so it
+/// shows up in stats and can be weighted like any other metric.
+pub fn add_metric(metric_data: &mut MetricData, grid: &EffortGrid) -> Result<()> {
+    let rows: Vec<usize> = metric_data.keyboard.keys.map.iter().map(Vec::len).collect();
+    let grid_rows: Vec<usize> = grid.effort.iter().map(Vec::len).collect();
+    ensure!(
+        rows == grid_rows,
+        "effort grid rows {grid_rows:?} don't match the keyboard's rows {rows:?}"
+    );
+
+    let metric = metric_data.metrics.len();
+    metric_data.metrics.push(Metric {
+        name: "Effort".to_string(),
+        short: "effort".to_string(),
+        ngram_type: NgramType::Monogram,
+    });
+    for (pos, effort) in grid.effort.iter().flatten().enumerate() {
+        add_stroke_amount(metric_data, Nstroke::Monostroke(pos), metric, *effort);
+    }
+    Ok(())
+}
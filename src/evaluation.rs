@@ -0,0 +1,306 @@
+#[cfg(feature = "simd")]
+use crate::simd;
+
+use keycat::{NgramType, Totals};
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+/// A layout that generated ones are compared against as they improve.
+pub struct Reference {
+    name: String,
+    metrics: Vec<(String, usize, NgramType)>,
+    totals: Totals,
+    stats: Vec<f32>,
+    score: f32,
+}
+
+impl Reference {
+    /// `metrics` are the named metrics to show deltas for, and `stats` the
+    /// reference layout's raw stats.
+    pub fn new(
+        name: String,
+        metrics: Vec<(String, usize, NgramType)>,
+        totals: Totals,
+        stats: Vec<f32>,
+        evaluator: &Evaluator,
+    ) -> Self {
+        Reference {
+            name,
+            metrics,
+            totals,
+            score: evaluator.eval(&stats),
+            stats,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Score and per-metric percentage deltas of `stats` relative to the reference layout.
+    pub fn deltas(&self, stats: &[f32], score: f32) -> String {
+        let mut s = format!("score {:+.3}", score - self.score);
+        for (name, m, ngram_type) in &self.metrics {
+            let _ = write!(
+                s,
+                "  {name} {:+.3}%",
+                self.totals
+                    .percentage(stats[*m] - self.stats[*m], *ngram_type)
+            );
+        }
+        s
+    }
+}
+
+/// Scores layouts from their stats as a weighted sum of metrics, optionally
+/// compared tier by tier and with soft constraints.
+pub struct Evaluator {
+    metrics: Vec<(usize, f32)>,
+    weight_sum: f32,
+    tiers: Vec<usize>,
+    tier_count: usize,
+    tolerance: f32,
+    penalties: Vec<Penalty>,
+    // Where each keyboard keeps its copy of the weighted metrics, and its weight
+    keyboards: Vec<(Vec<usize>, f32)>,
+    worst_case: bool,
+    // Each metric's weight at its index in the stats, for a vectorized sum
+    #[cfg(feature = "simd")]
+    dense: Vec<f32>,
+}
+
+/// A soft constraint: every unit of the metric above `threshold` costs `weight`.
+struct Penalty {
+    metric: usize,
+    threshold: f32,
+    weight: f32,
+}
+
+impl Penalty {
+    fn cost(&self, stat: f32) -> f32 {
+        self.weight * (stat - self.threshold).max(0.0)
+    }
+}
+
+impl From<Vec<(usize, i16)>> for Evaluator {
+    fn from(metrics: Vec<(usize, i16)>) -> Self {
+        let sum: f32 = metrics.iter().map(|(_, x)| *x as f32).sum();
+        #[cfg(feature = "simd")]
+        let dense = {
+            let len = metrics.iter().map(|(m, _)| m + 1).max().unwrap_or(0);
+            let mut dense = vec![0.0; len];
+            for (m, x) in &metrics {
+                dense[*m] += *x as f32 / sum;
+            }
+            dense
+        };
+        Self {
+            tiers: vec![0; metrics.len()],
+            tier_count: 1,
+            tolerance: 0.0,
+            penalties: vec![],
+            keyboards: vec![],
+            worst_case: false,
+            weight_sum: sum,
+            metrics: metrics.iter().map(|(m, x)| (*m, *x as f32 / sum)).collect(),
+            #[cfg(feature = "simd")]
+            dense,
+        }
+    }
+}
+
+// Differences smaller than this are treated as float noise
+const EPSILON: f32 = 0.000001;
+
+impl Evaluator {
+    /// Splits the metrics into lexicographic tiers, given each metric's tier in order.
+    /// A lower tier only decides between layouts whose higher tiers are within
+    /// `tolerance` (relative) of each other.
+    pub fn with_tiers(mut self, tiers: Vec<usize>, tolerance: f32) -> Self {
+        self.tier_count = tiers.iter().max().map_or(1, |t| t + 1);
+        self.tiers = tiers;
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Adds a soft constraint on a raw metric value, weighted like the metric weights.
+    /// Penalties count towards the first tier.
+    pub fn with_penalty(mut self, metric: usize, threshold: f32, weight: f32) -> Self {
+        self.penalties.push(Penalty {
+            metric,
+            threshold,
+            weight: weight / self.weight_sum,
+        });
+        self
+    }
+
+    /// Scores the weighted metrics on several keyboards at once, given where
+    /// each keyboard's copies of them are (in weight order) and its weight.
+    /// Keyboards are averaged by weight, or with `worst_case` only the worst counts.
+    pub fn with_keyboards(mut self, keyboards: Vec<(Vec<usize>, f32)>, worst_case: bool) -> Self {
+        self.keyboards = keyboards;
+        self.worst_case = worst_case;
+        self
+    }
+
+    pub fn eval(&self, stats: &[f32]) -> f32 {
+        self.linear_score(stats, None, None) + self.penalty(stats)
+    }
+
+    /// The weighted sum of the metrics in `tier`, or all of them, reading each
+    /// from a keyboard's `copy` of it if given.
+    fn weighted_sum(&self, stats: &[f32], copy: Option<&[usize]>, tier: Option<usize>) -> f32 {
+        #[cfg(feature = "simd")]
+        if copy.is_none() && (tier.is_none() || self.tier_count == 1) {
+            return simd::dot(&self.dense, stats);
+        }
+        self.metrics
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| tier.is_none_or(|t| self.tiers[*i] == t))
+            .map(|(i, (m, x))| x * stats[copy.map_or(*m, |c| c[i])])
+            .sum()
+    }
+
+    /// The weighted sum of `stats`, plus `diff` if given, combined across keyboards.
+    fn linear_score(&self, stats: &[f32], diff: Option<&[f32]>, tier: Option<usize>) -> f32 {
+        let sum = |copy| {
+            self.weighted_sum(stats, copy, tier)
+                + diff.map_or(0.0, |d| self.weighted_sum(d, copy, tier))
+        };
+        if self.keyboards.is_empty() {
+            return sum(None);
+        }
+        let sums = self.keyboards.iter().map(|(copy, w)| (sum(Some(copy)), *w));
+        match self.worst_case {
+            true => sums.map(|(s, _)| s).fold(f32::NEG_INFINITY, f32::max),
+            false => {
+                let total: f32 = self.keyboards.iter().map(|(_, w)| w).sum();
+                sums.map(|(s, w)| s * w).sum::<f32>() / total
+            }
+        }
+    }
+
+    fn penalty(&self, stats: &[f32]) -> f32 {
+        self.penalties.iter().map(|p| p.cost(stats[p.metric])).sum()
+    }
+
+    fn penalty_diff(&self, stats: &[f32], diff: &[f32]) -> f32 {
+        self.penalties
+            .iter()
+            .map(|p| p.cost(stats[p.metric] + diff[p.metric]) - p.cost(stats[p.metric]))
+            .sum()
+    }
+
+    /// The weighted metrics, in the order they were given.
+    pub fn metrics(&self) -> impl Iterator<Item = usize> + '_ {
+        self.metrics.iter().map(|(m, _)| *m)
+    }
+
+    /// Each weighted metric with its normalized weight, in the order they were given.
+    pub fn weights(&self) -> &[(usize, f32)] {
+        &self.metrics
+    }
+
+    /// Each metric's weighted term of `eval`, in the order the metrics were given.
+    /// Across keyboards, that's the weighted average of the terms, or the terms
+    /// on the worst keyboard.
+    pub fn contributions(&self, stats: &[f32]) -> Vec<f32> {
+        let terms = |copy: Option<&[usize]>| -> Vec<f32> {
+            self.metrics
+                .iter()
+                .enumerate()
+                .map(|(i, (m, x))| x * stats[copy.map_or(*m, |c| c[i])])
+                .collect()
+        };
+        match (self.keyboards.is_empty(), self.worst_case) {
+            (true, _) => terms(None),
+            (false, true) => {
+                let worst = self.keyboards.iter().max_by(|a, b| {
+                    let sum = |copy: &[usize]| self.weighted_sum(stats, Some(copy), None);
+                    sum(&a.0).total_cmp(&sum(&b.0))
+                });
+                terms(worst.map(|(copy, _)| copy.as_slice()))
+            }
+            (false, false) => {
+                let total: f32 = self.keyboards.iter().map(|(_, w)| w).sum();
+                let mut average = vec![0.0; self.metrics.len()];
+                for (copy, w) in &self.keyboards {
+                    for (a, term) in average.iter_mut().zip(terms(Some(copy))) {
+                        *a += term * w / total;
+                    }
+                }
+                average
+            }
+        }
+    }
+
+    fn linear_tier_score(&self, stats: &[f32], tier: usize) -> f32 {
+        self.linear_score(stats, None, Some(tier))
+    }
+
+    /// How much `diff` changes the weighted sum of the metrics in `tier`. Only
+    /// a single keyboard's score is linear enough to take from `diff` alone.
+    fn linear_tier_diff(&self, stats: &[f32], diff: &[f32], tier: usize) -> f32 {
+        match self.keyboards.is_empty() {
+            true => self.linear_tier_score(diff, tier),
+            false => {
+                self.linear_score(stats, Some(diff), Some(tier))
+                    - self.linear_tier_score(stats, tier)
+            }
+        }
+    }
+
+    fn tier_score(&self, stats: &[f32], tier: usize) -> f32 {
+        match tier {
+            0 => self.linear_tier_score(stats, tier) + self.penalty(stats),
+            _ => self.linear_tier_score(stats, tier),
+        }
+    }
+
+    fn tier_diff(&self, stats: &[f32], diff: &[f32], tier: usize) -> f32 {
+        match tier {
+            0 => self.linear_tier_diff(stats, diff, tier) + self.penalty_diff(stats, diff),
+            _ => self.linear_tier_diff(stats, diff, tier),
+        }
+    }
+
+    /// Orders two full stat vectors tier by tier.
+    pub fn compare(&self, a: &[f32], b: &[f32]) -> Ordering {
+        for tier in 0..self.tier_count {
+            let (x, y) = (self.tier_score(a, tier), self.tier_score(b, tier));
+            let tolerance = self.tolerance * x.abs().max(y.abs());
+            if (x - y).abs() > tolerance.max(EPSILON) {
+                return x.total_cmp(&y);
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Orders two swap diffs tier by tier, with tolerances relative to the current `stats`.
+    pub fn compare_diffs(&self, stats: &[f32], a: &[f32], b: &[f32]) -> Ordering {
+        for tier in 0..self.tier_count {
+            let (x, y) = (
+                self.tier_diff(stats, a, tier),
+                self.tier_diff(stats, b, tier),
+            );
+            let tolerance = self.tolerance * self.tier_score(stats, tier).abs();
+            if (x - y).abs() > tolerance.max(EPSILON) {
+                return x.total_cmp(&y);
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Whether applying `diff` to the current `stats` is a (tiered) improvement.
+    pub fn improves(&self, stats: &[f32], diff: &[f32]) -> bool {
+        for tier in 0..self.tier_count {
+            let d = self.tier_diff(stats, diff, tier);
+            let tolerance = self.tolerance * self.tier_score(stats, tier).abs();
+            if d.abs() > tolerance.max(EPSILON) {
+                return d < 0.0;
+            }
+        }
+        false
+    }
+}
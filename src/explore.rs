@@ -0,0 +1,262 @@
+use crate::analysis::layout_matrices;
+use crate::fingers::finger_color;
+use crate::heatmap::key_labels;
+use crate::keyboard::{key_positions, key_rows};
+
+use anyhow::{Context, Result};
+use keycat::{Layout, Swap};
+use keymeow::{Keyboard, LayoutData, MetricContext};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Direction},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use std::fs::File;
+use std::path::PathBuf;
+
+/// The style of the key at position `i`: black on its finger's color.
+pub fn finger_style(keyboard: &Keyboard, i: usize) -> Style {
+    match keyboard.fingers.map.iter().flatten().nth(i) {
+        Some(finger) => {
+            let (r, g, b) = finger_color(finger);
+            Style::default().bg(Color::Rgb(r, g, b)).fg(Color::Black)
+        }
+        None => Style::default(),
+    }
+}
+
+/// The keyboard drawn row by row with each key's label, styled by position.
+pub fn keyboard_lines<'l>(
+    keyboard: &Keyboard,
+    labels: &[String],
+    style: impl Fn(usize) -> Style,
+) -> Vec<Line<'l>> {
+    key_rows(keyboard, labels)
+        .into_iter()
+        .map(|row| {
+            let spans: Vec<Span> = row
+                .into_iter()
+                .flat_map(|(gap, i, text)| {
+                    [Span::raw(" ".repeat(gap)), Span::styled(text, style(i))]
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+struct Explorer {
+    ctx: MetricContext,
+    name: String,
+    layout: Layout,
+    stats: Vec<f32>,
+    diff: Vec<f32>,
+    positions: Vec<(f32, f32)>,
+    cursor: usize,
+    selected: Option<usize>,
+    history: Vec<Swap>,
+    message: String,
+}
+
+impl Explorer {
+    fn pending_swap(&self) -> Option<Swap> {
+        self.selected
+            .filter(|s| *s != self.cursor)
+            .map(|s| Swap::new(s, self.cursor))
+    }
+
+    fn update_diff(&mut self) {
+        self.diff.iter_mut().for_each(|x| *x = 0.0);
+        if let Some(swap) = self.pending_swap() {
+            self.ctx
+                .analyzer
+                .swap_diff(&mut self.diff, &self.layout, &swap);
+        }
+    }
+
+    fn apply(&mut self, swap: Swap) {
+        self.layout.swap(&swap);
+        self.stats = self.ctx.analyzer.calc_stats(&self.layout);
+    }
+
+    /// Move the cursor to the nearest key in the direction of (dx, dy).
+    fn step(&mut self, dx: f32, dy: f32) {
+        let (x, y) = self.positions[self.cursor];
+        let next = self
+            .positions
+            .iter()
+            .enumerate()
+            .filter(|(_, (kx, ky))| (kx - x) * dx + (ky - y) * dy > 0.1)
+            .min_by(|(_, a), (_, b)| {
+                // Strongly prefer keys in line with the movement
+                let cost = |(kx, ky): &(f32, f32)| {
+                    let along = (kx - x) * dx + (ky - y) * dy;
+                    let across = (kx - x) * dy - (ky - y) * dx;
+                    along + 4.0 * across.abs()
+                };
+                cost(a).total_cmp(&cost(b))
+            });
+        if let Some((i, _)) = next {
+            self.cursor = i;
+        }
+    }
+
+    fn export(&mut self) -> Result<PathBuf> {
+        let name = format!("{}-edited", self.name);
+        let data = LayoutData::flexible_from_keyboard_layout(
+            &self.ctx.keyboard,
+            &self.layout,
+            &self.ctx.analyzer.corpus,
+        )
+        .name(name.clone());
+        let path = PathBuf::from(name).with_extension("json");
+        serde_json::to_writer_pretty(File::create(&path)?, &data)?;
+        Ok(path)
+    }
+
+    /// Handle a key press, returning false to quit.
+    fn handle(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') => self.step(-1.0, 0.0),
+            KeyCode::Right | KeyCode::Char('l') => self.step(1.0, 0.0),
+            KeyCode::Up | KeyCode::Char('k') => self.step(0.0, -1.0),
+            KeyCode::Down | KeyCode::Char('j') => self.step(0.0, 1.0),
+            KeyCode::Char(' ') => {
+                self.selected = match self.selected {
+                    Some(s) if s == self.cursor => None,
+                    _ => Some(self.cursor),
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(swap) = self.pending_swap() {
+                    self.apply(swap.clone());
+                    self.history.push(swap);
+                    self.selected = None;
+                }
+            }
+            KeyCode::Char('u') => {
+                if let Some(swap) = self.history.pop() {
+                    self.apply(swap);
+                }
+            }
+            KeyCode::Char('e') => {
+                self.message = match self.export() {
+                    Ok(path) => format!("wrote {}", path.display()),
+                    Err(e) => format!("export failed: {e}"),
+                }
+            }
+            _ => {}
+        }
+        self.update_diff();
+        true
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let chunks = ratatui::layout::Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(f.area());
+
+        let labels = key_labels(&self.ctx.analyzer.corpus, &self.layout.0);
+        let mut lines = keyboard_lines(&self.ctx.keyboard, &labels, |i| {
+            match (i == self.cursor, Some(i) == self.selected) {
+                (true, _) => Style::default().bg(Color::Yellow).fg(Color::Black),
+                (false, true) => Style::default().bg(Color::Blue).fg(Color::White),
+                (false, false) => finger_style(&self.ctx.keyboard, i),
+            }
+        });
+        lines.push(Line::raw(""));
+        lines.push(Line::raw(
+            "arrows/hjkl move, space select, enter swap, u undo, e export, q quit",
+        ));
+        lines.push(Line::raw(self.message.as_str()));
+        let keyboard = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(self.name.as_str()),
+        );
+        f.render_widget(keyboard, chunks[0]);
+
+        let totals = self.layout.totals(&self.ctx.analyzer.corpus);
+        let rows = self.ctx.metrics.iter().enumerate().map(|(m, metric)| {
+            let value = totals.percentage(self.stats[m], metric.ngram_type);
+            let delta = totals.percentage(self.diff[m], metric.ngram_type);
+            let delta_style = match delta {
+                d if d < 0.0 => Style::default().fg(Color::Green),
+                d if d > 0.0 => Style::default().fg(Color::Red),
+                _ => Style::default(),
+            };
+            Row::new(vec![
+                Span::styled(metric.name.clone(), Style::default().fg(Color::Yellow)),
+                Span::raw(format!("{value:.2}%")),
+                match delta {
+                    0.0 => Span::raw(""),
+                    d => Span::styled(format!("{d:+.2}%"), delta_style),
+                },
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(50),
+                Constraint::Percentage(25),
+                Constraint::Percentage(25),
+            ],
+        )
+        .header(Row::new(vec![
+            Span::styled("Metric", Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled("Value", Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled("Swap", Style::default().add_modifier(Modifier::BOLD)),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Stats"));
+        f.render_widget(table, chunks[1]);
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, explorer: &mut Explorer) -> Result<()> {
+    loop {
+        terminal.draw(|f| explorer.draw(f))?;
+        if let Event::Key(key) = event::read()? {
+            // Raw mode delivers Ctrl-C as a key rather than a signal
+            let interrupt =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press && (interrupt || !explorer.handle(key.code)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Interactively try swaps on a layout, seeing each one's stat deltas before applying it.
+pub fn explore(
+    metric_data: keymeow::MetricData,
+    corpus: keycat::Corpus,
+    layout: LayoutData,
+) -> Result<()> {
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let name = layout.name.clone();
+    let matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let stats = ctx.analyzer.calc_stats(&matrix);
+    let mut explorer = Explorer {
+        name,
+        positions: key_positions(&ctx.keyboard),
+        diff: vec![0.0; stats.len()],
+        stats,
+        layout: matrix,
+        ctx,
+        cursor: 0,
+        selected: None,
+        history: vec![],
+        message: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut explorer);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,261 @@
+use crate::blank;
+use crate::keyboard::key_positions;
+use crate::lookup;
+use crate::naming::home_row_name;
+
+use anyhow::{ensure, Context, Result};
+use keycat::Corpus;
+use keymeow::{Keyboard, LayoutData, MetricContext};
+use km_data::Data as KeymeowData;
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::fs;
+use std::iter;
+use std::path::PathBuf;
+
+pub fn layout_data(chars: &str, keyboard: &Keyboard, name: &str, fixed: bool) -> LayoutData {
+    let corpus = Corpus::with_char_list(chars.chars().map(|c| vec![c]).collect());
+    let layout = keycat::Layout(
+        chars
+            .chars()
+            .map(|c| blank::corpus_char(&corpus, c))
+            .collect(),
+    );
+    if fixed {
+        LayoutData::fixed_from_layout(&layout, &corpus)
+    } else {
+        LayoutData::flexible_from_keyboard_layout(keyboard, &layout, &corpus)
+    }
+    .name(name.to_string())
+}
+
+/// A known layout by name, or a raw character string laid out on `keyboard`
+/// the way the `layout-data` subcommand does.
+pub fn load_layout(keymeow: &KeymeowData, layout: &str, keyboard: &Keyboard) -> Result<LayoutData> {
+    if keymeow.layouts.contains_key(layout) {
+        return lookup::layout(keymeow, layout).context("couldn't load layout");
+    }
+    // Short strings are more likely misspelled names than layouts
+    let keys = keyboard.keys.map.iter().flatten().count();
+    ensure!(
+        layout.chars().count() * 2 >= keys,
+        "no layout named {layout}{}, and too short to be a {keys}-key layout",
+        lookup::did_you_mean(layout, keymeow.layouts.keys().map(String::as_str))
+    );
+    let chars: Vec<char> = layout.chars().collect();
+    let name = home_row_name(keyboard, &chars);
+    Ok(layout_data(layout, keyboard, &name, false))
+}
+
+/// Write `layout` into the data directory's layouts, where it's found by name.
+pub fn save_layout(keymeow: &KeymeowData, layout: &LayoutData, force: bool) -> Result<PathBuf> {
+    lookup::ensure_file_name("layout", &layout.name)?;
+    ensure!(
+        force || !keymeow.layouts.contains_key(&layout.name),
+        "a layout named {} already exists; use --force to replace it",
+        layout.name
+    );
+    let dir = keymeow.dir.join("layouts");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", layout.name));
+    fs::write(&path, serde_json::to_string_pretty(layout)?)
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+    Ok(path)
+}
+
+// Named layouts are decoded through a throwaway corpus covering ASCII and Latin-1
+fn export_corpus() -> Corpus {
+    Corpus::with_char_list(
+        (' '..='~')
+            .chain('\u{a1}'..='\u{17f}')
+            .map(|c| vec![c])
+            .collect(),
+    )
+}
+
+/// The character at every layout position (keys, then combos), `'\0'` for blanks.
+/// `layout` is either a known layout name or a raw character string.
+pub fn layout_chars(keymeow: &KeymeowData, layout: &str, keyboard: &Keyboard) -> Result<Vec<char>> {
+    let size = keyboard.keys.map.iter().flatten().count() + keyboard.combos.len();
+    if keymeow.layouts.contains_key(layout) {
+        let data = keymeow.get_layout(layout).context("couldn't load layout")?;
+        let corpus = export_corpus();
+        let matrix = MetricContext::layout_matrix(&data, keyboard, &corpus)
+            .with_context(|| format!("layout {} incompatible with keyboard", data.name))?;
+        Ok(matrix
+            .0
+            .iter()
+            .map(|c| corpus.uncorpus_unigram(*c))
+            .collect())
+    } else {
+        Ok(layout
+            .chars()
+            .map(blank::hide)
+            .chain(iter::repeat(blank::NONE))
+            .take(size)
+            .collect())
+    }
+}
+
+pub fn qmk_keycode(c: char) -> String {
+    let code = match c {
+        'a'..='z' | 'A'..='Z' => return format!("KC_{}", c.to_ascii_uppercase()),
+        '1'..='9' | '0' => return format!("KC_{c}"),
+        blank::NONE => "KC_NO",
+        ' ' => "KC_SPC",
+        ',' => "KC_COMM",
+        '.' => "KC_DOT",
+        '/' => "KC_SLSH",
+        ';' => "KC_SCLN",
+        '\'' => "KC_QUOT",
+        '-' => "KC_MINS",
+        '=' => "KC_EQL",
+        '[' => "KC_LBRC",
+        ']' => "KC_RBRC",
+        '\\' => "KC_BSLS",
+        '`' => "KC_GRV",
+        '\n' => "KC_ENT",
+        '\t' => "KC_TAB",
+        '!' => "KC_EXLM",
+        '@' => "KC_AT",
+        '#' => "KC_HASH",
+        '$' => "KC_DLR",
+        '%' => "KC_PERC",
+        '^' => "KC_CIRC",
+        '&' => "KC_AMPR",
+        '*' => "KC_ASTR",
+        '(' => "KC_LPRN",
+        ')' => "KC_RPRN",
+        '_' => "KC_UNDS",
+        '+' => "KC_PLUS",
+        '{' => "KC_LCBR",
+        '}' => "KC_RCBR",
+        '|' => "KC_PIPE",
+        ':' => "KC_COLN",
+        '"' => "KC_DQUO",
+        '<' => "KC_LABK",
+        '>' => "KC_RABK",
+        '?' => "KC_QUES",
+        '~' => "KC_TILD",
+        _ => "KC_NO",
+    };
+    code.to_string()
+}
+
+fn keymap_rows(keyboard: &Keyboard, chars: &[char]) -> Vec<Vec<String>> {
+    let mut i = 0;
+    keyboard
+        .keys
+        .map
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|_| {
+                    i += 1;
+                    qmk_keycode(chars[i - 1])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A QMK `keymap.c` with a single base layer and any assigned combos.
+pub fn qmk_keymap_c(keyboard: &Keyboard, chars: &[char]) -> String {
+    let kb_size = keyboard.keys.map.iter().flatten().count();
+    let rows: Vec<String> = keymap_rows(keyboard, chars)
+        .iter()
+        .map(|row| format!("        {}", row.join(", ")))
+        .collect();
+
+    let mut c = String::from("// Generated by keywhisker\n#include QMK_KEYBOARD_H\n\n");
+    let _ = write!(
+        c,
+        "const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{\n    [0] = LAYOUT(\n{}\n    )\n}};\n",
+        rows.join(",\n")
+    );
+
+    let mut keyboard = keyboard.clone();
+    keyboard.process_combo_indexes();
+    let combos: Vec<(usize, &Vec<usize>)> = keyboard
+        .combo_indexes
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| chars.get(kb_size + idx).is_some_and(|c| *c != blank::NONE))
+        .collect();
+    if !combos.is_empty() {
+        c.push('\n');
+        for (idx, keys) in &combos {
+            let keys: Vec<String> = keys.iter().map(|k| qmk_keycode(chars[*k])).collect();
+            let _ = writeln!(
+                c,
+                "const uint16_t PROGMEM combo{idx}[] = {{{}, COMBO_END}};",
+                keys.join(", ")
+            );
+        }
+        c.push_str("combo_t key_combos[] = {\n");
+        for (idx, _) in &combos {
+            let _ = writeln!(
+                c,
+                "    COMBO(combo{idx}, {}),",
+                qmk_keycode(chars[kb_size + idx])
+            );
+        }
+        c.push_str("};\n");
+    }
+    c
+}
+
+/// A QMK configurator style `keymap.json` with a single base layer.
+pub fn qmk_keymap_json(keyboard: &Keyboard, chars: &[char], name: &str) -> Result<String> {
+    let layer: Vec<String> = keymap_rows(keyboard, chars).into_iter().flatten().collect();
+    let keymap = json!({
+        "keyboard": name,
+        "keymap": "keywhisker",
+        "layout": "LAYOUT",
+        "layers": [layer],
+    });
+    Ok(serde_json::to_string_pretty(&keymap)? + "\n")
+}
+
+/// keyboard-layout-editor.com JSON with the layout's characters as legends.
+pub fn kle_json(keyboard: &Keyboard, chars: &[char]) -> Result<String> {
+    let mut keys: Vec<((f32, f32), char)> = key_positions(keyboard)
+        .into_iter()
+        .zip(chars.iter().copied())
+        .collect();
+    keys.sort_by(|((ax, ay), _), ((bx, by), _)| ay.total_cmp(by).then(ax.total_cmp(bx)));
+
+    // KLE positions are relative: each row starts one unit below the previous
+    // one at x = 0, and each key advances the cursor by its width.
+    let mut rows: Vec<Value> = vec![];
+    let mut row: Vec<Value> = vec![];
+    let (mut row_y, mut cursor) = (None, 0.0);
+    for ((x, y), c) in keys {
+        if row_y != Some(y) {
+            let dy = match row_y {
+                Some(prev) => y - prev - 1.0,
+                None => y,
+            };
+            if row_y.is_some() {
+                rows.push(Value::Array(std::mem::take(&mut row)));
+            }
+            if dy != 0.0 {
+                row.push(json!({ "y": dy }));
+            }
+            row_y = Some(y);
+            cursor = 0.0;
+        }
+        if x != cursor {
+            row.push(json!({ "x": x - cursor }));
+        }
+        row.push(json!(match c {
+            blank::NONE => String::new(),
+            c => c.to_string(),
+        }));
+        cursor = x + 1.0;
+    }
+    if !row.is_empty() {
+        rows.push(Value::Array(row));
+    }
+    Ok(serde_json::to_string_pretty(&rows)? + "\n")
+}
@@ -0,0 +1,105 @@
+use crate::analysis::layout_matrices;
+use crate::heatmap::key_frequencies;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use keycat::{Corpus, CorpusChar};
+use keymeow::{Finger, Keyboard, LayoutData, MetricContext, MetricData};
+
+const FINGER_NAMES: [&str; 10] = [
+    "Left pinky",
+    "Left ring",
+    "Left middle",
+    "Left index",
+    "Left thumb",
+    "Right thumb",
+    "Right index",
+    "Right middle",
+    "Right ring",
+    "Right pinky",
+];
+
+/// Background colors for each finger in `FINGER_NAMES` order, the same for
+/// both hands so columns read alike.
+const FINGER_COLORS: [(u8, u8, u8); 10] = [
+    (255, 179, 186),
+    (255, 223, 186),
+    (255, 255, 186),
+    (186, 255, 201),
+    (186, 225, 255),
+    (186, 225, 255),
+    (186, 255, 201),
+    (255, 255, 186),
+    (255, 223, 186),
+    (255, 179, 186),
+];
+
+/// The RGB color keys typed by `finger` are drawn in.
+pub fn finger_color(finger: &Finger) -> (u8, u8, u8) {
+    FINGER_COLORS[finger_index(finger)]
+}
+
+/// `text` on `finger`'s color, as ANSI escapes.
+pub fn paint(finger: &Finger, text: &str) -> String {
+    let (r, g, b) = finger_color(finger);
+    format!("\x1b[30;48;2;{r};{g};{b}m{text}\x1b[0m")
+}
+
+pub fn finger_index(finger: &Finger) -> usize {
+    match finger {
+        Finger::LP => 0,
+        Finger::LR => 1,
+        Finger::LM => 2,
+        Finger::LI => 3,
+        Finger::LT => 4,
+        Finger::RT => 5,
+        Finger::RI => 6,
+        Finger::RM => 7,
+        Finger::RR => 8,
+        Finger::RP => 9,
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    /// The hand `finger` is on, thumbs included.
+    pub fn of(finger: &Finger) -> Hand {
+        match finger_index(finger) {
+            0..=4 => Hand::Left,
+            _ => Hand::Right,
+        }
+    }
+}
+
+/// Percentage of typed characters pressed by each finger, in `FINGER_NAMES` order.
+pub fn finger_usage(keyboard: &Keyboard, corpus: &Corpus, keys: &[CorpusChar]) -> [f32; 10] {
+    let mut usage = [0.0; 10];
+    let frequencies = key_frequencies(corpus, keys);
+    for (finger, frequency) in keyboard.fingers.map.iter().flatten().zip(frequencies) {
+        usage[finger_index(finger)] += frequency;
+    }
+    usage
+}
+
+pub fn print_fingers(metric_data: MetricData, corpus: Corpus, layout: LayoutData) -> Result<()> {
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let usage = finger_usage(&ctx.keyboard, &ctx.analyzer.corpus, &matrix.0);
+
+    for (name, percentage) in FINGER_NAMES.iter().zip(usage) {
+        println!("{name:<13}{percentage:>6.2}%");
+    }
+    let left: f32 = usage[..5].iter().sum();
+    let right: f32 = usage[5..].iter().sum();
+    println!();
+    println!("{:<13}{left:>6.2}%", "Left hand");
+    println!("{:<13}{right:>6.2}%", "Right hand");
+    println!("{:<13}{:>+6.2}%", "Balance", left - right);
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::blank;
+use crate::fingers::finger_index;
+
+use anyhow::{ensure, Context, Result};
+use keymeow::Finger;
+use std::fmt::Write;
+
+// genkey and Oxeylyzer both store a 3x10 grid row by row, while keywhisker
+// orders the same 30 positions column by column.
+const ROWS: usize = 3;
+const COLS: usize = 10;
+
+// Written in place of blank keys
+const BLANK: char = '~';
+// The fingers of a standard 3x10 board's columns, for keys a keyboard doesn't map
+const COLUMN_FINGERS: [usize; COLS] = [0, 1, 2, 3, 3, 6, 6, 7, 8, 9];
+
+fn parse_grid<'a>(lines: impl Iterator<Item = &'a str>) -> Result<String> {
+    let rows: Vec<Vec<char>> = lines
+        .take(ROWS)
+        .map(|line| {
+            line.split_whitespace()
+                .filter_map(|key| key.chars().next())
+                .map(|c| if c == BLANK { blank::BLANK } else { c })
+                .collect()
+        })
+        .collect();
+    ensure!(
+        rows.len() == ROWS && rows.iter().all(|r| r.len() == COLS),
+        "expected {ROWS} rows of {COLS} keys"
+    );
+    Ok((0..COLS)
+        .flat_map(|col| rows.iter().map(move |row| row[col]))
+        .collect())
+}
+
+fn write_grid(output: &mut String, chars: &[char]) {
+    for row in 0..ROWS {
+        let keys: Vec<String> = (0..COLS)
+            .map(|col| match chars.get(col * ROWS + row) {
+                Some(c) if !blank::is_blank(*c) => *c,
+                _ => BLANK,
+            })
+            .map(|c| c.to_string())
+            .collect();
+        let _ = writeln!(output, "{}  {}", keys[..5].join(" "), keys[5..].join(" "));
+    }
+}
+
+/// Name and column-major characters of a genkey `.layout` file.
+pub fn parse_genkey(text: &str) -> Result<(String, String)> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let name = lines
+        .next()
+        .context("empty genkey layout")?
+        .trim()
+        .to_string();
+    Ok((name, parse_grid(lines)?))
+}
+
+/// Column-major characters of an Oxeylyzer `.kb` file.
+pub fn parse_oxeylyzer(text: &str) -> Result<String> {
+    parse_grid(text.lines().filter(|l| !l.trim().is_empty()))
+}
+
+/// A genkey `.layout` file of `chars`, with the finger typing each key taken
+/// from `fingers`, both in keywhisker's position order.
+pub fn to_genkey(name: &str, chars: &[char], fingers: &[Finger]) -> String {
+    let mut output = format!("{name}\n");
+    write_grid(&mut output, chars);
+    for row in 0..ROWS {
+        let keys: Vec<String> = (0..COLS)
+            .map(|col| {
+                fingers
+                    .get(col * ROWS + row)
+                    .map_or(COLUMN_FINGERS[col], finger_index)
+            })
+            .map(|f| f.to_string())
+            .collect();
+        let _ = writeln!(output, "{}  {}", keys[..5].join(" "), keys[5..].join(" "));
+    }
+    output
+}
+
+pub fn to_oxeylyzer(chars: &[char]) -> String {
+    let mut output = String::new();
+    write_grid(&mut output, chars);
+    output
+}
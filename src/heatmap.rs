@@ -0,0 +1,159 @@
+use crate::analysis::{get_metric, layout_matrices};
+use crate::keyboard::key_positions;
+use crate::svg;
+
+use anyhow::{Context, Result};
+use keycat::{analysis::Analyzer, Corpus, CorpusChar, Layout, NgramType};
+use keymeow::{Keyboard, LayoutData, MetricContext, MetricData};
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+pub fn key_labels(corpus: &Corpus, keys: &[CorpusChar]) -> Vec<String> {
+    keys.iter()
+        .map(|c| match c {
+            0 => String::new(),
+            c => match corpus.uncorpus_unigram(*c) {
+                ' ' => "␣".to_string(),
+                c => c.to_string(),
+            },
+        })
+        .collect()
+}
+
+/// Share of all typed characters produced by each key, in percent.
+pub fn key_frequencies(corpus: &Corpus, keys: &[CorpusChar]) -> Vec<f32> {
+    let total: f32 = corpus.chars.iter().map(|c| *c as f32).sum::<f32>().max(1.0);
+    keys.iter()
+        .map(|c| match c {
+            0 => 0.0,
+            c => 100.0 * corpus.chars[*c] as f32 / total,
+        })
+        .collect()
+}
+
+pub fn ngram_frequency(
+    corpus: &Corpus,
+    layout: &Layout,
+    ngram_type: NgramType,
+    positions: &[usize],
+) -> f32 {
+    let l = &layout.0;
+    let count = match (ngram_type, positions) {
+        (NgramType::Monogram, [a]) => corpus.chars[l[*a]],
+        (NgramType::Bigram, [a, b]) => corpus.bigrams[corpus.bigram_idx(l[*a], l[*b])],
+        (NgramType::Skipgram, [a, b]) => corpus.skipgrams[corpus.bigram_idx(l[*a], l[*b])],
+        (NgramType::Trigram, [a, b, c]) => corpus.trigrams[corpus.trigram_idx(l[*a], l[*b], l[*c])],
+        _ => 0,
+    };
+    count as f32
+}
+
+/// Raw amount of `metric` attributed to each position, splitting every
+/// nstroke's weighted frequency evenly between the positions it uses.
+pub fn position_contributions(analyzer: &Analyzer, layout: &Layout, metric: usize) -> Vec<f32> {
+    let mut contributions = vec![0.0; layout.0.len()];
+    let ngram_type = analyzer.data.metrics[metric];
+    for stroke in &analyzer.data.strokes {
+        for amount in stroke.amounts.iter().filter(|a| a.metric == metric) {
+            let positions = stroke.nstroke.to_vec();
+            let value =
+                amount.amount * ngram_frequency(&analyzer.corpus, layout, ngram_type, &positions);
+            for pos in &positions {
+                contributions[*pos] += value / positions.len() as f32;
+            }
+        }
+    }
+    contributions
+}
+
+/// Label and value of every key: its frequency, or how much the position
+/// contributes to `metric` when one is given.
+fn key_values(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+    metric: Option<&str>,
+) -> Result<(Keyboard, Vec<String>, Vec<f32>)> {
+    let metric = metric
+        .map(|name| {
+            get_metric(name, &metric_data).with_context(|| format!("invalid metric {name}"))
+        })
+        .transpose()?;
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let key_count = key_positions(&ctx.keyboard).len();
+    let keys = &matrix.0[..key_count.min(matrix.0.len())];
+    let corpus = &ctx.analyzer.corpus;
+
+    let values = match metric {
+        Some(m) => {
+            let totals = matrix.totals(corpus);
+            position_contributions(&ctx.analyzer, &matrix, m)
+                .into_iter()
+                .take(keys.len())
+                .map(|v| totals.percentage(v, ctx.metrics[m].ngram_type))
+                .collect()
+        }
+        None => key_frequencies(corpus, keys),
+    };
+    let labels = key_labels(corpus, keys);
+    Ok((ctx.keyboard, labels, values))
+}
+
+/// Write an SVG of the keyboard colored by key frequency, or by how much
+/// each position contributes to `metric` when one is given.
+pub fn svg_heatmap(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+    metric: Option<&str>,
+    output: &Path,
+) -> Result<()> {
+    let (keyboard, labels, values) = key_values(metric_data, corpus, layout, metric)?;
+    let positions = key_positions(&keyboard);
+
+    if let Some(parent) = output.parent() {
+        create_dir_all(parent).context("couldn't create output directory")?;
+    }
+    write(output, svg::keyboard_heatmap(&positions, &labels, &values))
+        .context("couldn't write heatmap")?;
+    Ok(())
+}
+
+// Terminal columns per key unit
+const TERMINAL_UNIT: f32 = 4.0;
+
+/// Print the keyboard row by row with each key's background shaded like the SVG.
+pub fn terminal_heatmap(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layout: LayoutData,
+    metric: Option<&str>,
+) -> Result<()> {
+    let (keyboard, labels, values) = key_values(metric_data, corpus, layout, metric)?;
+    let max = values.iter().copied().fold(0.0, f32::max);
+
+    let mut i = 0;
+    for row in &keyboard.keys.map {
+        let mut line = String::new();
+        let mut column = 0;
+        for key in row {
+            let start = (key.x * TERMINAL_UNIT).round() as usize;
+            line.push_str(&" ".repeat(start.saturating_sub(column)));
+            let t = match max {
+                0.0 => 0.0,
+                max => values.get(i).copied().unwrap_or(0.0) / max,
+            };
+            let fade = (255.0 * (1.0 - 0.8 * t)) as u8;
+            let label = labels.get(i).map_or("", |l| l.as_str());
+            line.push_str(&format!(
+                "\x1b[30;48;2;255;{fade};{fade}m {label:^2}\x1b[0m"
+            ));
+            column = start.max(column) + 3;
+            i += 1;
+        }
+        println!("{line}");
+    }
+    Ok(())
+}
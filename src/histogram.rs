@@ -0,0 +1,195 @@
+use crate::analysis::{get_metric, layout_matrices};
+use crate::percentiles::read_distribution;
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+use std::path::Path;
+
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SHADES: [char; 6] = [' ', '·', ':', '+', '#', '@'];
+
+/// A layout to mark on the charts, with its value for every column of the csv.
+pub struct Marker {
+    name: String,
+    values: Vec<f32>,
+}
+
+impl Marker {
+    /// Analyze `layout` for each metric named in `columns`.
+    pub fn new(
+        metric_data: MetricData,
+        corpus: Corpus,
+        layout: LayoutData,
+        columns: &[String],
+    ) -> Result<Self> {
+        let metrics: Result<Vec<usize>> = columns
+            .iter()
+            .map(|name| {
+                get_metric(name, &metric_data).with_context(|| format!("invalid metric {name}"))
+            })
+            .collect();
+        let metrics = metrics?;
+        let ctx = MetricContext::new(&layout, metric_data, corpus)
+            .context("could not produce metric context")?;
+        let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+        let stats = ctx
+            .analyzer
+            .calc_stats(&layout_matrices(&ctx, std::slice::from_ref(&layout))?[0]);
+        Ok(Marker {
+            name: layout.name,
+            values: metrics
+                .iter()
+                .map(|m| totals.percentage(stats[*m], ctx.metrics[*m].ngram_type))
+                .collect(),
+        })
+    }
+}
+
+/// The metric names heading a csv written by `collect`.
+pub fn columns(path: &Path) -> Result<Vec<String>> {
+    Ok(read_distribution(path)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// The range of `values`, widened to take in a marked value so it's always on the chart.
+fn bounds(values: &[f32], marked: Option<f32>) -> (f32, f32) {
+    let all = values.iter().copied().chain(marked);
+    let lo = all.clone().fold(f32::INFINITY, f32::min);
+    let hi = all.fold(f32::NEG_INFINITY, f32::max);
+    (lo, hi.max(lo + f32::EPSILON))
+}
+
+/// Which of `bins` equal-width bins between `lo` and `hi` a value falls into.
+fn bin(value: f32, (lo, hi): (f32, f32), bins: usize) -> usize {
+    let position = ((value - lo) / (hi - lo) * bins as f32).floor();
+    (position.max(0.0) as usize).min(bins - 1)
+}
+
+fn print_histogram(
+    name: &str,
+    values: &[f32],
+    width: usize,
+    height: usize,
+    marker: Option<(&str, f32)>,
+) {
+    let (lo, hi) = bounds(values, marker.map(|(_, v)| v));
+    let mut counts = vec![0usize; width];
+    for v in values {
+        counts[bin(*v, (lo, hi), width)] += 1;
+    }
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mean = values.iter().sum::<f32>() / values.len().max(1) as f32;
+
+    println!("{name} ({} layouts, mean {mean:.2}%)", values.len());
+    // Eighths of a row, so bars rise smoothly through the block characters
+    let levels: Vec<usize> = counts.iter().map(|c| c * height * 8 / max).collect();
+    for row in (0..height).rev() {
+        let line: String = levels
+            .iter()
+            .map(|level| BLOCKS[level.saturating_sub(row * 8).min(8)])
+            .collect();
+        println!("  {line}");
+    }
+    if let Some((layout, v)) = marker {
+        let column = bin(v, (lo, hi), width);
+        let label = format!("^ {layout} {v:.2}%");
+        // Put the label to the left of the caret when it would run off the end
+        match column + label.len() > width {
+            true => println!("  {:>1$}", format!("{layout} {v:.2}% ^"), column + 1),
+            false => println!("  {}{label}", " ".repeat(column)),
+        }
+    }
+    let low = format!("{lo:.2}%");
+    println!("  {low}{:>1$.2}%", hi, width.saturating_sub(low.len() + 1));
+    println!();
+}
+
+fn print_scatter(
+    (x_name, xs): (&str, &[f32]),
+    (y_name, ys): (&str, &[f32]),
+    width: usize,
+    height: usize,
+    marker: Option<(&str, f32, f32)>,
+) {
+    let x_bounds = bounds(xs, marker.map(|(_, x, _)| x));
+    let y_bounds = bounds(ys, marker.map(|(_, _, y)| y));
+    let mut counts = vec![vec![0usize; width]; height];
+    for (x, y) in xs.iter().zip(ys) {
+        counts[bin(*y, y_bounds, height)][bin(*x, x_bounds, width)] += 1;
+    }
+    let max = counts.iter().flatten().copied().max().unwrap_or(0).max(1);
+    let marked = marker.map(|(_, x, y)| (bin(y, y_bounds, height), bin(x, x_bounds, width)));
+
+    println!("{y_name} against {x_name}");
+    let y_label_width = 8;
+    for (row, cells) in counts.iter().enumerate().rev() {
+        let label = match row {
+            _ if row == height - 1 => format!("{:.2}%", y_bounds.1),
+            0 => format!("{:.2}%", y_bounds.0),
+            _ => String::new(),
+        };
+        let line: String = cells
+            .iter()
+            .enumerate()
+            .map(|(column, count)| match marked == Some((row, column)) {
+                true => 'X',
+                // Any populated cell gets at least the faintest shade
+                false => SHADES[(count * (SHADES.len() - 1)).div_ceil(max)],
+            })
+            .collect();
+        println!("{label:>y_label_width$} |{line}");
+    }
+    println!("{:>y_label_width$} +{}", "", "-".repeat(width));
+    let low = format!("{:.2}%", x_bounds.0);
+    println!(
+        "{:>y_label_width$}  {low}{:>2$.2}%",
+        "",
+        x_bounds.1,
+        width.saturating_sub(low.len() + 1)
+    );
+    if let Some((layout, x, y)) = marker {
+        println!("X: {layout} ({x_name} {x:.2}%, {y_name} {y:.2}%)");
+    }
+}
+
+/// Print a histogram of every metric in a `collect` csv, or a scatter plot
+/// of two of them, optionally marking where a layout falls.
+pub fn view(
+    path: &Path,
+    scatter: Option<(&str, &str)>,
+    width: usize,
+    height: usize,
+    marker: Option<&Marker>,
+) -> Result<()> {
+    let columns = read_distribution(path)?;
+    let width = width.max(2);
+    let height = height.max(1);
+    match scatter {
+        Some((x, y)) => {
+            let find = |name: &str| {
+                columns
+                    .iter()
+                    .position(|(c, _)| c == name)
+                    .with_context(|| format!("no column {name} in {}", path.display()))
+            };
+            let (x, y) = (find(x)?, find(y)?);
+            print_scatter(
+                (&columns[x].0, &columns[x].1),
+                (&columns[y].0, &columns[y].1),
+                width,
+                height,
+                marker.map(|m| (m.name.as_str(), m.values[x], m.values[y])),
+            );
+        }
+        None => {
+            for (i, (name, values)) in columns.iter().enumerate() {
+                let marked = marker.map(|m| (m.name.as_str(), m.values[i]));
+                print_histogram(name, values, width, height, marked);
+            }
+        }
+    }
+    Ok(())
+}
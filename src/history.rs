@@ -0,0 +1,141 @@
+//! A record of every generation run, one JSON object per line, so old runs
+//! can be found again by their parameters without digging through tsvs.
+
+use crate::metadata::RunMetadata;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, create_dir_all, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::warn;
+
+// Best results kept per generation
+pub const TOP: usize = 5;
+
+/// One generation, as recorded when it finishes or is interrupted.
+#[derive(Serialize, Deserialize)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub metadata: RunMetadata,
+    pub finished: String,
+    pub completed_runs: u64,
+    /// The best results, best first, as score and layout characters
+    pub best: Vec<(f32, String)>,
+}
+
+/// Append `entry` to the history at `path`.
+pub fn record(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).context("couldn't create history directory")?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("couldn't open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Every entry of the history at `path`, oldest first; lines that can't be
+/// read are warned about and skipped.
+pub fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let text =
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .filter_map(|(i, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("skipping line {} of {}: {e}", i + 1, path.display());
+                None
+            }
+        })
+        .collect())
+}
+
+/// Which entries `history` shows.
+#[derive(Default)]
+pub struct Filter {
+    /// A strategy's name, or its initials like `sa` for simulated-annealing
+    pub strategy: Option<String>,
+    /// Part of the corpus name
+    pub corpus: Option<String>,
+    pub keyboard: Option<String>,
+    /// A date or time in the history's format, e.g. `2024-01-01`
+    pub since: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, metadata: &RunMetadata) -> bool {
+        let strategy = &metadata.strategy;
+        let initials: String = strategy
+            .split('-')
+            .filter_map(|w| w.chars().next())
+            .collect();
+        // Times are ISO 8601, so they compare as strings, and a date is a prefix of its times
+        let since = |s: &String| metadata.started.as_str() >= s.as_str();
+        self.strategy
+            .as_ref()
+            .is_none_or(|s| s == strategy || *s == initials)
+            && self
+                .corpus
+                .as_ref()
+                .is_none_or(|c| metadata.corpus.contains(c.as_str()))
+            && self
+                .keyboard
+                .as_ref()
+                .is_none_or(|k| *k == metadata.keyboard)
+            && self.since.as_ref().is_none_or(since)
+    }
+}
+
+/// Print the last `limit` entries that pass `filter`, newest last, with their
+/// best layouts if `best` is set.
+pub fn print(entries: &[HistoryEntry], filter: &Filter, limit: usize, best: bool) {
+    let matching: Vec<&HistoryEntry> = entries
+        .iter()
+        .filter(|e| filter.matches(&e.metadata))
+        .collect();
+    let shown = &matching[matching.len().saturating_sub(limit)..];
+    let width = shown
+        .iter()
+        .map(|e| e.metadata.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<21}{:width$}  {:<27}{:<16}{:<10}{:>6}{:>10}",
+        "Started", "Name", "Strategy", "Corpus", "Keyboard", "Runs", "Best"
+    );
+    for entry in shown {
+        let m = &entry.metadata;
+        let score = entry
+            .best
+            .first()
+            .map_or("-".to_string(), |(s, _)| format!("{s:.4}"));
+        println!(
+            "{:<21}{:width$}  {:<27}{:<16}{:<10}{:>6}{score:>10}",
+            m.started,
+            m.name,
+            m.strategy,
+            m.corpus,
+            m.keyboard,
+            format!("{}/{}", entry.completed_runs, m.runs),
+        );
+        if best {
+            for (score, chars) in &entry.best {
+                println!("    {score:.4}  {chars}");
+            }
+        }
+    }
+    if matching.len() > shown.len() {
+        println!("({} older runs not shown)", matching.len() - shown.len());
+    }
+}
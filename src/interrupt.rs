@@ -0,0 +1,28 @@
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Make the first Ctrl-C ask long-running loops to stop and keep what they
+/// have so far. A second one exits right away.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            ratatui::restore();
+            std::process::exit(130);
+        }
+        eprintln!("interrupted, finishing up (press Ctrl-C again to quit now)");
+    })
+    .context("couldn't install Ctrl-C handler")
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Ask long-running loops to stop, as a first Ctrl-C would.
+pub fn request() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,236 @@
+use crate::fingers::{paint, Hand};
+use crate::travel::home_keys;
+
+use anyhow::{ensure, Result};
+use keycat::analysis::MetricAmount;
+use keymeow::{Finger, Keyboard, Metric, MetricData};
+use std::collections::{HashMap, HashSet};
+
+/// Physical (x, y) of every non-combo key, in layout order, in key units.
+pub fn key_positions(keyboard: &Keyboard) -> Vec<(f32, f32)> {
+    keyboard
+        .keys
+        .map
+        .iter()
+        .flatten()
+        .map(|k| (k.x, k.y))
+        .collect()
+}
+
+/// The position each of `len` positions lands on when the keyboard is flipped
+/// left to right, or None if some key has no mirror image. Combos stay put.
+pub fn mirror_positions(keyboard: &Keyboard, len: usize) -> Option<Vec<usize>> {
+    let positions = key_positions(keyboard);
+    let right = positions.iter().map(|(x, _)| *x).fold(0.0, f32::max);
+    let mut mirror: Vec<usize> = (0..len).collect();
+    for (i, (x, y)) in positions.iter().enumerate() {
+        mirror[i] = positions
+            .iter()
+            .position(|(mx, my)| (mx - (right - x)).abs() < 0.01 && (my - y).abs() < 0.01)?;
+    }
+    Some(mirror)
+}
+
+/// The keyboard drawn row by row in plain text with each key's label, keys
+/// placed by their x coordinate, then a line for each combo's keys and label.
+pub fn keyboard_text(keyboard: &Keyboard, labels: &[String]) -> Vec<String> {
+    draw_text(keyboard, labels, |_, text| text.to_string())
+}
+
+/// `keyboard_text` with each key on the color of the finger typing it.
+pub fn finger_colored_text(keyboard: &Keyboard, labels: &[String]) -> Vec<String> {
+    let fingers: Vec<Finger> = keyboard.fingers.map.iter().flatten().copied().collect();
+    draw_text(keyboard, labels, |i, text| match fingers.get(i) {
+        Some(finger) => paint(finger, text),
+        None => text.to_string(),
+    })
+}
+
+/// Each row of keys placed by their x coordinate, as the spaces before each
+/// key, its position and its text. Both the plain text and terminal renderers
+/// draw from this.
+pub fn key_rows(keyboard: &Keyboard, labels: &[String]) -> Vec<Vec<(usize, usize, String)>> {
+    let mut rows = vec![];
+    let mut i = 0;
+    for row in &keyboard.keys.map {
+        let mut keys = vec![];
+        let mut column = 0;
+        for key in row {
+            let start = (key.x * 4.0).round() as usize;
+            let text = format!(" {:^2}", labels.get(i).map_or("", String::as_str));
+            keys.push((start.saturating_sub(column), i, text));
+            column = start.max(column) + keys[keys.len() - 1].2.chars().count();
+            i += 1;
+        }
+        rows.push(keys);
+    }
+    rows
+}
+
+/// The keyboard as in `keyboard_text`, with each position's text passed
+/// through `style`, which may add escapes that take no columns.
+fn draw_text(
+    keyboard: &Keyboard,
+    labels: &[String],
+    style: impl Fn(usize, &str) -> String,
+) -> Vec<String> {
+    let label = |i: usize| labels.get(i).map_or("", String::as_str);
+    let mut lines: Vec<String> = key_rows(keyboard, labels)
+        .into_iter()
+        .map(|row| {
+            let line: String = row
+                .iter()
+                .map(|(gap, i, text)| " ".repeat(*gap) + &style(*i, text))
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect();
+    let keys = keyboard.keys.map.iter().flatten().count();
+    for (i, combo) in keyboard.combos.iter().enumerate() {
+        let combo_keys: Vec<String> = combo.0.iter().map(|k| style(*k, label(*k))).collect();
+        lines.push(format!("{} = {}", combo_keys.join("+"), label(keys + i)));
+    }
+    lines
+}
+
+/// Check that a keyboard's fingers match its keys, and that its combos and
+/// metric strokes only refer to positions and metrics it has.
+pub fn validate(metric_data: &MetricData) -> Result<()> {
+    let keyboard = &metric_data.keyboard;
+    let rows: Vec<usize> = keyboard.keys.map.iter().map(Vec::len).collect();
+    let finger_rows: Vec<usize> = keyboard.fingers.map.iter().map(Vec::len).collect();
+    ensure!(
+        rows == finger_rows,
+        "finger rows {finger_rows:?} don't match the key rows {rows:?}"
+    );
+
+    let keys: usize = rows.iter().sum();
+    for (i, combo) in keyboard.combos.iter().enumerate() {
+        ensure!(combo.0.len() >= 2, "combo {i} has fewer than two keys");
+        ensure!(
+            combo.0.iter().all(|k| *k < keys),
+            "combo {i} uses a key outside the keyboard's {keys} keys: {:?}",
+            combo.0
+        );
+        let mut sorted = combo.0.clone();
+        sorted.sort();
+        sorted.dedup();
+        ensure!(
+            sorted.len() == combo.0.len(),
+            "combo {i} repeats a key: {:?}",
+            combo.0
+        );
+    }
+
+    let positions = keys + keyboard.combos.len();
+    for stroke in &metric_data.strokes {
+        ensure!(
+            stroke.nstroke.to_vec().iter().all(|p| *p < positions),
+            "a stroke uses a position outside the keyboard's {positions}: {:?}",
+            stroke.nstroke.to_vec()
+        );
+        ensure!(
+            stroke
+                .amounts
+                .iter()
+                .all(|a| a.metric < metric_data.metrics.len()),
+            "a stroke refers to a metric beyond the {} defined",
+            metric_data.metrics.len()
+        );
+    }
+    Ok(())
+}
+
+/// Add `other`'s metrics to `metric_data` under its `name`, on the same
+/// positions, so one analyzer measures a layout on both keyboards. Returns
+/// where `other`'s metrics start.
+pub fn merge_metrics(metric_data: &mut MetricData, other: MetricData, name: &str) -> Result<usize> {
+    let count = |k: &Keyboard| (k.keys.map.iter().flatten().count(), k.combos.len());
+    let (keys, combos) = count(&metric_data.keyboard);
+    let (other_keys, other_combos) = count(&other.keyboard);
+    ensure!(
+        (keys, combos) == (other_keys, other_combos),
+        "{name} has {other_keys} keys and {other_combos} combos, but layouts here have \
+         {keys} keys and {combos} combos"
+    );
+
+    let offset = metric_data.metrics.len();
+    metric_data
+        .metrics
+        .extend(other.metrics.into_iter().map(|m| Metric {
+            name: format!("{} ({name})", m.name),
+            short: format!("{}@{name}", m.short),
+            ngram_type: m.ngram_type,
+        }));
+    let mut strokes: HashMap<Vec<usize>, usize> = metric_data
+        .strokes
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.nstroke.to_vec(), i))
+        .collect();
+    for mut stroke in other.strokes {
+        let amounts = stroke.amounts.iter().map(|a| MetricAmount {
+            metric: a.metric + offset,
+            amount: a.amount,
+        });
+        match strokes.get(&stroke.nstroke.to_vec()) {
+            Some(i) => metric_data.strokes[*i].amounts.extend(amounts),
+            None => {
+                stroke.amounts = amounts.collect();
+                strokes.insert(stroke.nstroke.to_vec(), metric_data.strokes.len());
+                metric_data.strokes.push(stroke);
+            }
+        }
+    }
+    Ok(offset)
+}
+
+/// Positions of the non-combo keys typed by `hand`, in layout order.
+pub fn hand_positions(keyboard: &Keyboard, hand: Hand) -> Vec<usize> {
+    keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, f)| Hand::of(f) == hand)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+/// The positions kept in place: the first `pin` of `keys`, in order, and every
+/// one of `also`. Generation and collect both pin this way.
+pub fn pinned_positions(
+    keys: impl IntoIterator<Item = usize>,
+    pin: usize,
+    also: &[usize],
+) -> HashSet<usize> {
+    keys.into_iter()
+        .take(pin)
+        .chain(also.iter().copied())
+        .collect()
+}
+
+/// The positions of the key map row holding the most home keys, left to right.
+pub fn home_row(keyboard: &Keyboard) -> Vec<usize> {
+    let homes: HashSet<usize> = home_keys(keyboard).into_iter().collect();
+    let mut start = 0;
+    let mut rows = vec![];
+    for row in &keyboard.keys.map {
+        rows.push((start..start + row.len()).collect::<Vec<_>>());
+        start += row.len();
+    }
+    rows.into_iter()
+        .max_by_key(|row| row.iter().filter(|p| homes.contains(p)).count())
+        .unwrap_or_default()
+}
+
+/// `metric_data` with only the strokes typed entirely by `hand`, so every
+/// metric describes that hand alone.
+pub fn one_hand(mut metric_data: MetricData, hand: Hand) -> MetricData {
+    let keys = hand_positions(&metric_data.keyboard, hand);
+    metric_data
+        .strokes
+        .retain(|s| s.nstroke.to_vec().iter().all(|p| keys.contains(p)));
+    metric_data
+}
@@ -0,0 +1,184 @@
+//! Layers: copies of the keyboard's keys reached by holding a layer key, for
+//! numbers and symbols. A key on a layer is pressed with the same motion as
+//! the base key under it, so each layer gets a copy of every stroke, and the
+//! cost of reaching the layer is counted by the `layer` metric.
+//!
+//! A layout with layers is the base layout followed by each layer's keys, the
+//! nth layer's copy of position `p` at `n * len + p` for a base layout of `len`
+//! positions. Every ngram stroke is copied for each mix of layers, so metric
+//! data grows by `(layers + 1)^3` for trigrams; keep layers few.
+
+use crate::analysis::{kc_metric_data, LayoutStats, MetricStat};
+use crate::blank;
+
+use anyhow::{ensure, Context, Error, Result};
+use keycat::analysis::{Analyzer, MetricAmount, Nstroke, NstrokeData};
+use keycat::{Corpus, Layout, NgramType};
+use keymeow::{Keyboard, LayoutData, Metric, MetricContext, MetricData};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A layer's characters in position order, and what reaching it costs per press.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub name: String,
+    pub chars: String,
+    pub cost: f32,
+}
+
+impl FromStr for Layer {
+    type Err = Error;
+
+    /// `name=chars` or `name:cost=chars`, with `�` for an empty key. The cost
+    /// defaults to 1.
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, chars) = s
+            .split_once('=')
+            .with_context(|| format!("expected name:cost=chars but got `{s}`"))?;
+        let (name, cost) = match name.split_once(':') {
+            Some((name, cost)) => (name, cost.parse().context("invalid layer cost")?),
+            None => (name, 1.0),
+        };
+        Ok(Layer {
+            name: name.to_string(),
+            chars: chars.to_string(),
+            cost,
+        })
+    }
+}
+
+/// Positions of a layout on `keyboard` without layers: its keys, then its combos.
+pub fn base_len(keyboard: &Keyboard) -> usize {
+    keyboard.keys.map.iter().flatten().count() + keyboard.combos.len()
+}
+
+/// `nstroke` with each of its positions moved to the layer given for it.
+fn on_layers(nstroke: &Nstroke, layers: &[usize], len: usize) -> Nstroke {
+    let at = |i: usize, p: usize| layers[i] * len + p;
+    match *nstroke {
+        Nstroke::Monostroke(a) => Nstroke::Monostroke(at(0, a)),
+        Nstroke::Bistroke([a, b]) => Nstroke::Bistroke([at(0, a), at(1, b)]),
+        Nstroke::Tristroke([a, b, c]) => Nstroke::Tristroke([at(0, a), at(1, b), at(2, c)]),
+    }
+}
+
+/// Copy every stroke of `metric_data` onto `layers`, and add the `layer`
+/// monogram metric, counting each layer's cost for each press of its keys.
+pub fn add_layers(metric_data: &mut MetricData, layers: &[Layer]) -> Result<()> {
+    ensure!(
+        !metric_data.metrics.iter().any(|m| m.short == "layer"),
+        "the layer metric is already defined"
+    );
+    let len = base_len(&metric_data.keyboard);
+    let copies = layers.len() + 1;
+    let metric = metric_data.metrics.len();
+    metric_data.metrics.push(Metric {
+        name: "Layer access".to_string(),
+        short: "layer".to_string(),
+        ngram_type: NgramType::Monogram,
+    });
+
+    let strokes = std::mem::take(&mut metric_data.strokes);
+    for stroke in strokes {
+        let keys = stroke.nstroke.to_vec().len();
+        for mut mix in 0..copies.pow(keys as u32) {
+            let on: Vec<usize> = (0..keys)
+                .map(|_| {
+                    let layer = mix % copies;
+                    mix /= copies;
+                    layer
+                })
+                .collect();
+            metric_data.strokes.push(NstrokeData {
+                nstroke: on_layers(&stroke.nstroke, &on, len),
+                amounts: stroke.amounts.clone(),
+            });
+        }
+    }
+
+    let monostrokes: HashMap<usize, usize> = metric_data
+        .strokes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| match s.nstroke {
+            Nstroke::Monostroke(p) => Some((p, i)),
+            _ => None,
+        })
+        .collect();
+    for (l, layer) in layers.iter().enumerate() {
+        for p in (l + 1) * len..(l + 2) * len {
+            let amount = MetricAmount {
+                metric,
+                amount: layer.cost,
+            };
+            match monostrokes.get(&p) {
+                Some(i) => metric_data.strokes[*i].amounts.push(amount),
+                None => metric_data.strokes.push(NstrokeData {
+                    nstroke: Nstroke::Monostroke(p),
+                    amounts: vec![amount],
+                }),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `base` followed by the characters of each of `layers`, empty keys filling
+/// out any layer shorter than the base.
+pub fn layered_layout(corpus: &Corpus, base: Layout, layers: &[Layer]) -> Result<Layout> {
+    let len = base.0.len();
+    let mut matrix = base.0;
+    for layer in layers {
+        let count = layer.chars.chars().count();
+        ensure!(
+            count <= len,
+            "layer {} has {count} characters but only {len} keys",
+            layer.name
+        );
+        matrix.extend(layer.chars.chars().map(|c| blank::corpus_char(corpus, c)));
+        matrix.extend(std::iter::repeat_n(0, len - count));
+    }
+    Ok(Layout(matrix))
+}
+
+/// Like `layout_stats`, with `layers` on every layout, for `metric_data`
+/// that `add_layers` has already been given them.
+pub fn layered_stats(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: &[LayoutData],
+    layers: &[Layer],
+) -> Result<Vec<LayoutStats>> {
+    let matrices: Result<Vec<Layout>> = layouts
+        .iter()
+        .map(|l| {
+            let base = MetricContext::layout_matrix(l, &metric_data.keyboard, &corpus)
+                .with_context(|| format!("layout {} incompatible with keyboard", l.name))?;
+            layered_layout(&corpus, base, layers)
+        })
+        .collect();
+    let matrices = matrices?;
+    let first = matrices
+        .first()
+        .context("need at least one layout to show stats for")?;
+    let totals = first.totals(&corpus);
+
+    let metrics = metric_data.metrics.clone();
+    let analyzer = Analyzer::from(kc_metric_data(metric_data, first.0.len()), corpus);
+    Ok(layouts
+        .iter()
+        .zip(&matrices)
+        .map(|(l, matrix)| LayoutStats {
+            name: l.name.clone(),
+            metrics: metrics
+                .iter()
+                .zip(analyzer.calc_stats(matrix))
+                .map(|(m, raw)| MetricStat {
+                    name: m.name.clone(),
+                    raw,
+                    percentage: totals.percentage(raw, m.ngram_type),
+                })
+                .collect(),
+        })
+        .collect())
+}
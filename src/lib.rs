@@ -0,0 +1,79 @@
+//! Keyboard layout analysis and generation on top of keycat and keymeow.
+//!
+//! [`Evaluator`] scores a layout's stats and [`OptimizationContext`] runs a
+//! [`GenerationStrategy`] over a layout with it, calling a [`Reporter`]'s
+//! hooks as it goes; the `keywhisker` binary is a command-line frontend to
+//! these modules.
+//!
+//! With the `python` feature the crate also builds a Python extension module,
+//! and with the `wasm` feature it builds for `wasm32-unknown-unknown`, where
+//! the terminal interfaces are left out.
+
+pub mod alt_fingering;
+pub mod analysis;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+pub mod blank;
+pub mod breakdown;
+pub mod cache;
+pub mod checkpoint;
+pub mod cluster;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod collect;
+pub mod combos;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compare;
+pub mod config;
+pub mod corpus;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dashboard;
+pub mod ddako {
+    pub mod simulated_annealing;
+}
+pub mod effort;
+pub mod evaluation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod explore;
+pub mod export;
+pub mod fingers;
+pub mod formats;
+pub mod heatmap;
+pub mod histogram;
+pub mod history;
+pub mod interrupt;
+pub mod keyboard;
+pub mod layers;
+pub mod lookup;
+pub mod metadata;
+pub mod naming;
+pub mod optimization;
+pub mod output_keys;
+pub mod pareto;
+pub mod percentiles;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod plugin;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod refine;
+pub mod report;
+pub mod reporter;
+pub mod robustness;
+pub mod running_stats;
+pub mod sampling;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod stratify;
+pub mod summary;
+pub mod survey;
+pub mod svg;
+pub mod template;
+pub mod transform;
+pub mod travel;
+pub mod trigrams;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use evaluation::{Evaluator, Reference};
+pub use optimization::{GenerationResult, GenerationStrategy, OptimizationContext};
+pub use reporter::Reporter;
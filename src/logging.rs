@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::Level;
+
+/// Send logs to stderr, or to `log_file`, at a level set by -v/-q counts.
+/// The default only shows warnings.
+pub fn init(verbose: u8, quiet: u8, log_file: Option<&Path>) -> Result<()> {
+    let level = match verbose as i8 - quiet as i8 {
+        i8::MIN..=-1 => Level::ERROR,
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let subscriber = tracing_subscriber::fmt().with_max_level(level);
+    match log_file {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("couldn't create log file {}", path.display()))?;
+            subscriber
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .init();
+        }
+        None => subscriber.with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
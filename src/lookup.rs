@@ -0,0 +1,98 @@
+use crate::keyboard::validate;
+
+use anyhow::{bail, ensure, Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricData};
+use km_data::Data as KeymeowData;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+
+/// Number of single-character insertions, deletions and substitutions
+/// turning `a` into `b`, ignoring case.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Check that `name`, of a `kind` of data saved under the data directory, is a
+/// plain file name, which can't reach outside the directory it's saved in.
+pub fn ensure_file_name(kind: &str, name: &str) -> Result<()> {
+    let mut components = Path::new(name).components();
+    ensure!(
+        matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ),
+        "can't save a {kind} named {name:?}: the name must be a plain file name"
+    );
+    Ok(())
+}
+
+/// A "did you mean" hint listing up to three candidates close to `name`,
+/// or an empty string when nothing is close.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    let limit = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= limit)
+        .collect();
+    close.sort();
+    close.dedup_by_key(|(_, c)| *c);
+    close.truncate(3);
+    match close.is_empty() {
+        true => String::new(),
+        false => {
+            let names: Vec<&str> = close.iter().map(|(_, c)| *c).collect();
+            format!(" (did you mean {}?)", names.join(", "))
+        }
+    }
+}
+
+fn ensure_known(kind: &str, name: &str, known: &HashMap<String, PathBuf>) -> Result<()> {
+    if !known.contains_key(name) {
+        bail!(
+            "no {kind} named {name}{}",
+            did_you_mean(name, known.keys().map(String::as_str))
+        );
+    }
+    Ok(())
+}
+
+pub fn corpus(data: &KeymeowData, name: &str) -> Result<Corpus> {
+    ensure_known("corpus", name, &data.corpora)?;
+    data.get_corpus(name)
+}
+
+/// Metric data for a known keyboard, or read from a keymeow metric data JSON
+/// file if `keyboard` is the path of one.
+pub fn metrics(data: &KeymeowData, keyboard: &str) -> Result<MetricData> {
+    let path = Path::new(keyboard);
+    if !data.keyboards.contains_key(keyboard) && path.is_file() {
+        let file = File::open(path).with_context(|| format!("couldn't open {keyboard}"))?;
+        let metric_data: MetricData = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("{keyboard} isn't keymeow metric data"))?;
+        validate(&metric_data).with_context(|| format!("invalid keyboard {keyboard}"))?;
+        return Ok(metric_data);
+    }
+    ensure_known("keyboard", keyboard, &data.keyboards)?;
+    data.get_metrics(keyboard)
+}
+
+pub fn layout(data: &KeymeowData, name: &str) -> Result<LayoutData> {
+    ensure_known("layout", name, &data.layouts)?;
+    data.get_layout(name)
+}
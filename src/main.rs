@@ -1,25 +1,65 @@
-mod analysis;
-mod ddako {
-    pub mod simulated_annealing;
-}
+mod batch;
+mod completions;
+mod logging;
+mod serve;
 
 use std::error::Error;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use analysis::{combos, output_table};
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use keycat::Corpus;
-use keymeow::LayoutData;
+use keymeow::{Finger, LayoutData};
+use keywhisker::analysis::{combos, GenerationOptions, Ngrams, StatsFormat, StatsOptions};
+use keywhisker::cache::MetricCache;
+use keywhisker::collect::{output_table, CollectOptions};
+use keywhisker::config::Config;
+use keywhisker::corpus::NgramKind;
+use keywhisker::fingers::Hand;
+use keywhisker::layers::Layer;
+use keywhisker::metadata::{Provenance, RunMetadata};
+use keywhisker::naming::home_row_name;
+use keywhisker::optimization::GenerationStrategy;
+use keywhisker::output_keys::{self, Output, OutputKey};
+use keywhisker::refine::Refine;
+use keywhisker::reporter::ProgressStyle;
+use keywhisker::stratify::Stratum;
+use keywhisker::transform::{load_transformed, transformed_chars, Transform};
+use keywhisker::{
+    alt_fingering, analysis, bench, blank, breakdown, cluster, combos, compare, corpus, effort,
+    explore, export, fingers, formats, heatmap, histogram, history, interrupt, keyboard, lookup,
+    pareto, percentiles, plugin, report, robustness, summary, survey, travel, trigrams, validate,
+};
 use km_data::Data as KeymeowData;
+use tracing::warn;
+
+/// The fingers usually typing each column of a 3x10 grid.
+const MATRIX_FINGERS: [Finger; 10] = [
+    Finger::LP,
+    Finger::LR,
+    Finger::LM,
+    Finger::LI,
+    Finger::LI,
+    Finger::RI,
+    Finger::RI,
+    Finger::RM,
+    Finger::RR,
+    Finger::RP,
+];
 
-pub fn print_matrix(letters: &[char]) {
+pub fn print_matrix(letters: &[char], color: bool) {
+    let letter = |col: usize, row: usize| match color {
+        true => fingers::paint(&MATRIX_FINGERS[col], &letters[col * 3 + row].to_string()),
+        false => letters[col * 3 + row].to_string(),
+    };
     for row in 0..3 {
         for col in 0..5 {
-            print!("{} ", letters[col * 3 + row]);
+            print!("{} ", letter(col, row));
         }
         print!(" ");
         for col in 5..10 {
-            print!("{} ", letters[col * 3 + row]);
+            print!("{} ", letter(col, row));
         }
         println!();
     }
@@ -30,57 +70,669 @@ pub fn print_matrix(letters: &[char]) {
 pub struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Read defaults from this file instead of ~/.config/keywhisker/config.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Load corpora, keyboards and layouts from this directory instead of downloading them
+    #[arg(long, global = true, env = "KEYWHISKER_DATA")]
+    data_dir: Option<PathBuf>,
+    /// Never touch the network; fail if the data directory is missing
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Log more to stderr; repeat for more detail
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Log less to stderr, and hide progress bars
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    quiet: u8,
+    /// Write logs to this file instead of stderr
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+}
+
+impl Cli {
+    fn progress_style(&self, progress: Option<ProgressStyle>) -> ProgressStyle {
+        // The dashboard draws on stdout, while other progress goes to stderr: a person
+        // still watches stderr when only stdout is piped, and a program reads it otherwise
+        let (stdout, stderr) = (
+            atty::is(atty::Stream::Stdout),
+            atty::is(atty::Stream::Stderr),
+        );
+        match (progress, self.quiet) {
+            (Some(style), _) => style,
+            (None, 0) if stdout && stderr => ProgressStyle::Tui,
+            (None, 0) if stderr => ProgressStyle::Plain,
+            (None, 0) => ProgressStyle::Json,
+            (None, _) => ProgressStyle::None,
+        }
+    }
+
+    fn load_data(&self, config: &Config) -> Result<KeymeowData> {
+        match self.data_dir.as_ref().or(config.data_dir.as_ref()) {
+            Some(dir) => {
+                if !dir.is_dir() {
+                    bail!("data directory {} doesn't exist", dir.display());
+                }
+                KeymeowData::from_dir(dir.clone())
+                    .with_context(|| format!("couldn't load data from {}", dir.display()))
+            }
+            None if self.offline => {
+                bail!("--offline needs a data directory from --data-dir or KEYWHISKER_DATA")
+            }
+            None => KeymeowData::with_download(),
+        }
+    }
 }
 
 #[derive(Args)]
 pub struct AnalysisArgs {
-    /// The corpus to use for analysis
+    /// The corpus to use for analysis; repeat as name:weight to blend several, whose
+    /// counts are then rescaled like those of `corpus merge`
+    #[arg(short, long, value_parser = parse_corpus_weight)]
+    corpus: Vec<(String, f32)>,
+    /// The keyboard to use for analysis, by name or as a path to keymeow metric data JSON
     #[arg(short, long)]
-    corpus: String,
-    /// The keyboard to use for analysis
-    #[arg(short, long)]
-    keyboard: String,
+    keyboard: Option<String>,
+    /// A TOML table of per-key effort, added as the "effort" metric
+    #[arg(long)]
+    effort: Option<PathBuf>,
+    /// Add "home" and "sftravel" finger travel metrics from the key coordinates
+    #[arg(long)]
+    travel: bool,
+    /// Count same-finger bigrams in adjacent columns, which can be alt-fingered, at this
+    /// fraction of their cost
+    #[arg(long)]
+    alt_fingering: Option<f32>,
+    /// A dynamic library adding its own metrics (see the plugin module); repeatable
+    #[arg(long = "plugin")]
+    plugins: Vec<PathBuf>,
+    /// A key typing two characters, as label=chars like &=th, with its label standing
+    /// for it in char sets and layouts; repeatable
+    #[arg(long)]
+    ngram_key: Vec<OutputKey>,
+    /// A key typing the character before it again, by the label standing for it in char
+    /// sets and layouts
+    #[arg(long)]
+    repeat_key: Option<char>,
 }
 
 impl AnalysisArgs {
-    pub fn get(&self, data: &KeymeowData) -> Result<(keycat::Corpus, keymeow::MetricData)> {
-        Ok((
-            data.get_corpus(&self.corpus)?,
-            data.get_metrics(&self.keyboard)?,
-        ))
+    pub fn get(
+        &self,
+        data: &KeymeowData,
+        config: &Config,
+    ) -> Result<(keycat::Corpus, keymeow::MetricData)> {
+        let keyboard = self
+            .keyboard(config)
+            .context("no keyboard given with -k or in the config file")?;
+        let mut metric_data = lookup::metrics(data, keyboard)?;
+        if let Some(path) = &self.effort {
+            effort::add_metric(&mut metric_data, &effort::EffortGrid::load(path)?)?;
+        }
+        if self.travel {
+            travel::add_metrics(&mut metric_data);
+        }
+        for path in &self.plugins {
+            plugin::add_metrics(&mut metric_data, path)?;
+        }
+        if let Some(factor) = self.alt_fingering {
+            alt_fingering::apply(&mut metric_data, factor)?;
+        }
+        Ok((self.get_corpus(data, config)?, metric_data))
+    }
+
+    /// Where metric data from these arguments is cached, if anywhere, noting
+    /// every file and option it's built from.
+    pub fn metric_cache(&self, data: &KeymeowData, config: &Config) -> Option<MetricCache> {
+        let keyboard = self.keyboard(config)?;
+        let mut cache = MetricCache::new(config.cache_dir()?).with(keyboard);
+        cache = match data.keyboards.get(keyboard) {
+            Some(path) => cache.with_file(path),
+            None => cache.with_file(keyboard),
+        };
+        if let Some(path) = &self.effort {
+            cache = cache.with("effort").with_file(path);
+        }
+        for path in &self.plugins {
+            cache = cache.with("plugin").with_file(path);
+        }
+        Some(
+            cache
+                .with(format!("travel {}", self.travel))
+                .with(format!("alt-fingering {:?}", self.alt_fingering)),
+        )
+    }
+
+    /// The keyboard given, or else the config file's.
+    pub fn keyboard<'a>(&'a self, config: &'a Config) -> Option<&'a str> {
+        self.keyboard.as_deref().or(config.keyboard.as_deref())
     }
+
+    /// The corpora given, or else the config file's, with their weights.
+    fn corpora(&self, config: &Config) -> Result<Vec<(String, f32)>> {
+        Ok(match self.corpus.as_slice() {
+            [] => {
+                let corpus: Result<Vec<_>, _> = config
+                    .corpus
+                    .iter()
+                    .map(|c| parse_corpus_weight(c))
+                    .collect();
+                corpus.map_err(|e| anyhow!("invalid corpus in config: {e}"))?
+            }
+            corpus => corpus.to_vec(),
+        })
+    }
+
+    /// The corpus and keyboard by name, and this command line, for generation metadata.
+    pub fn provenance(&self, config: &Config) -> Result<Provenance> {
+        let corpus: Vec<String> = match self.corpora(config)?.as_slice() {
+            [(name, _)] => vec![name.clone()],
+            corpora => corpora
+                .iter()
+                .map(|(name, weight)| format!("{name}:{weight}"))
+                .collect(),
+        };
+        Ok(Provenance {
+            command: std::env::args().collect(),
+            corpus: corpus.join(","),
+            keyboard: self.keyboard(config).unwrap_or_default().to_string(),
+        })
+    }
+
+    /// The corpus, or the blend of corpora, recounted for any ngram and repeat keys.
+    pub fn get_corpus(&self, data: &KeymeowData, config: &Config) -> Result<keycat::Corpus> {
+        let corpus = self.corpora(config)?;
+        let corpus = match corpus.as_slice() {
+            [] => bail!("no corpus given with -c or in the config file"),
+            [(name, _)] => lookup::corpus(data, name).context("couldn't load corpus")?,
+            corpora => {
+                let corpora: Result<Vec<_>> = corpora
+                    .iter()
+                    .map(|(name, weight)| Ok((lookup::corpus(data, name)?, *weight)))
+                    .collect();
+                corpus::merge(&corpora?)?
+            }
+        };
+        let repeat = self.repeat_key.map(|label| OutputKey {
+            label,
+            output: Output::Repeat,
+        });
+        let keys: Vec<OutputKey> = self.ngram_key.iter().cloned().chain(repeat).collect();
+        match keys.is_empty() {
+            true => Ok(corpus),
+            false => output_keys::apply(&corpus, &keys),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum EnvSection {
+    Corpora,
+    Keyboards,
+    Layouts,
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+enum ExportTarget {
+    /// QMK keymap.c
+    Qmk,
+    /// QMK configurator keymap.json
+    QmkJson,
+    /// keyboard-layout-editor.com JSON
+    Kle,
 }
 
 #[derive(ValueEnum, Debug, Clone)]
-enum GenerationStrategy {
-    GreedyDeterministic,
-    GreedyNaive,
-    SimulatedAnnealing,
-    DDAKOSimulatedAnnealing,
+enum LayoutFormat {
+    /// genkey .layout files
+    Genkey,
+    /// Oxeylyzer .kb files
+    Oxeylyzer,
+}
+
+#[derive(Subcommand)]
+enum LayoutCommands {
+    /// Convert a genkey or Oxeylyzer layout file into LayoutData JSON
+    Import {
+        #[clap(value_enum)]
+        format: LayoutFormat,
+        file: PathBuf,
+        /// The keyboard the layout is placed on
+        #[arg(short, long)]
+        keyboard: String,
+        /// Override the layout's name
+        #[arg(short, long)]
+        name: Option<String>,
+        #[arg(short, long)]
+        fixed: bool,
+    },
+    /// Write a layout in genkey or Oxeylyzer format
+    Export {
+        #[clap(value_enum)]
+        format: LayoutFormat,
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// The keyboard the layout is placed on
+        #[arg(short, long)]
+        keyboard: String,
+    },
+    /// Print a layout's characters after mirroring, angle or wide mods, or row swaps
+    Transform {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// mirror, angle, wide or swap-rows=A,B, applied in order
+        #[arg(required = true)]
+        transforms: Vec<Transform>,
+        /// The keyboard the layout is placed on
+        #[arg(short, long)]
+        keyboard: String,
+    },
+    /// Add a layout to the data directory so it can be used by name
+    Save {
+        chars: String,
+        #[arg(short, long)]
+        name: String,
+        /// The keyboard the layout is placed on
+        #[arg(short, long)]
+        keyboard: String,
+        #[arg(short, long)]
+        fixed: bool,
+        /// Replace an existing layout of the same name
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CorpusCommands {
+    /// Print a corpus' most frequent ngrams
+    Show {
+        name: String,
+        /// How many ngrams to print
+        #[arg(long, default_value_t = 50)]
+        top: usize,
+        #[arg(long, value_enum, default_value_t = NgramKind::Trigram)]
+        ngram: NgramKind,
+    },
+    /// Tokenize text files into a new corpus in the data directory
+    Build {
+        name: String,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Lowercase the text before counting
+        #[arg(long)]
+        fold_case: bool,
+        /// Drop ASCII punctuation before counting
+        #[arg(long)]
+        strip_punctuation: bool,
+        /// Only count these characters; others split ngrams
+        #[arg(long)]
+        chars: Option<String>,
+        /// Count shifted characters as their base key (capitals and US symbol pairs)
+        #[arg(long)]
+        shift: bool,
+        /// Use these unshifted/shifted symbol pairs instead, e.g. "1!2@"; implies --shift
+        #[arg(long)]
+        shift_pairs: Option<String>,
+    },
+    /// Drop rare trigrams from a corpus, so layouts aren't tuned to noise. Trigram
+    /// counts are stored densely, so the pruned corpus analyzes no faster
+    Prune {
+        name: String,
+        /// Drop trigrams seen fewer than this many times
+        #[arg(
+            long,
+            required_unless_present = "coverage",
+            conflicts_with = "coverage"
+        )]
+        min_count: Option<u32>,
+        /// Keep the most frequent trigrams making up this percentage of the corpus, e.g. 99.9%
+        #[arg(long, value_parser = parse_percentage)]
+        coverage: Option<f64>,
+        /// Name of the pruned corpus (defaults to <name>-pruned)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Blend existing corpora by weight into a new corpus, e.g. out=english:0.8,code:0.2.
+    /// Its counts are rescaled to a billion of each ngram type, not counts of any text
+    Merge {
+        #[arg(value_parser = parse_corpus_merge)]
+        spec: CorpusMerge,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectCommands {
+    /// Chart the distribution of each metric in a csv written by collect
+    View {
+        /// The csv to chart
+        path: PathBuf,
+        /// Plot two metrics against each other instead
+        #[arg(long, num_args = 2, value_names = ["X", "Y"])]
+        scatter: Vec<String>,
+        /// Mark where a layout (a known name, or its characters) falls
+        #[arg(short, long)]
+        layout: Option<String>,
+        /// Chart width in columns
+        #[arg(long, default_value_t = 60)]
+        width: usize,
+        /// Chart height in rows
+        #[arg(long, default_value_t = 8)]
+        height: usize,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Print each metric's mean, spread and percentiles in a csv written by collect,
+    /// and how the metrics correlate
+    Summarize {
+        /// The csv to summarize
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetricsCommands {
+    /// Show the strokes a metric counts on a keyboard, and how much
+    Show {
+        /// The metric's name or short name
+        name: String,
+        #[arg(short, long)]
+        keyboard: Option<String>,
+        /// How many of the strokes with the largest amounts to list
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        /// Draw each listed stroke's keys on the keyboard
+        #[arg(long)]
+        grid: bool,
+        /// A known layout name, or the layout's characters, to name keys by
+        #[arg(long)]
+        layout: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CombosCommands {
+    /// Suggest characters for unassigned combo slots
+    Suggest {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// How many suggestions to print
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Display information about the environment (e.g. available layouts, corpora)
-    Env,
+    Env {
+        /// Print the names as a JSON object
+        #[arg(long)]
+        json: bool,
+        /// Only list one kind of data, one name per line
+        #[arg(long, value_enum)]
+        filter: Option<EnvSection>,
+        /// Only list names containing this
+        pattern: Option<String>,
+    },
+    /// List the metrics available for a keyboard
+    #[command(args_conflicts_with_subcommands = true, visible_alias = "metric")]
+    Metrics {
+        #[command(subcommand)]
+        command: Option<MetricsCommands>,
+        #[arg(short, long)]
+        keyboard: Option<String>,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
     /// Collect metric data into a csv
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Collect {
+        #[command(subcommand)]
+        command: Option<CollectCommands>,
         /// The total number of layouts to analyze
-        count: u64,
-        /// The set of characters to use as keys in the randomized layouts
-        char_set: String,
+        #[arg(required = true)]
+        count: Option<u64>,
+        /// The set of characters to use as keys in the randomized layouts, `�` for a blank
+        /// key; left out with --keyboard-chars
+        #[arg(required_unless_present = "keyboard_chars")]
+        char_set: Option<String>,
+        /// Use the keyboard's char set from the config file
+        #[arg(long)]
+        keyboard_chars: bool,
+        /// Add this many blank keys to the char set
+        #[arg(long, default_value_t = 0)]
+        blanks: usize,
         /// The list of metrics to collect data for
         metrics: Vec<String>,
+        /// Where to write the csv, or - for stdout [default: data/data.csv]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Keep the rows already in the output and only add enough to reach the count
+        #[arg(long)]
+        append: bool,
+        /// Worker threads [default: available parallelism]
+        #[arg(long)]
+        threads: Option<u64>,
+        /// Skip shuffles already analyzed, so every row is a different layout
+        #[arg(long)]
+        unique: bool,
+        /// Number of positions to keep in place [default: the keyboard's pinned positions
+        /// from the config file, or none]
+        #[arg(short, long)]
+        pin: Option<usize>,
+        /// Only analyze layouts keeping to this constraint; repeatable
+        #[arg(long, value_enum)]
+        stratify: Vec<Stratum>,
+        /// Improve each layout with a short local search first, as greedy:N (N random
+        /// swaps tried) or steepest:N (N best swaps), to sample achievable layouts
+        #[arg(long, requires = "weights")]
+        refine: Option<Refine>,
+        /// The metric weights --refine reduces, e.g. sfb=3 rolls=-1
+        #[arg(
+            long,
+            value_parser = parse_key_val::<String, i16>,
+            num_args = 1..,
+            requires = "refine"
+        )]
+        weights: Vec<(String, i16)>,
         #[command(flatten)]
         analysis_args: AnalysisArgs,
     },
     Stats {
+        /// Known layout names, or layouts' characters
         layouts: Vec<String>,
+        /// Show each layout's score split into weighted per-metric terms
+        #[arg(long, value_parser = parse_key_val::<String, i16>, num_args = 1..)]
+        explain: Vec<(String, i16)>,
+        /// How to print the results
+        #[arg(long, value_enum, default_value_t = StatsFormat::Table)]
+        format: StatsFormat,
+        /// Print one line per layout from a template, e.g. "{name}\t{sfb:.2}"
+        #[arg(long, conflicts_with = "format")]
+        format_template: Option<String>,
+        /// Metrics where a larger value is better, for highlighting the table
+        #[arg(long, num_args = 1..)]
+        higher_is_better: Vec<String>,
+        /// Show each metric's weighted ngram count next to its percentage, and the corpus
+        /// totals they're percentages of
+        #[arg(long)]
+        raw: bool,
+        /// Show per-metric deltas between exactly two layouts instead
+        #[arg(long, conflicts_with = "breakdown")]
+        diff: bool,
+        /// List the ngrams contributing most to this metric instead
+        #[arg(long)]
+        breakdown: Option<String>,
+        /// Place each layout within a distribution written by `collect` instead
+        #[arg(long, conflicts_with_all = ["diff", "breakdown"])]
+        percentiles: Option<PathBuf>,
+        /// How many ngrams to list with --breakdown
+        #[arg(long, default_value_t = 20, requires = "breakdown")]
+        top: usize,
+        /// Transform every layout first: mirror, angle, wide or swap-rows=A,B; repeatable
+        #[arg(long = "transform")]
+        transforms: Vec<Transform>,
+        /// Only count strokes typed entirely by this hand
+        #[arg(long, value_enum)]
+        hand: Option<Hand>,
+        /// Add a layer to every layout, as name:cost=chars with the chars in key order,
+        /// counting its strokes and a "layer" access metric; repeatable
+        #[arg(long = "layer", conflicts_with_all = ["diff", "breakdown", "percentiles"])]
+        layers: Vec<Layer>,
         #[command(flatten)]
         analysis_args: AnalysisArgs,
     },
+    /// Write the requested metrics of every known layout to a csv, a row per layout
+    Survey {
+        /// The list of metrics to collect data for
+        #[arg(required = true)]
+        metrics: Vec<String>,
+        /// Where to write the csv, or - for stdout
+        #[arg(short, long, default_value = "data/survey.csv")]
+        output: PathBuf,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// List past generation runs from the run history, oldest first
+    History {
+        /// Only runs of this strategy, by name or initials (e.g. sa)
+        #[arg(long)]
+        strategy: Option<String>,
+        /// Only runs whose corpus name contains this
+        #[arg(long)]
+        corpus: Option<String>,
+        /// Only runs on this keyboard
+        #[arg(long)]
+        keyboard: Option<String>,
+        /// Only runs started on or after this date, as YYYY-MM-DD
+        #[arg(long)]
+        since: Option<String>,
+        /// Also print each run's best layouts
+        #[arg(long)]
+        best: bool,
+        /// How many of the latest matching runs to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Score every known layout and print them best first
+    Rank {
+        /// Metric weights, e.g. sfb=3 rolls=-1
+        #[arg(long, value_parser = parse_key_val::<String, i16>, num_args = 1.., required = true)]
+        metrics: Vec<(String, i16)>,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Show how much layouts' stats vary with the sample of text in the corpus, by
+    /// analyzing them on bootstrap resamples of its ngram counts
+    Robustness {
+        /// Known layout names, or layouts' characters; later ones are also compared with
+        /// the first
+        #[arg(required = true)]
+        layouts: Vec<String>,
+        /// Only show these metrics, e.g. sfb,rolls
+        #[arg(short, long, value_delimiter = ',')]
+        metrics: Vec<String>,
+        /// How many resampled corpora to analyze
+        #[arg(long, default_value_t = 200)]
+        samples: usize,
+        /// Resample as if the corpus were counted from this many characters of text;
+        /// needed for blended corpora, whose counts are rescaled
+        #[arg(long)]
+        sample_size: Option<u64>,
+        /// Share of resamples each interval covers
+        #[arg(long, default_value_t = 0.95)]
+        confidence: f64,
+        /// Seed for the resampling [default: random]
+        #[arg(long)]
+        seed: Option<u64>,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Show how much each key's placement costs compared to its best alternative
+    AnalyzeKeys {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// Metric weights, e.g. sfb=3 rolls=-1
+        #[arg(long, value_parser = parse_key_val::<String, i16>, num_args = 1.., required = true)]
+        metrics: Vec<(String, i16)>,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Interactively try swaps on a layout and export the result
+    Explore {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Compare several layouts side by side, toggling which are shown
+    Compare {
+        /// Known layout names, or layouts' characters
+        #[arg(required = true)]
+        layouts: Vec<String>,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Inspect and create corpora
     Corpus {
-        name: String,
+        #[command(subcommand)]
+        command: CorpusCommands,
+    },
+    /// Show how much each finger and hand is used by a layout
+    Fingers {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Show how a layout's trigrams split into rolls, alternates, redirects and so on
+    Trigrams {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// List a layout's same-finger bigrams that --alt-fingering counts as alt-fingered
+    AltFingering {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// How many bigrams to list
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Draw a layout as an SVG keyboard colored by key frequency or metric load
+    Heatmap {
+        /// A known layout name, or the layout's characters
+        layout: String,
+        /// Color keys by their share of this metric instead of by frequency
+        #[arg(short, long)]
+        metric: Option<String>,
+        /// The SVG file to write
+        #[arg(short, long, default_value = "heatmap.svg")]
+        output: PathBuf,
+        /// Print the heatmap to the terminal instead of writing an SVG
+        #[arg(short, long)]
+        terminal: bool,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Write a self-contained HTML report comparing layouts
+    Report {
+        /// Known layout names, or layouts' characters
+        layouts: Vec<String>,
+        /// The HTML file to write
+        #[arg(short, long, default_value = "report.html")]
+        output: PathBuf,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
     },
     RunGeneration {
         /// The number of generation runs to perform
@@ -88,22 +740,213 @@ enum Commands {
         /// The generation strategy to use
         #[clap(value_enum)]
         strategy: GenerationStrategy,
-        /// The set of characters to use as keys in the layout
-        char_set: String,
+        /// The set of characters to use as keys in the layout, `�` for a blank key; left
+        /// out with --keyboard-chars
+        #[arg(required_unless_present = "keyboard_chars")]
+        char_set: Option<String>,
+        /// Use the keyboard's char set from the config file
+        #[arg(long)]
+        keyboard_chars: bool,
+        /// Add this many blank keys to the char set
+        #[arg(long, default_value_t = 0)]
+        blanks: usize,
         /// The metric to reduce
         #[arg(value_parser = parse_key_val::<String, i16>)]
         metrics: Vec<(String, i16)>,
         /// If true, outputs tsv to stdout
         #[arg(short, long)]
         stdout: bool,
-        /// Number of positions to pin
+        /// Number of positions to pin [default: the keyboard's pinned positions from the
+        /// config file, or none]
+        #[arg(short, long)]
+        pin: Option<usize>,
+        /// Only place characters on this hand's keys, for one-handed layouts; --pin then
+        /// counts its keys
+        #[arg(long, value_enum)]
+        hand: Option<Hand>,
+        /// A layer of characters to place, as name:cost=chars, swapped only within the
+        /// layer; weight the "layer" metric to weigh reaching it; repeatable
+        #[arg(long = "layer", conflicts_with = "also_keyboard")]
+        layers: Vec<Layer>,
+        /// A known layout to report the best result's per-metric deltas against
         #[arg(short, long)]
+        reference: Option<String>,
+        /// Re-score the best layouts on this held-out corpus when done, flagging overfit ones
+        #[arg(long, conflicts_with = "layers")]
+        validate_corpus: Option<String>,
+        /// Relative worsening of score on the held-out corpus that counts as overfit
+        #[arg(long, default_value_t = 0.1)]
+        overfit_tolerance: f32,
+        /// Comma-separated metrics forming a priority tier; later tiers only break ties
+        #[arg(long)]
+        tier: Vec<String>,
+        /// Relative difference within which a tier counts as tied
+        #[arg(long, default_value_t = 0.001)]
+        tier_tolerance: f32,
+        /// Soft constraint as metric:percent:weight, penalizing usage above the threshold
+        #[arg(long, value_parser = parse_penalty)]
+        penalty: Vec<(String, f32, f32)>,
+        /// Only score by bigram metrics, leaving the rest out of the weights, penalties and
+        /// tiers, for much faster exploratory runs
+        #[arg(long, value_enum, default_value_t = Ngrams::All)]
+        ngrams: Ngrams,
+        /// Also write each run's layout as LayoutData JSON into this directory
+        #[arg(long)]
+        emit_layouts: Option<PathBuf>,
+        /// Only write the best N distinct layouts, treating mirror images as the same
+        #[arg(long)]
+        keep_top: Option<usize>,
+        /// Also swap characters in and out of combo slots, which chars past the keys fill,
+        /// and add the "combo" metric counting characters typed through them
+        #[arg(long)]
+        combos: bool,
+        /// Share of candidate swaps, 0 to 1, that move a key picked by how often its
+        /// character is typed and how much its strokes cost, instead of uniformly;
+        /// ignored by greedy-deterministic
+        #[arg(long, default_value_t = 0.0)]
+        swap_bias: f32,
+        /// Directory for the tsv and checkpoint [default: generations]
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+        /// Name for the tsv, instead of a timestamped one
+        #[arg(long, conflicts_with = "resume")]
+        run_name: Option<String>,
+        /// How to show progress while generating [default: tui on a terminal, plain when
+        /// only stderr is one, json when neither is, or none with -q]
+        #[arg(long, value_enum)]
+        progress: Option<ProgressStyle>,
+        /// Continue an interrupted ddako-simulated-annealing generation from its checkpoint
+        #[arg(long)]
+        resume: Option<PathBuf>,
+        /// Also score layouts on this keyboard, as name or name:weight, against the main
+        /// keyboard's weight of 1
+        #[arg(long, value_parser = parse_corpus_weight)]
+        also_keyboard: Vec<(String, f32)>,
+        /// Score layouts by their worst keyboard instead of the weighted average
+        #[arg(long, requires = "also_keyboard")]
+        worst_case: bool,
+        /// Run n is seeded with this plus n, recorded in the generation's metadata
+        /// [default: random]
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Leave blank keys where the char set puts them instead of moving characters
+        /// onto them
+        #[arg(long)]
+        keep_blanks: bool,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Run each generation strategy with the same seeds and compare their scores and times
+    BenchStrategies {
+        /// The set of characters to use as keys in the layout
+        char_set: String,
+        /// The metric weights to reduce, e.g. sfb=3 rolls=-1
+        #[arg(value_parser = parse_key_val::<String, i16>, required = true)]
+        metrics: Vec<(String, i16)>,
+        /// Runs per strategy
+        #[arg(long, default_value_t = 10)]
+        runs: u64,
+        /// Seconds per strategy, after which it starts no more runs
+        #[arg(long)]
+        seconds: Option<f64>,
+        /// Only bench this strategy; repeatable [default: all of them]
+        #[arg(long, value_enum)]
+        strategy: Vec<GenerationStrategy>,
+        /// Run n of every strategy is seeded with this plus n
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Number of positions to pin
+        #[arg(short, long, default_value_t = 0)]
         pin: usize,
+        /// Share of candidate swaps picked by frequency and cost; see run-generation
+        #[arg(long, default_value_t = 0.0)]
+        swap_bias: f32,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Measure how many swaps and evaluations per second the analyzer manages
+    Bench {
+        /// The set of characters to use as keys in the layout
+        char_set: String,
+        /// The metric weights to evaluate, e.g. sfb=3 rolls=-1
+        #[arg(value_parser = parse_key_val::<String, i16>, required = true)]
+        metrics: Vec<(String, i16)>,
+        /// Seconds to spend timing each operation
+        #[arg(long, default_value_t = 2.0)]
+        seconds: f64,
+        #[command(flatten)]
+        analysis_args: AnalysisArgs,
+    },
+    /// Run the generate and collect jobs listed in a TOML manifest
+    Batch {
+        manifest: PathBuf,
+        /// Each job writes into a directory of its name under this one
+        #[arg(short, long, default_value = "batch")]
+        output_dir: PathBuf,
+    },
+    /// Answer stats and generation requests over HTTP, keeping what they load in memory
+    Serve {
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// Requests handled at once [default: available parallelism]
+        #[arg(long)]
+        threads: Option<u64>,
+    },
+    /// Group near-duplicate layouts from a run-generation tsv
+    Cluster {
+        file: PathBuf,
+        /// Largest distance between a layout and its cluster's representative
+        #[arg(short, long, default_value_t = 4)]
+        threshold: usize,
+        /// Measure distance by finger assignment on this keyboard instead of by position
+        #[arg(short, long)]
+        keyboard: Option<String>,
+    },
+    /// Print the layouts from run-generation tsvs that no other is better than on every metric
+    Pareto {
+        /// The tsvs to merge, e.g. from several strategies or runs
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// The metric columns to compare, e.g. sfb,rolls
+        #[arg(short, long, required = true, value_delimiter = ',')]
+        metrics: Vec<String>,
+        /// Metrics where a larger value is better
+        #[arg(long, num_args = 1..)]
+        higher_is_better: Vec<String>,
+    },
+    /// Re-score the best layouts of a run-generation tsv on a held-out corpus, comparing
+    /// them with the training corpus given by -c
+    Validate {
+        file: PathBuf,
+        /// The held-out corpus
+        held_out: String,
+        /// The metric weights to score by [default: the generation's, from its metadata]
+        #[arg(value_parser = parse_key_val::<String, i16>)]
+        metrics: Vec<(String, i16)>,
+        /// How many of the best layouts to re-score
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        /// Relative worsening of score on the held-out corpus that counts as overfit
+        #[arg(long, default_value_t = 0.1)]
+        overfit_tolerance: f32,
         #[command(flatten)]
         analysis_args: AnalysisArgs,
     },
+    /// Print a layout's characters as a grid
     FormatLayout {
+        /// A known layout name (with --keyboard), or the layout's characters
         chars: String,
+        /// Draw the layout on this keyboard's keys and combos instead of a 3x10 grid
+        #[arg(short, long)]
+        keyboard: Option<String>,
+        /// Don't color keys by the finger typing them, which is also skipped when stdout
+        /// isn't a terminal or NO_COLOR is set. Without --keyboard, the 3x10 grid's fingers
+        /// are the usual ones for its columns, not read from any keyboard definition
+        #[arg(long)]
+        no_color: bool,
     },
     LayoutData {
         chars: String,
@@ -113,13 +956,57 @@ enum Commands {
         #[arg(short, long)]
         fixed: bool,
     },
-    Combos {
+    /// Import and export layouts in other analyzers' formats
+    Layout {
+        #[command(subcommand)]
+        command: LayoutCommands,
+    },
+    /// Convert a layout into a firmware or tool format
+    Export {
+        #[clap(value_enum)]
+        target: ExportTarget,
+        /// A known layout name, or the layout's characters
         layout: String,
+        /// The keyboard to lay the keys out on
+        #[arg(short, long)]
+        keyboard: String,
+        /// Where to write the result instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show the characters assigned to each combo, or suggest more
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Combos {
+        #[command(subcommand)]
+        command: Option<CombosCommands>,
+        /// A known layout name, or the layout's characters
+        #[arg(required = true)]
+        layout: Option<String>,
+        /// Rank combos by how often their keys are rolled in normal typing instead
+        #[arg(long)]
+        misfires: bool,
         #[command(flatten)]
         analysis_args: AnalysisArgs,
     },
 }
 
+/// With --keyboard-chars there's no char set argument, so what clap took for
+/// one is really the first metric, parsed with `parse`.
+fn shift_char_set<T: Clone>(
+    keyboard_chars: bool,
+    char_set: &Option<String>,
+    metrics: &[T],
+    parse: impl Fn(&str) -> Result<T>,
+) -> Result<(Option<String>, Vec<T>)> {
+    match char_set {
+        Some(first) if keyboard_chars => {
+            let metrics = iter::once(parse(first)?).chain(metrics.iter().cloned());
+            Ok((None, metrics.collect()))
+        }
+        _ => Ok((char_set.clone(), metrics.to_vec())),
+    }
+}
+
 // from https://docs.rs/clap/latest/clap/_derive/_cookbook/typed_derive/index.html
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
 where
@@ -134,68 +1021,761 @@ where
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
 
+fn parse_corpus_weight(s: &str) -> Result<(String, f32), Box<dyn Error + Send + Sync + 'static>> {
+    match s.rsplit_once(':') {
+        Some((name, weight)) => Ok((name.to_string(), weight.parse()?)),
+        None => Ok((s.to_string(), 1.0)),
+    }
+}
+
+type CorpusMerge = (String, Vec<(String, f32)>);
+
+fn parse_corpus_merge(s: &str) -> Result<CorpusMerge, Box<dyn Error + Send + Sync + 'static>> {
+    let (name, inputs) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected out=corpus:weight,... but got `{s}`"))?;
+    let inputs: Result<Vec<_>, _> = inputs.split(',').map(parse_corpus_weight).collect();
+    Ok((name.to_string(), inputs?))
+}
+
+/// A percentage between 0 and 100, with or without its `%`.
+fn parse_percentage(s: &str) -> Result<f64, Box<dyn Error + Send + Sync + 'static>> {
+    let percent: f64 = s.strip_suffix('%').unwrap_or(s).parse()?;
+    match (0.0..=100.0).contains(&percent) {
+        true => Ok(percent),
+        false => Err(format!("{s} isn't between 0% and 100%").into()),
+    }
+}
+
+type Penalty = (String, f32, f32);
+
+fn parse_penalty(s: &str) -> Result<Penalty, Box<dyn Error + Send + Sync + 'static>> {
+    match s.splitn(3, ':').collect::<Vec<_>>()[..] {
+        [metric, threshold, weight] => {
+            Ok((metric.to_string(), threshold.parse()?, weight.parse()?))
+        }
+        _ => Err(format!("invalid metric:percent:weight penalty `{s}`").into()),
+    }
+}
+
+/// Every known layout by name, skipping any that can't be loaded.
+fn known_layouts(keymeow: &KeymeowData) -> Vec<LayoutData> {
+    let mut names: Vec<_> = keymeow.layouts.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| match keymeow.get_layout(name) {
+            Ok(layout) => Some(layout),
+            Err(e) => {
+                warn!("skipping {name}: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
 fn main() -> Result<()> {
-    let keymeow = KeymeowData::with_download()?;
     let cli = Cli::parse();
+    logging::init(cli.verbose, cli.quiet, cli.log_file.as_deref())?;
+    let config = Config::load(cli.config.as_deref())?;
+    let keymeow = cli.load_data(&config)?;
 
     match &cli.command {
-        Some(Commands::Env) => {
-            println!("Corpora: {:?}", keymeow.corpora.keys().collect::<Vec<_>>());
-            println!(
-                "Keyboards: {:?}",
-                keymeow.keyboards.keys().collect::<Vec<_>>()
-            );
-            println!("Layouts: {:?}", keymeow.layouts.keys().collect::<Vec<_>>());
+        Some(Commands::Env {
+            json,
+            filter,
+            pattern,
+        }) => {
+            let sections = [
+                (EnvSection::Corpora, "corpora", "Corpora", &keymeow.corpora),
+                (
+                    EnvSection::Keyboards,
+                    "keyboards",
+                    "Keyboards",
+                    &keymeow.keyboards,
+                ),
+                (EnvSection::Layouts, "layouts", "Layouts", &keymeow.layouts),
+            ];
+            let mut listing = serde_json::Map::new();
+            for (section, key, title, map) in sections {
+                if filter.is_some_and(|f| f != section) {
+                    continue;
+                }
+                let mut names: Vec<&String> = map
+                    .keys()
+                    .filter(|name| pattern.as_ref().is_none_or(|p| name.contains(p.as_str())))
+                    .collect();
+                names.sort();
+                match (json, filter) {
+                    (true, _) => {
+                        listing.insert(key.to_string(), serde_json::to_value(names)?);
+                    }
+                    (false, Some(_)) => names.iter().for_each(|name| println!("{name}")),
+                    (false, None) => println!("{title}: {names:?}"),
+                }
+            }
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&listing)?);
+            }
+        }
+        Some(Commands::Metrics {
+            command: None,
+            keyboard,
+        }) => {
+            let keyboard = keyboard
+                .as_ref()
+                .or(config.keyboard.as_ref())
+                .context("no keyboard given with -k or in the config file")?;
+            analysis::list_metrics(&lookup::metrics(&keymeow, keyboard)?);
+        }
+        Some(Commands::Metrics {
+            command:
+                Some(MetricsCommands::Show {
+                    name,
+                    keyboard,
+                    top,
+                    grid,
+                    layout,
+                }),
+            ..
+        }) => {
+            let keyboard = keyboard
+                .as_ref()
+                .or(config.keyboard.as_ref())
+                .context("no keyboard given with -k or in the config file")?;
+            let metric_data = lookup::metrics(&keymeow, keyboard)?;
+            let metric = analysis::get_metric(name, &metric_data)?;
+            let chars = match layout {
+                Some(layout) => Some(export::layout_chars(
+                    &keymeow,
+                    layout,
+                    &metric_data.keyboard,
+                )?),
+                None => None,
+            };
+            analysis::show_metric(&metric_data, metric, *top, *grid, chars.as_deref());
         }
+        Some(Commands::Completions { shell }) => {
+            completions::print_completions(*shell, &keymeow);
+        }
+        Some(Commands::Collect {
+            command:
+                Some(CollectCommands::View {
+                    path,
+                    scatter,
+                    layout,
+                    width,
+                    height,
+                    analysis_args,
+                }),
+            ..
+        }) => {
+            let marker = match layout {
+                Some(layout) => {
+                    let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+                    let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+                    let columns = histogram::columns(path)?;
+                    Some(histogram::Marker::new(
+                        metric_data,
+                        corpus,
+                        layout,
+                        &columns,
+                    )?)
+                }
+                None => None,
+            };
+            let scatter = match &scatter[..] {
+                [x, y] => Some((x.as_str(), y.as_str())),
+                _ => None,
+            };
+            histogram::view(path, scatter, *width, *height, marker.as_ref())?;
+        }
+        Some(Commands::Collect {
+            command: Some(CollectCommands::Summarize { path }),
+            ..
+        }) => summary::summarize(path)?,
         Some(Commands::Collect {
+            command: None,
             count,
             char_set,
+            keyboard_chars,
+            blanks,
             metrics,
+            output,
+            append,
+            threads,
+            unique,
+            pin,
+            stratify,
+            refine,
+            weights,
             analysis_args,
         }) => {
-            let (corpus, metric_data) = analysis_args.get(&keymeow)?;
-            output_table(metrics.to_owned(), metric_data, corpus, *count, char_set)?
+            let Some(count) = count else {
+                unreachable!("clap requires a count without a subcommand");
+            };
+            let (char_set, metrics) =
+                shift_char_set(*keyboard_chars, char_set, metrics, |m| Ok(m.to_string()))?;
+            let keyboard = analysis_args.keyboard(&config);
+            let char_set = config.char_set(keyboard, char_set.as_deref())?;
+            let char_set = &blank::char_set(&char_set, *blanks);
+            let (mut corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            blank::clear_for(&mut corpus, char_set);
+            corpus::check_coverage(&corpus, char_set)?;
+            interrupt::install()?;
+            let output = output
+                .clone()
+                .or(config.collect_output.clone())
+                .unwrap_or_else(|| PathBuf::from("data/data.csv"));
+            let cache = analysis_args.metric_cache(&keymeow, &config);
+            let threads = threads.unwrap_or_else(|| config.threads());
+            let options = CollectOptions {
+                append: *append,
+                unique: *unique,
+                pin: pin.unwrap_or(0),
+                pinned: &config.pinned(keyboard, *pin),
+                strata: stratify,
+                refine: refine.map(|refine| (refine, weights.as_slice())),
+                cache: cache.as_ref(),
+                ..CollectOptions::new(*count, char_set, &output, threads)
+            };
+            output_table(metrics, metric_data, corpus, options)?
         }
         Some(Commands::Stats {
+            layouts,
+            explain,
+            format,
+            format_template,
+            higher_is_better,
+            raw,
+            diff,
+            breakdown,
+            percentiles,
+            top,
+            transforms,
+            hand,
+            layers,
+            analysis_args,
+        }) => {
+            let (corpus, mut metric_data) = analysis_args.get(&keymeow, &config)?;
+            if let Some(hand) = hand {
+                metric_data = keyboard::one_hand(metric_data, *hand);
+            }
+            let layouts: Result<Vec<_>> = layouts
+                .iter()
+                .map(|l| load_transformed(&keymeow, l, &metric_data.keyboard, transforms))
+                .collect();
+            match (breakdown, percentiles) {
+                (Some(metric), _) => {
+                    breakdown::breakdown(metric_data, corpus, layouts?, metric, *top)?
+                }
+                (None, Some(path)) => {
+                    percentiles::percentiles(metric_data, corpus, layouts?, path, higher_is_better)?
+                }
+                (None, None) if *diff => {
+                    analysis::diff(metric_data, corpus, layouts?, higher_is_better)?
+                }
+                (None, None) => {
+                    let options = StatsOptions {
+                        layers,
+                        explain,
+                        format: format.clone(),
+                        format_template: format_template.as_deref(),
+                        higher_is_better,
+                        raw: *raw,
+                    };
+                    analysis::stats(metric_data, corpus, layouts?, options)?
+                }
+            }
+        }
+        Some(Commands::Rank {
+            metrics,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            analysis::rank(metrics, metric_data, corpus, known_layouts(&keymeow))?;
+        }
+        Some(Commands::Robustness {
+            layouts,
+            metrics,
+            samples,
+            sample_size,
+            confidence,
+            seed,
+            analysis_args,
+        }) => {
+            ensure!(
+                0.0 < *confidence && *confidence < 1.0,
+                "confidence must be between 0 and 1"
+            );
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layouts: Result<Vec<_>> = layouts
+                .iter()
+                .map(|l| export::load_layout(&keymeow, l, &metric_data.keyboard))
+                .collect();
+            robustness::robustness(
+                metric_data,
+                corpus,
+                &layouts?,
+                metrics,
+                &robustness::Resampling {
+                    samples: *samples,
+                    sample_size: *sample_size,
+                    seed: seed.unwrap_or_else(rand::random),
+                },
+                *confidence,
+            )?;
+        }
+        Some(Commands::History {
+            strategy,
+            corpus,
+            keyboard,
+            since,
+            best,
+            limit,
+        }) => {
+            let path = config
+                .history_file()
+                .context("no history file configured")?;
+            let filter = history::Filter {
+                strategy: strategy.clone(),
+                corpus: corpus.clone(),
+                keyboard: keyboard.clone(),
+                since: since.clone(),
+            };
+            history::print(&history::load(&path)?, &filter, *limit, *best);
+        }
+        Some(Commands::Survey {
+            metrics,
+            output,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            survey::survey(
+                metrics,
+                metric_data,
+                corpus,
+                known_layouts(&keymeow),
+                output,
+            )?;
+        }
+        Some(Commands::AnalyzeKeys {
+            layout,
+            metrics,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            analysis::analyze_keys(metrics, metric_data, corpus, layout)?;
+        }
+        Some(Commands::Explore {
+            layout,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            explore::explore(metric_data, corpus, layout)?;
+        }
+        Some(Commands::Compare {
             layouts,
             analysis_args,
         }) => {
-            let (corpus, metric_data) = analysis_args.get(&keymeow)?;
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
             let layouts: Result<Vec<_>> = layouts
                 .iter()
-                .map(|l| keymeow.get_layout(l).context("couldn't load layout"))
+                .map(|l| export::load_layout(&keymeow, l, &metric_data.keyboard))
                 .collect();
-            analysis::stats(metric_data, corpus, layouts?)?;
+            compare::compare(metric_data, corpus, layouts?)?;
         }
-        Some(Commands::Corpus { name }) => {
-            let corpus = keymeow.get_corpus(name)?;
-            println!("{:?}", corpus.trigrams);
-            println!("Size: {:?} bytes", std::mem::size_of_val(&*corpus.trigrams));
-            println!("Length: {:?}", corpus.trigrams.len());
+        Some(Commands::Report {
+            layouts,
+            output,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layouts: Result<Vec<_>> = layouts
+                .iter()
+                .map(|l| export::load_layout(&keymeow, l, &metric_data.keyboard))
+                .collect();
+            report::report(metric_data, corpus, layouts?, output)?;
+        }
+        Some(Commands::Fingers {
+            layout,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            fingers::print_fingers(metric_data, corpus, layout)?;
         }
+        Some(Commands::Trigrams {
+            layout,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            trigrams::trigram_summary(metric_data, corpus, layout)?;
+        }
+        Some(Commands::AltFingering {
+            layout,
+            top,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            alt_fingering::report(metric_data, corpus, layout, *top)?;
+        }
+        Some(Commands::Heatmap {
+            layout,
+            metric,
+            output,
+            terminal,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            match terminal {
+                true => heatmap::terminal_heatmap(metric_data, corpus, layout, metric.as_deref())?,
+                false => {
+                    heatmap::svg_heatmap(metric_data, corpus, layout, metric.as_deref(), output)?
+                }
+            }
+        }
+        Some(Commands::Corpus { command }) => match command {
+            CorpusCommands::Show { name, top, ngram } => {
+                let corpus = lookup::corpus(&keymeow, name)?;
+                for (ngram, percentage) in corpus::top_ngrams(&corpus, *ngram, *top) {
+                    println!("{ngram:?}\t{percentage:.3}%");
+                }
+            }
+            CorpusCommands::Build {
+                name,
+                files,
+                fold_case,
+                strip_punctuation,
+                chars,
+                shift,
+                shift_pairs,
+            } => {
+                let shift_pairs = match (shift, shift_pairs) {
+                    (_, Some(pairs)) => Some(pairs.clone()),
+                    (true, None) => Some(corpus::US_SHIFT_PAIRS.to_string()),
+                    (false, None) => None,
+                };
+                let options = corpus::BuildOptions {
+                    fold_case: *fold_case,
+                    strip_punctuation: *strip_punctuation,
+                    whitelist: chars.clone(),
+                    shift_pairs,
+                };
+                let built = corpus::build(files, &options)?;
+                let path = corpus::save(&keymeow, name, &built)?;
+                println!("Wrote {}", path.display());
+            }
+            CorpusCommands::Prune {
+                name,
+                min_count,
+                coverage,
+                output,
+            } => {
+                let mut pruned = lookup::corpus(&keymeow, name)?;
+                let min_count = match (min_count, coverage) {
+                    (Some(n), _) => *n,
+                    (None, Some(percent)) => corpus::coverage_min_count(&pruned, *percent),
+                    (None, None) => unreachable!(),
+                };
+                let dropped = corpus::prune(&mut pruned, min_count);
+                println!("Dropped {dropped} trigrams seen fewer than {min_count} times");
+                let output = output.clone().unwrap_or_else(|| format!("{name}-pruned"));
+                let path = corpus::save(&keymeow, &output, &pruned)?;
+                println!("Wrote {}", path.display());
+            }
+            CorpusCommands::Merge {
+                spec: (name, inputs),
+            } => {
+                let corpora: Result<Vec<_>> = inputs
+                    .iter()
+                    .map(|(name, weight)| Ok((lookup::corpus(&keymeow, name)?, *weight)))
+                    .collect();
+                let merged = corpus::merge(&corpora?)?;
+                let path = corpus::save(&keymeow, name, &merged)?;
+                println!("Wrote {}", path.display());
+            }
+        },
         Some(Commands::RunGeneration {
             runs,
             strategy,
             char_set,
+            keyboard_chars,
+            blanks,
             metrics,
             stdout,
             analysis_args,
             pin,
+            hand,
+            layers,
+            reference,
+            validate_corpus,
+            overfit_tolerance,
+            tier,
+            tier_tolerance,
+            penalty,
+            ngrams,
+            emit_layouts,
+            keep_top,
+            combos,
+            swap_bias,
+            output_dir,
+            run_name,
+            progress,
+            resume,
+            also_keyboard,
+            worst_case,
+            seed,
+            keep_blanks,
         }) => {
-            let (corpus, metric_data) = analysis_args.get(&keymeow)?;
-            crate::analysis::output_generation(
-                metrics,
+            let (char_set, metrics) = shift_char_set(*keyboard_chars, char_set, metrics, |m| {
+                parse_key_val(m).map_err(|e| anyhow!("invalid metric weight: {e}"))
+            })?;
+            let keyboard = analysis_args.keyboard(&config);
+            let char_set = config.char_set(keyboard, char_set.as_deref())?;
+            let char_set = &blank::char_set(&char_set, *blanks);
+            let (mut corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            blank::clear_for(&mut corpus, char_set);
+            corpus::check_coverage(&corpus, char_set)?;
+            let tier_names = tier.iter().flat_map(|t| t.split(',').map(str::trim));
+            let dropped = ngrams.dropped(
+                metrics
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .chain(penalty.iter().map(|(name, _, _)| name.as_str()))
+                    .chain(tier_names),
+                &metric_data,
+            );
+            let kept = |name: &str| !dropped.iter().any(|d| d == name);
+            let mut metrics = metrics;
+            let mut penalty = penalty.clone();
+            metrics.retain(|(name, _)| kept(name));
+            penalty.retain(|(name, _, _)| kept(name));
+            // Tiers keep their order, less any left empty
+            let tier: Vec<String> = tier
+                .iter()
+                .map(|t| {
+                    t.split(',')
+                        .map(str::trim)
+                        .filter(|n| kept(n))
+                        .collect::<Vec<_>>()
+                })
+                .filter(|names| !names.is_empty())
+                .map(|names| names.join(","))
+                .collect();
+            ensure!(
+                !metrics.is_empty() || *ngrams == Ngrams::All,
+                "none of the metrics are bigram metrics"
+            );
+            let keyboards: Result<Vec<_>> = also_keyboard
+                .iter()
+                .map(|(name, weight)| Ok((name.clone(), lookup::metrics(&keymeow, name)?, *weight)))
+                .collect();
+            interrupt::install()?;
+            let reference = match reference {
+                Some(name) => Some(export::load_layout(&keymeow, name, &metric_data.keyboard)?),
+                None => None,
+            };
+            let held_out = match validate_corpus {
+                Some(name) => {
+                    let held_out = lookup::corpus(&keymeow, name)?;
+                    corpus::check_coverage(&held_out, char_set)?;
+                    Some((held_out, *overfit_tolerance))
+                }
+                None => None,
+            };
+            let options = GenerationOptions {
+                metrics: &metrics,
+                char_set,
+                strategy: strategy.clone(),
+                keyboards: keyboards?,
+                worst_case: *worst_case,
+                pin: pin.unwrap_or(0),
+                pinned: &config.pinned(keyboard, *pin),
+                hand: *hand,
+                layers,
+                runs: *runs,
+                use_stdout: *stdout,
+                reference,
+                held_out,
+                tiers: &tier,
+                tier_tolerance: *tier_tolerance,
+                penalties: &penalty,
+                emit_layouts: emit_layouts.as_deref(),
+                keep_top: *keep_top,
+                combos: *combos,
+                swap_bias: *swap_bias,
+                keep_blanks: *keep_blanks,
+                seed: *seed,
+                output_dir: output_dir.as_deref(),
+                run_name: run_name.as_deref(),
+                resume: resume.as_deref(),
+                progress_style: cli.progress_style(*progress),
+                provenance: analysis_args.provenance(&config)?,
+                cache: analysis_args.metric_cache(&keymeow, &config),
+            };
+            analysis::output_generation(metric_data, corpus, options, &config)?;
+        }
+        Some(Commands::BenchStrategies {
+            char_set,
+            metrics,
+            runs,
+            seconds,
+            strategy,
+            seed,
+            pin,
+            swap_bias,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            corpus::check_coverage(&corpus, char_set)?;
+            interrupt::install()?;
+            let strategies = match strategy.as_slice() {
+                [] => GenerationStrategy::value_variants().to_vec(),
+                chosen => chosen.to_vec(),
+            };
+            let cache = analysis_args.metric_cache(&keymeow, &config);
+            let budget = seconds.map(Duration::from_secs_f64);
+            bench::bench_strategies(&strategies, *runs, budget, |strategy| {
+                let context = analysis::weighted_context(
+                    metric_data.clone(),
+                    corpus.clone(),
+                    metrics,
+                    char_set,
+                    strategy,
+                    cache.as_ref(),
+                )?;
+                context
+                    .with_swaps(*pin, false)
+                    .with_annealing(config.annealing.clone())
+                    .with_seed(*seed)
+                    .with_swap_bias(*swap_bias)
+            })?;
+        }
+        Some(Commands::Bench {
+            char_set,
+            metrics,
+            seconds,
+            analysis_args,
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            corpus::check_coverage(&corpus, char_set)?;
+            let context = analysis::weighted_context(
                 metric_data,
                 corpus,
+                metrics,
                 char_set,
-                strategy,
-                *pin,
-                *runs,
-                *stdout,
+                GenerationStrategy::GreedyNaive,
+                analysis_args.metric_cache(&keymeow, &config).as_ref(),
             )?;
+            let seconds = Duration::try_from_secs_f64(*seconds).context("invalid --seconds")?;
+            bench::bench_throughput(&context, seconds)?;
         }
-        Some(Commands::FormatLayout { chars }) => {
-            print_matrix(chars.chars().collect::<Vec<_>>().as_ref());
+        Some(Commands::Batch {
+            manifest,
+            output_dir,
+        }) => {
+            batch::run(
+                manifest,
+                output_dir,
+                &keymeow,
+                &config,
+                cli.progress_style(None),
+            )?;
+        }
+        Some(Commands::Serve {
+            host,
+            port,
+            threads,
+        }) => {
+            interrupt::install()?;
+            let threads = threads.unwrap_or_else(|| config.threads());
+            serve::serve(&keymeow, &format!("{host}:{port}"), threads)?;
+        }
+        Some(Commands::Cluster {
+            file,
+            threshold,
+            keyboard,
+        }) => {
+            let keyboard = match keyboard {
+                Some(name) => Some(lookup::metrics(&keymeow, name)?.keyboard),
+                None => None,
+            };
+            cluster::cluster(file, *threshold, keyboard.as_ref())?;
+        }
+        Some(Commands::Pareto {
+            files,
+            metrics,
+            higher_is_better,
+        }) => pareto::pareto(files, metrics, higher_is_better)?,
+        Some(Commands::Validate {
+            file,
+            held_out,
+            metrics,
+            top,
+            overfit_tolerance,
+            analysis_args,
+        }) => {
+            let (train, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let test = lookup::corpus(&keymeow, held_out)?;
+            let metrics = match metrics.as_slice() {
+                [] => {
+                    let dir = file.parent().unwrap_or(Path::new(""));
+                    let name = file
+                        .file_stem()
+                        .context("no generation name")?
+                        .to_string_lossy();
+                    RunMetadata::load(&RunMetadata::path(dir, &name))
+                        .context("no metrics given and no generation metadata to find them in")?
+                        .metrics
+                }
+                metrics => metrics.to_vec(),
+            };
+            let results = validate::best_results(file, *top)?;
+            let results = results.iter().map(String::as_str);
+            let layouts = validate::generated_layouts(&metric_data.keyboard, results);
+            validate::validate(
+                &metrics,
+                metric_data,
+                train,
+                test,
+                &layouts,
+                *overfit_tolerance,
+                &mut std::io::stdout(),
+            )?;
+        }
+        Some(Commands::FormatLayout {
+            chars,
+            keyboard,
+            no_color,
+        }) => {
+            let color = !no_color
+                && atty::is(atty::Stream::Stdout)
+                && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+            match keyboard {
+                Some(keyboard) => {
+                    let keyboard = lookup::metrics(&keymeow, keyboard)?.keyboard;
+                    let labels: Vec<String> = export::layout_chars(&keymeow, chars, &keyboard)?
+                        .iter()
+                        .map(|c| match *c {
+                            blank::NONE => String::new(),
+                            ' ' => "␣".to_string(),
+                            c => c.to_string(),
+                        })
+                        .collect();
+                    let lines = match color {
+                        true => keyboard::finger_colored_text(&keyboard, &labels),
+                        false => keyboard::keyboard_text(&keyboard, &labels),
+                    };
+                    lines.iter().for_each(|line| println!("{line}"));
+                }
+                None => print_matrix(chars.chars().collect::<Vec<_>>().as_ref(), color),
+            }
         }
         Some(Commands::LayoutData {
             chars,
@@ -203,35 +1783,131 @@ fn main() -> Result<()> {
             name,
             fixed,
         }) => {
-            let corpus = Corpus::with_char_list(chars.chars().map(|c| vec![c]).collect());
-            let metrics = keymeow.get_metrics(keyboard)?;
-            let layout = keycat::Layout(
-                chars
-                    .chars()
-                    .map(|c| match c {
-                        '�' => 0,
-                        _ => corpus.corpus_char(c),
-                    })
-                    .collect(),
-            );
-            let data = if *fixed {
-                LayoutData::fixed_from_layout(&layout, &corpus)
-            } else {
-                LayoutData::flexible_from_keyboard_layout(&metrics.keyboard, &layout, &corpus)
-            }
-            .name(match name {
-                Some(name) => name.to_owned(),
-                None => "Custom".to_string(),
-            });
+            let metrics = lookup::metrics(&keymeow, keyboard)?;
+            let name = match name {
+                Some(name) => name.clone(),
+                None => home_row_name(&metrics.keyboard, &chars.chars().collect::<Vec<_>>()),
+            };
+            let data = export::layout_data(chars, &metrics.keyboard, &name, *fixed);
             println!("{}", serde_json::to_string_pretty(&data)?);
         }
+        Some(Commands::Layout { command }) => match command {
+            LayoutCommands::Import {
+                format,
+                file,
+                keyboard,
+                name,
+                fixed,
+            } => {
+                let text = std::fs::read_to_string(file).context("couldn't read layout file")?;
+                let (file_name, chars) = match format {
+                    LayoutFormat::Genkey => formats::parse_genkey(&text)?,
+                    LayoutFormat::Oxeylyzer => (
+                        file.file_stem()
+                            .map_or("Custom".into(), |s| s.to_string_lossy().into_owned()),
+                        formats::parse_oxeylyzer(&text)?,
+                    ),
+                };
+                let metrics = lookup::metrics(&keymeow, keyboard)?;
+                let name = name.as_deref().unwrap_or(&file_name);
+                let data = export::layout_data(&chars, &metrics.keyboard, name, *fixed);
+                println!("{}", serde_json::to_string_pretty(&data)?);
+            }
+            LayoutCommands::Export {
+                format,
+                layout,
+                keyboard,
+            } => {
+                let metrics = lookup::metrics(&keymeow, keyboard)?;
+                let chars = export::layout_chars(&keymeow, layout, &metrics.keyboard)?;
+                match format {
+                    LayoutFormat::Genkey => {
+                        let fingers: Vec<Finger> = metrics
+                            .keyboard
+                            .fingers
+                            .map
+                            .iter()
+                            .flatten()
+                            .copied()
+                            .collect();
+                        print!("{}", formats::to_genkey(layout, &chars, &fingers));
+                    }
+                    LayoutFormat::Oxeylyzer => print!("{}", formats::to_oxeylyzer(&chars)),
+                }
+            }
+            LayoutCommands::Transform {
+                layout,
+                transforms,
+                keyboard,
+            } => {
+                let metrics = lookup::metrics(&keymeow, keyboard)?;
+                let keyboard = &metrics.keyboard;
+                println!(
+                    "{}",
+                    transformed_chars(&keymeow, layout, keyboard, transforms)?
+                );
+            }
+            LayoutCommands::Save {
+                chars,
+                name,
+                keyboard,
+                fixed,
+                force,
+            } => {
+                let metrics = lookup::metrics(&keymeow, keyboard)?;
+                let data = export::layout_data(chars, &metrics.keyboard, name, *fixed);
+                let path = export::save_layout(&keymeow, &data, *force)?;
+                println!("Wrote {}", path.display());
+            }
+        },
+        Some(Commands::Export {
+            target,
+            layout,
+            keyboard,
+            output,
+        }) => {
+            let metrics = lookup::metrics(&keymeow, keyboard)?;
+            let chars = export::layout_chars(&keymeow, layout, &metrics.keyboard)?;
+            let text = match target {
+                ExportTarget::Qmk => export::qmk_keymap_c(&metrics.keyboard, &chars),
+                ExportTarget::QmkJson => {
+                    export::qmk_keymap_json(&metrics.keyboard, &chars, keyboard)?
+                }
+                ExportTarget::Kle => export::kle_json(&metrics.keyboard, &chars)?,
+            };
+            match output {
+                Some(path) => std::fs::write(path, text).context("couldn't write export")?,
+                None => print!("{text}"),
+            }
+        }
         Some(Commands::Combos {
+            command: None,
             layout,
+            misfires,
             analysis_args,
         }) => {
-            let (corpus, metric_data) = analysis_args.get(&keymeow)?;
-            let layout = keymeow.get_layout(layout)?;
-            combos(metric_data, corpus, layout)?;
+            let Some(layout) = layout else {
+                unreachable!("clap requires a layout without a subcommand");
+            };
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            match misfires {
+                true => combos::misfires(metric_data, corpus, layout)?,
+                false => combos(metric_data, corpus, layout)?,
+            }
+        }
+        Some(Commands::Combos {
+            command:
+                Some(CombosCommands::Suggest {
+                    layout,
+                    top,
+                    analysis_args,
+                }),
+            ..
+        }) => {
+            let (corpus, metric_data) = analysis_args.get(&keymeow, &config)?;
+            let layout = export::load_layout(&keymeow, layout, &metric_data.keyboard)?;
+            combos::suggest(metric_data, corpus, layout, *top)?;
         }
         None => {}
     };
@@ -0,0 +1,82 @@
+use crate::analysis::utc_now;
+use crate::config::Annealing;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Where a generation's corpus and keyboard came from, which only the caller
+/// knows by name, and the command line that started it.
+#[derive(Clone, Debug, Default)]
+pub struct Provenance {
+    pub command: Vec<String>,
+    pub corpus: String,
+    pub keyboard: String,
+}
+
+/// How a generation was produced, written next to its tsv as `<name>.meta.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunMetadata {
+    pub name: String,
+    /// UTC, as `YYYY-MM-DDTHH:MM:SSZ`
+    pub started: String,
+    pub version: String,
+    /// The commit keywhisker was built from, if built from a git checkout
+    pub git_revision: Option<String>,
+    pub command: Vec<String>,
+    pub corpus: String,
+    pub keyboard: String,
+    pub char_set: String,
+    pub strategy: String,
+    pub metrics: Vec<(String, i16)>,
+    pub runs: u64,
+    pub pin: usize,
+    /// Positions kept in place besides the first `pin`
+    #[serde(default)]
+    pub pinned: Vec<usize>,
+    pub hand: Option<String>,
+    /// As `name:cost=chars`
+    pub layers: Vec<String>,
+    pub combos: bool,
+    pub tiers: Vec<String>,
+    pub tier_tolerance: f32,
+    pub penalties: Vec<(String, f32, f32)>,
+    pub swap_bias: f32,
+    #[serde(default)]
+    pub keep_blanks: bool,
+    /// Run n is seeded with this plus n
+    pub seed: u64,
+    pub annealing: Annealing,
+}
+
+/// The current UTC time as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn iso_now() -> String {
+    let (year, month, day, h, m, s) = utc_now();
+    format!("{year:04}-{month:02}-{day:02}T{h:02}:{m:02}:{s:02}Z")
+}
+
+pub fn git_revision() -> Option<String> {
+    option_env!("KEYWHISKER_GIT_REV").map(str::to_string)
+}
+
+impl RunMetadata {
+    /// Where the metadata of the generation `name` in `dir` goes.
+    pub fn path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{name}.meta.json"))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = RunMetadata::path(dir, &self.name);
+        let file =
+            File::create(&path).with_context(|| format!("couldn't create {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid metadata {}", path.display()))
+    }
+}
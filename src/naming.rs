@@ -0,0 +1,46 @@
+use crate::fingers::Hand;
+use crate::keyboard::home_row;
+
+use keymeow::Keyboard;
+use std::collections::HashMap;
+
+/// A name for a layout from its home row, each hand's letters and digits in
+/// turn, like `noste-haiul`. `chars` are the layout's characters in position
+/// order. Layouts with nothing nameable there are `unnamed`.
+pub fn home_row_name(keyboard: &Keyboard, chars: &[char]) -> String {
+    let fingers: Vec<_> = keyboard.fingers.map.iter().flatten().collect();
+    let row = home_row(keyboard);
+    let hand = |hand: Hand| -> String {
+        row.iter()
+            .filter(|p| Hand::of(fingers[**p]) == hand)
+            .filter_map(|p| chars.get(*p))
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    };
+    let halves: Vec<String> = [hand(Hand::Left), hand(Hand::Right)]
+        .into_iter()
+        .filter(|h| !h.is_empty())
+        .collect();
+    match halves.is_empty() {
+        true => "unnamed".to_string(),
+        false => halves.join("-"),
+    }
+}
+
+/// Hands out names, adding `-2`, `-3` and so on to any it has given before.
+#[derive(Default)]
+pub struct Names {
+    given: HashMap<String, usize>,
+}
+
+impl Names {
+    pub fn unique(&mut self, name: String) -> String {
+        let count = self.given.entry(name.clone()).or_insert(0);
+        *count += 1;
+        match *count {
+            1 => name,
+            n => format!("{name}-{n}"),
+        }
+    }
+}
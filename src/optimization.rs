@@ -0,0 +1,742 @@
+use crate::analysis::layout_chars;
+use crate::checkpoint::Checkpoint;
+use crate::config::Annealing;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::dashboard::{Dashboard, TuiReporter};
+use crate::ddako::simulated_annealing::{self as ddako_sa, Improvement, Snapshot};
+use crate::evaluation::{Evaluator, Reference};
+use crate::fingers::Hand;
+use crate::interrupt;
+use crate::keyboard::{hand_positions, pinned_positions};
+use crate::reporter::{JsonReporter, NullReporter, PlainReporter, ProgressStyle, Reporter};
+use crate::running_stats::RunningStats;
+use crate::sampling::SwapSampler;
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+use keycat::{analysis::Analyzer, Layout, NgramType, Swap, Totals};
+use keymeow::Keyboard;
+#[cfg(not(target_arch = "wasm32"))]
+use linya::{Bar, Progress};
+use rand::prelude::*;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::iter;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum GenerationStrategy {
+    GreedyDeterministic,
+    GreedyNaive,
+    SimulatedAnnealing,
+    DDAKOSimulatedAnnealing,
+}
+
+// Iterations between progress redraws inside a run
+const PROGRESS_STEP: usize = 10_000;
+const NAIVE_ITERATIONS: usize = 5000;
+const SA_ITERATIONS: usize = 1_000_000;
+
+/// Progress bars for `run-generation`: one for each run's iterations and one
+/// for the runs overall.
+#[cfg(not(target_arch = "wasm32"))]
+struct GenerationProgress {
+    progress: Mutex<Progress>,
+    overall: Bar,
+    run: Mutex<Option<Bar>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl GenerationProgress {
+    fn new(runs: u64) -> Result<Self> {
+        let mut progress = Progress::new();
+        let overall = progress.bar(runs.try_into()?, "Runs");
+        Ok(Self {
+            progress: Mutex::new(progress),
+            overall,
+            run: Mutex::new(None),
+        })
+    }
+
+    fn start_run(&self, iterations: usize, label: String) {
+        let bar = self.progress.lock().unwrap().bar(iterations, label);
+        *self.run.lock().unwrap() = Some(bar);
+    }
+
+    fn advance(&self, iterations: usize) {
+        if let Some(bar) = self.run.lock().unwrap().as_ref() {
+            self.progress.lock().unwrap().inc_and_draw(bar, iterations);
+        }
+    }
+
+    fn finish_run(&self, iterations: usize) {
+        let mut progress = self.progress.lock().unwrap();
+        if let Some(bar) = self.run.lock().unwrap().take() {
+            progress.set_and_draw(&bar, iterations);
+        }
+        progress.inc_and_draw(&self.overall, 1);
+    }
+}
+
+/// Browsers have no terminal to draw bars on, so there's never any progress.
+#[cfg(target_arch = "wasm32")]
+enum GenerationProgress {}
+
+#[cfg(target_arch = "wasm32")]
+impl GenerationProgress {
+    fn start_run(&self, _iterations: usize, _label: String) {
+        match *self {}
+    }
+
+    fn advance(&self, _iterations: usize) {
+        match *self {}
+    }
+
+    fn finish_run(&self, _iterations: usize) {
+        match *self {}
+    }
+}
+
+/// The outcome of one optimization run.
+#[derive(Serialize, Clone)]
+pub struct GenerationResult {
+    pub iterations: u32,
+    pub score: f32,
+    /// Raw values of every metric the analyzer knows, by index
+    pub stats: Vec<f32>,
+    /// The layout's characters in position order
+    pub chars: String,
+    #[serde(skip)]
+    pub layout: Layout,
+}
+
+impl GenerationResult {
+    /// Analyze and score the layout a run ended with.
+    pub fn new(
+        iterations: u32,
+        layout: Layout,
+        analyzer: &Analyzer,
+        evaluator: &Evaluator,
+    ) -> Self {
+        let stats = analyzer.calc_stats(&layout);
+        GenerationResult {
+            iterations,
+            score: evaluator.eval(&stats),
+            stats,
+            chars: layout_chars(&analyzer.corpus, &layout),
+            layout,
+        }
+    }
+}
+
+/// Everything a generation strategy needs to optimize a layout, shared by every run.
+pub struct OptimizationContext {
+    strategy: GenerationStrategy,
+    layout: Layout,
+    // Shared, so that a server can reuse one across requests
+    analyzer: Arc<Analyzer>,
+    possible_swaps: Vec<Swap>,
+    // Groups of positions a run starts by shuffling, each among itself
+    movable: Vec<Vec<usize>>,
+    evaluator: Evaluator,
+    pin: usize,
+    // Positions kept in place besides the first `pin`
+    pinned: Vec<usize>,
+    combos: bool,
+    // With a hand given, the only keys that hold characters
+    hand_keys: Option<Vec<usize>>,
+    // Layers following the base layout; see the layers module
+    layers: usize,
+    // Each run's randomness comes from this plus the run number, if given
+    seed: Option<u64>,
+    // Share of candidate swaps chosen by character frequency rather than uniformly
+    swap_bias: f32,
+    // Whether blank keys of the starting layout stay blank
+    keep_blanks: bool,
+    reference: Option<Reference>,
+    annealing: Annealing,
+    progress: Option<GenerationProgress>,
+    progress_style: ProgressStyle,
+    keyboard: Keyboard,
+    // Weighted metrics by name, for display
+    metric_names: Vec<(String, usize, NgramType)>,
+    runs: u64,
+}
+
+impl OptimizationContext {
+    /// Optimize `layout` with `strategy`, swapping any of the keyboard's keys.
+    pub fn new(
+        strategy: GenerationStrategy,
+        layout: Layout,
+        analyzer: impl Into<Arc<Analyzer>>,
+        evaluator: Evaluator,
+        keyboard: Keyboard,
+    ) -> Self {
+        OptimizationContext {
+            strategy,
+            possible_swaps: vec![],
+            movable: vec![],
+            layout,
+            analyzer: analyzer.into(),
+            evaluator,
+            pin: 0,
+            pinned: vec![],
+            combos: false,
+            hand_keys: None,
+            layers: 0,
+            seed: None,
+            swap_bias: 0.0,
+            keep_blanks: false,
+            reference: None,
+            annealing: Annealing::default(),
+            progress: None,
+            progress_style: ProgressStyle::None,
+            keyboard,
+            metric_names: vec![],
+            runs: 1,
+        }
+        .with_swaps(0, false)
+    }
+
+    /// Keep the first `pin` positions in place, and swap combo slots (the tail
+    /// of the layout) too if `combos` is set.
+    pub fn with_swaps(mut self, pin: usize, combos: bool) -> Self {
+        self.pin = pin;
+        self.combos = combos;
+        self.update_swaps();
+        self
+    }
+
+    /// Also keep `pinned` positions in place, wherever they are.
+    pub fn with_pinned(mut self, pinned: Vec<usize>) -> Self {
+        self.pinned = pinned;
+        self.update_swaps();
+        self
+    }
+
+    /// Only place characters on the keys of `hand`, leaving the other hand's
+    /// keys and the combo slots dead. `pin` then counts the hand's keys.
+    pub fn with_hand(mut self, hand: Hand) -> Self {
+        self.hand_keys = Some(hand_positions(&self.keyboard, hand));
+        self.update_swaps();
+        self
+    }
+
+    /// Leave the base's blank keys out of every swap and shuffle if `keep`
+    /// is set, so characters stay on the keys the char set gave them.
+    pub fn with_blanks_kept(mut self, keep: bool) -> Self {
+        self.keep_blanks = keep;
+        self.update_swaps();
+        self
+    }
+
+    /// Treat the layout as a base followed by `layers` layers of the same
+    /// length, swapping keys only within each layer. `pin` only counts the base.
+    pub fn with_layers(mut self, layers: usize) -> Self {
+        self.layers = layers;
+        self.update_swaps();
+        self
+    }
+
+    fn update_swaps(&mut self) {
+        let len = self.layout.0.len() / (self.layers + 1);
+        let keys = self.keyboard.keys.map.iter().flatten().count();
+        let (movable, swappable): (Vec<usize>, Vec<usize>) = match &self.hand_keys {
+            Some(keys) => (keys.clone(), keys.clone()),
+            None => {
+                let swappable = match self.combos {
+                    true => len,
+                    false => keys,
+                };
+                ((0..len).collect(), (0..swappable).collect())
+            }
+        };
+        let pinned = pinned_positions(movable.iter().copied(), self.pin, &self.pinned);
+        let blank = |p: &usize| self.keep_blanks && self.layout.0[*p] == 0;
+        let kept = |p: &usize| !(blank(p) || pinned.contains(p));
+        let movable: Vec<usize> = movable.into_iter().filter(kept).collect();
+        let swappable: Vec<usize> = swappable.into_iter().filter(kept).collect();
+        let mut groups = vec![swappable];
+        let layer_keys = self
+            .hand_keys
+            .clone()
+            .unwrap_or_else(|| (0..keys).collect());
+        for layer in 1..=self.layers {
+            groups.push(layer_keys.iter().map(|p| layer * len + p).collect());
+        }
+        self.possible_swaps = groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .iter()
+                    .flat_map(move |a| group.iter().map(move |b| Swap::new(*a, *b)))
+            })
+            .filter(|Swap { a, b }| a != b)
+            .collect();
+        self.movable = iter::once(movable)
+            .chain(groups.into_iter().skip(1))
+            .collect();
+    }
+
+    /// Seed every run from `seed` and its run number, so the same runs can be repeated.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Choose this share of the swaps random strategies try by how frequent
+    /// the characters they move are, instead of uniformly.
+    pub fn with_swap_bias(mut self, bias: f32) -> Result<Self> {
+        ensure!(
+            (0.0..=1.0).contains(&bias),
+            "the swap bias must be between 0 and 1"
+        );
+        self.swap_bias = bias;
+        Ok(self)
+    }
+
+    pub fn with_reference(mut self, reference: Reference) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+
+    pub fn with_annealing(mut self, annealing: Annealing) -> Self {
+        self.annealing = annealing;
+        self
+    }
+
+    /// Show progress over `runs` runs, naming the weighted metrics in live displays.
+    pub fn with_progress(
+        mut self,
+        progress_style: ProgressStyle,
+        runs: u64,
+        metric_names: Vec<(String, usize, NgramType)>,
+    ) -> Result<Self> {
+        // DDAKO draws its own live display instead
+        self.progress = match (progress_style, &self.strategy) {
+            (_, GenerationStrategy::DDAKOSimulatedAnnealing) => None,
+            #[cfg(not(target_arch = "wasm32"))]
+            (ProgressStyle::Tui | ProgressStyle::Plain, _) => Some(GenerationProgress::new(runs)?),
+            _ => None,
+        };
+        self.progress_style = progress_style;
+        self.runs = runs;
+        self.metric_names = metric_names;
+        Ok(self)
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    pub fn evaluator(&self) -> &Evaluator {
+        &self.evaluator
+    }
+
+    pub fn possible_swaps(&self) -> &[Swap] {
+        &self.possible_swaps
+    }
+
+    pub fn reference(&self) -> Option<&Reference> {
+        self.reference.as_ref()
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    /// The weighted metrics of `stats` as percentages, in the evaluator's order.
+    pub fn percentages(&self, stats: &[f32]) -> Vec<f32> {
+        let totals = self.layout.totals(&self.analyzer.corpus);
+        self.evaluator
+            .metrics()
+            .map(|m| totals.percentage(stats[m], self.analyzer.data.metrics[m]))
+            .collect()
+    }
+
+    fn run_iterations(&self) -> usize {
+        match self.strategy {
+            GenerationStrategy::GreedyNaive => NAIVE_ITERATIONS,
+            GenerationStrategy::SimulatedAnnealing => SA_ITERATIONS,
+            // Runs until no swap improves, so only completion is shown
+            _ => 1,
+        }
+    }
+
+    /// Start the progress bar of a run, labelled with `label`.
+    pub fn start_run(&self, label: String) {
+        if let Some(progress) = &self.progress {
+            progress.start_run(self.run_iterations(), label);
+        }
+    }
+
+    pub fn finish_run(&self) {
+        if let Some(progress) = &self.progress {
+            progress.finish_run(self.run_iterations());
+        }
+    }
+
+    /// Whether runs have progress bars to label.
+    pub fn has_progress_bars(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    /// Optimize one shuffled copy of the layout, showing its progress in the
+    /// context's progress style.
+    ///
+    /// DDAKO saves its state to `checkpoint` (a path and the generation's
+    /// name) as it goes, and carries on from `resume` if given.
+    pub fn optimize(
+        &self,
+        run: u64,
+        checkpoint: Option<(&Path, &str)>,
+        resume: Option<ddako_sa::State>,
+    ) -> GenerationResult {
+        // Progress bars already show the run, so don't draw over them
+        if self.progress.is_some() {
+            return self.optimize_with(run, checkpoint, resume, &mut NullReporter);
+        }
+        let totals = self.layout.totals(&self.analyzer.corpus);
+        let mut reporter = self.reporter(&totals, checkpoint, run);
+        self.optimize_with(run, checkpoint, resume, reporter.as_mut())
+    }
+
+    /// Like `optimize`, but calling the hooks of `reporter` as it runs.
+    pub fn optimize_with(
+        &self,
+        run: u64,
+        checkpoint: Option<(&Path, &str)>,
+        resume: Option<ddako_sa::State>,
+        reporter: &mut dyn Reporter,
+    ) -> GenerationResult {
+        let result = match self.strategy {
+            GenerationStrategy::GreedyDeterministic => {
+                greedy_neighbor_optimization(self, run, reporter)
+            }
+            GenerationStrategy::GreedyNaive => greedy_naive_optimization(self, run, reporter),
+            GenerationStrategy::SimulatedAnnealing => simulated_annealing(self, run, reporter),
+            GenerationStrategy::DDAKOSimulatedAnnealing => {
+                ddako_simulated_annealing(self, checkpoint, run, resume, reporter)
+            }
+        };
+        reporter.on_finish(result.iterations, result.score);
+        result
+    }
+
+    fn reporter<'s>(
+        &'s self,
+        totals: &'s Totals,
+        checkpoint: Option<(&'s Path, &'s str)>,
+        run: u64,
+    ) -> Box<dyn Reporter + 's> {
+        match self.progress_style {
+            #[cfg(not(target_arch = "wasm32"))]
+            ProgressStyle::Tui => {
+                let mut dashboard = Dashboard::new(
+                    &self.keyboard,
+                    &self.analyzer.corpus,
+                    totals,
+                    &self.metric_names,
+                    run,
+                    self.runs,
+                );
+                if let Some((Some(dir), name)) = checkpoint.map(|(p, n)| (p.parent(), n)) {
+                    dashboard = dashboard.save_to(dir, name);
+                }
+                if let Some(reference) = &self.reference {
+                    dashboard = dashboard.compare_to(reference);
+                }
+                Box::new(TuiReporter::new(dashboard))
+            }
+            ProgressStyle::Plain => Box::new(PlainReporter::new(run)),
+            ProgressStyle::Json => Box::new(JsonReporter::new(run)),
+            #[cfg(target_arch = "wasm32")]
+            ProgressStyle::Tui => Box::new(NullReporter),
+            ProgressStyle::None => Box::new(NullReporter),
+        }
+    }
+}
+
+/// The randomness of run `run`: seeded from `seed` if given, or else fresh.
+fn run_rng(seed: Option<u64>, run: u64) -> StdRng {
+    StdRng::seed_from_u64(seed.map_or_else(random, |seed| seed.wrapping_add(run)))
+}
+
+/// Shuffle the keys of each group of positions among themselves, leaving the
+/// rest in place.
+fn shuffle_positions(layout: &mut Layout, groups: &[Vec<usize>], rng: &mut impl Rng) {
+    for positions in groups {
+        let mut keys: Vec<_> = positions.iter().map(|p| layout.0[*p]).collect();
+        keys.shuffle(rng);
+        for (p, key) in positions.iter().zip(keys) {
+            layout.0[*p] = key;
+        }
+    }
+}
+
+/// Report where a run of one of the simpler strategies stands. They keep no
+/// best layout apart from the current one, nor a cooling schedule.
+fn report_iteration(
+    reporter: &mut dyn Reporter,
+    iteration: u32,
+    since_improvement: u32,
+    layout: &Layout,
+    stats: &[f32],
+    score: f32,
+    temperature: f32,
+) {
+    reporter.on_iteration(&Snapshot {
+        iteration,
+        since_improvement,
+        current: score,
+        current_layout: &layout.0,
+        best: score,
+        best_layout: &layout.0,
+        best_stats: stats,
+        stays: 0,
+        stopping_point: 0,
+        temperature,
+        cooling_interval: 0.0,
+        acceptance_rate: 0.0,
+    });
+}
+
+fn report_improvement(
+    reporter: &mut dyn Reporter,
+    iteration: u32,
+    layout: &Layout,
+    stats: &[f32],
+    score: f32,
+) {
+    reporter.on_improvement(&Improvement {
+        iteration,
+        score,
+        layout: &layout.0,
+        stats,
+    });
+}
+
+fn greedy_neighbor_optimization(
+    OptimizationContext {
+        layout,
+        analyzer,
+        possible_swaps,
+        movable,
+        evaluator,
+        seed,
+        ..
+    }: &OptimizationContext,
+    run: u64,
+    reporter: &mut dyn Reporter,
+) -> GenerationResult {
+    let mut rng = run_rng(*seed, run);
+    let mut layout = layout.clone();
+
+    shuffle_positions(&mut layout, movable, &mut rng);
+
+    let mut stats = RunningStats::new(analyzer.calc_stats(&layout));
+    let mut diff = vec![0.0; stats.len()];
+    let mut best_diff = vec![0.0; stats.len()];
+
+    let mut i = 0;
+    while !interrupt::interrupted() {
+        best_diff.fill(0.0);
+        let mut best_swap = &possible_swaps[0];
+        for swap in possible_swaps {
+            diff.fill(0.0);
+            analyzer.swap_diff(&mut diff, &layout, swap);
+            reporter.on_evaluation();
+            if evaluator.compare_diffs(&stats, &diff, &best_diff) == Ordering::Less {
+                best_swap = swap;
+                best_diff.copy_from_slice(&diff);
+            }
+        }
+        if evaluator.improves(&stats, &best_diff) {
+            layout.swap(best_swap);
+            stats.add(&best_diff);
+            i += 1;
+            let score = evaluator.eval(&stats);
+            report_improvement(reporter, i, &layout, &stats, score);
+            report_iteration(reporter, i, 0, &layout, &stats, score, 0.0);
+        } else {
+            break;
+        }
+    }
+    let result = GenerationResult::new(i, layout, analyzer, evaluator);
+    stats.report_drift(&result.stats);
+    result
+}
+
+fn greedy_naive_optimization(
+    OptimizationContext {
+        layout,
+        analyzer,
+        possible_swaps,
+        movable,
+        evaluator,
+        progress,
+        seed,
+        swap_bias,
+        ..
+    }: &OptimizationContext,
+    run: u64,
+    reporter: &mut dyn Reporter,
+) -> GenerationResult {
+    let mut rng = run_rng(*seed, run);
+    let mut layout = layout.clone();
+    let mut sampler = SwapSampler::new(possible_swaps, &analyzer.corpus, *swap_bias);
+
+    shuffle_positions(&mut layout, movable, &mut rng);
+    sampler.update(&layout, analyzer, evaluator);
+
+    let mut stats = RunningStats::new(analyzer.calc_stats(&layout));
+    let mut diff = vec![0.0; stats.len()];
+
+    let mut swap_i = 0;
+    for i in 0..NAIVE_ITERATIONS as u32 {
+        if interrupt::interrupted() {
+            break;
+        }
+        if i % 100 == 0 {
+            if let Some(progress) = progress {
+                progress.advance(100);
+            }
+            let score = evaluator.eval(&stats);
+            report_iteration(reporter, i, i - swap_i, &layout, &stats, score, 0.0);
+        }
+        let swap = sampler.choose(possible_swaps, &mut rng);
+        diff.fill(0.0);
+        analyzer.swap_diff(&mut diff, &layout, swap);
+        reporter.on_evaluation();
+        if evaluator.improves(&stats, &diff) {
+            layout.swap(swap);
+            sampler.update(&layout, analyzer, evaluator);
+            stats.add(&diff);
+            swap_i = i;
+            report_improvement(reporter, i, &layout, &stats, evaluator.eval(&stats));
+        }
+    }
+    let result = GenerationResult::new(swap_i, layout, analyzer, evaluator);
+    stats.report_drift(&result.stats);
+    result
+}
+
+fn simulated_annealing(
+    OptimizationContext {
+        layout,
+        analyzer,
+        possible_swaps,
+        movable,
+        evaluator,
+        progress,
+        seed,
+        swap_bias,
+        ..
+    }: &OptimizationContext,
+    run: u64,
+    reporter: &mut dyn Reporter,
+) -> GenerationResult {
+    let mut rng = run_rng(*seed, run);
+    let mut layout = layout.clone();
+    let mut sampler = SwapSampler::new(possible_swaps, &analyzer.corpus, *swap_bias);
+
+    shuffle_positions(&mut layout, movable, &mut rng);
+    sampler.update(&layout, analyzer, evaluator);
+
+    let mut stats = RunningStats::new(analyzer.calc_stats(&layout));
+    let mut diff = vec![0.0; stats.len()];
+    let mut best = evaluator.eval(&stats);
+    let mut last_improvement = 0;
+
+    let mut temp = 0.5;
+    let dec: f32 = temp / SA_ITERATIONS as f32;
+    let mut iterations = 0;
+    for i in 0..SA_ITERATIONS {
+        if i % PROGRESS_STEP == 0 {
+            if interrupt::interrupted() {
+                break;
+            }
+            if let Some(progress) = progress {
+                progress.advance(PROGRESS_STEP);
+            }
+            let (i, score) = (i as u32, evaluator.eval(&stats));
+            let since = i - last_improvement;
+            report_iteration(reporter, i, since, &layout, &stats, score, temp);
+        }
+        temp -= dec;
+        let swap = sampler.choose(possible_swaps, &mut rng);
+        diff.fill(0.0);
+        analyzer.swap_diff(&mut diff, &layout, swap);
+        reporter.on_evaluation();
+        if evaluator.improves(&stats, &diff) || rng.gen::<f32>() < temp {
+            layout.swap(swap);
+            sampler.update(&layout, analyzer, evaluator);
+            stats.add(&diff);
+            let score = evaluator.eval(&stats);
+            if score < best {
+                best = score;
+                last_improvement = i as u32;
+                report_improvement(reporter, last_improvement, &layout, &stats, score);
+            }
+        }
+        iterations += 1;
+    }
+    let result = GenerationResult::new(iterations, layout, analyzer, evaluator);
+    stats.report_drift(&result.stats);
+    result
+}
+
+fn ddako_simulated_annealing(
+    OptimizationContext {
+        layout,
+        analyzer,
+        possible_swaps,
+        evaluator,
+        annealing,
+        seed,
+        swap_bias,
+        ..
+    }: &OptimizationContext,
+    checkpoint: Option<(&Path, &str)>,
+    run: u64,
+    resume: Option<ddako_sa::State>,
+    reporter: &mut dyn Reporter,
+) -> GenerationResult {
+    let mut sa = ddako_sa::SimulatedAnnealing::new(
+        possible_swaps,
+        layout,
+        analyzer,
+        evaluator,
+        annealing,
+        *swap_bias,
+        reporter,
+    );
+    if let Some(seed) = seed {
+        sa = sa.with_seed(seed.wrapping_add(run));
+    }
+    if let Some(state) = resume {
+        sa = sa.with_resume(state);
+    }
+    let mut save;
+    if let Some((path, name)) = checkpoint {
+        save = move |state| {
+            let name = name.to_string();
+            Checkpoint {
+                name,
+                run,
+                state: Some(state),
+            }
+            .save(path)
+        };
+        sa = sa.with_checkpoints(&mut save);
+    }
+
+    sa.optimize(possible_swaps.len())
+}
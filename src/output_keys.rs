@@ -0,0 +1,276 @@
+//! Keys typing something other than a single character: ngram keys, like a
+//! `th` key, and a repeat key typing the character before it again. Each is
+//! labelled with a character the corpus doesn't have, which char sets and
+//! layouts use for it, and the corpus is recounted as if every `th` (or every
+//! doubled character) had been typed with it.
+//!
+//! The corpus only counts up to trigrams, so whatever would need longer ngrams
+//! is estimated: trigrams and skipgrams spanning an ngram key assume each
+//! character depends only on the two before it, and a character starting an
+//! ngram is never taken to be a repeat.
+
+use anyhow::{bail, ensure, Context, Error, Result};
+use keycat::{Corpus, CorpusChar};
+use std::str::FromStr;
+
+/// What a key types instead of its label.
+#[derive(Clone, Debug)]
+pub enum Output {
+    Ngram(char, char),
+    Repeat,
+}
+
+/// A key labelled `label` in char sets and layouts.
+#[derive(Clone, Debug)]
+pub struct OutputKey {
+    pub label: char,
+    pub output: Output,
+}
+
+impl FromStr for OutputKey {
+    type Err = Error;
+
+    /// `label=chars`, like `&=th`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (label, chars) = s
+            .split_once('=')
+            .with_context(|| format!("expected label=chars but got `{s}`"))?;
+        let mut label = label.chars();
+        let (Some(label), None) = (label.next(), label.next()) else {
+            bail!("an ngram key's label must be one character");
+        };
+        let chars: Vec<char> = chars.chars().collect();
+        let [a, b] = chars[..] else {
+            // Longer ones would need counts of longer ngrams than the corpus has
+            bail!("ngram keys can only type two characters");
+        };
+        Ok(OutputKey {
+            label,
+            output: Output::Ngram(a, b),
+        })
+    }
+}
+
+/// Ngram counts being recounted, as floats for the estimates.
+struct Counts<'a> {
+    corpus: &'a Corpus,
+    chars: Vec<f64>,
+    bigrams: Vec<f64>,
+    skipgrams: Vec<f64>,
+    trigrams: Vec<f64>,
+}
+
+fn floats(counts: &[u32]) -> Vec<f64> {
+    counts.iter().map(|c| *c as f64).collect()
+}
+
+fn rounded(values: &[f64]) -> Vec<u32> {
+    values.iter().map(|v| v.round().max(0.0) as u32).collect()
+}
+
+impl<'a> Counts<'a> {
+    fn new(corpus: &'a Corpus) -> Self {
+        Counts {
+            corpus,
+            chars: floats(&corpus.chars),
+            bigrams: floats(&corpus.bigrams),
+            skipgrams: floats(&corpus.skipgrams),
+            trigrams: floats(&corpus.trigrams),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.corpus.char_list.len()
+    }
+
+    fn bi(&self, a: CorpusChar, b: CorpusChar) -> usize {
+        self.corpus.bigram_idx(a, b)
+    }
+
+    fn tri(&self, a: CorpusChar, b: CorpusChar, c: CorpusChar) -> usize {
+        self.corpus.trigram_idx(a, b, c)
+    }
+
+    /// Recount as if every `x` followed by `y` were typed with `key`.
+    fn ngram(&mut self, x: CorpusChar, y: CorpusChar, key: CorpusChar) {
+        let bigrams = self.bigrams.clone();
+        let trigrams = self.trigrams.clone();
+        let (n, corpus) = (self.len(), self.corpus);
+        let bigram = |a, b| bigrams[corpus.bigram_idx(a, b)];
+        let trigram = |a, b, c| trigrams[corpus.trigram_idx(a, b, c)];
+        let xy = bigram(x, y);
+        if xy == 0.0 {
+            return;
+        }
+        // Estimated 4-grams: a x y b, x y a b and a b x y
+        let around = |a, b| trigram(a, x, y) * trigram(x, y, b) / xy;
+        let starting = |a, b| match bigram(y, a) {
+            0.0 => 0.0,
+            ya => trigram(x, y, a) * trigram(y, a, b) / ya,
+        };
+        let ending = |a, b| match bigram(b, x) {
+            0.0 => 0.0,
+            bx => trigram(a, b, x) * trigram(b, x, y) / bx,
+        };
+
+        self.chars[key] += xy;
+        self.chars[x] -= xy;
+        self.chars[y] -= xy;
+
+        let i = self.bi(x, y);
+        self.bigrams[i] = 0.0;
+        for a in 0..n {
+            let t = trigram(a, x, y);
+            let (i, j, k) = (self.bi(a, x), self.bi(a, key), self.bi(a, y));
+            self.bigrams[i] -= t;
+            self.bigrams[j] += t;
+            self.skipgrams[k] -= t;
+            let t = trigram(x, y, a);
+            let (i, j, k) = (self.bi(y, a), self.bi(key, a), self.bi(x, a));
+            self.bigrams[i] -= t;
+            self.bigrams[j] += t;
+            self.skipgrams[k] -= t;
+            let (i, j) = (self.tri(a, x, y), self.tri(x, y, a));
+            self.trigrams[i] = 0.0;
+            self.trigrams[j] = 0.0;
+        }
+
+        for a in 0..n {
+            for b in 0..n {
+                let t = around(a, b);
+                let (i, j) = (self.tri(a, key, b), self.bi(a, b));
+                self.trigrams[i] += t;
+                self.skipgrams[j] += t;
+
+                let t = starting(a, b);
+                let (i, j) = (self.tri(key, a, b), self.tri(y, a, b));
+                self.trigrams[i] += t;
+                self.trigrams[j] -= t;
+                let (i, j) = (self.bi(key, b), self.bi(y, b));
+                self.skipgrams[i] += t;
+                self.skipgrams[j] -= t;
+
+                let t = ending(a, b);
+                let (i, j) = (self.tri(a, b, key), self.tri(a, b, x));
+                self.trigrams[i] += t;
+                self.trigrams[j] -= t;
+                let (i, j) = (self.bi(a, key), self.bi(a, x));
+                self.skipgrams[i] += t;
+                self.skipgrams[j] -= t;
+            }
+        }
+    }
+
+    /// Recount as if every character typed right after itself were typed with `key`.
+    fn repeat(&mut self, key: CorpusChar) {
+        let old_bigrams = self.bigrams.clone();
+        let old_trigrams = self.trigrams.clone();
+        let n = self.len();
+        let repeated = |prev: CorpusChar, c: CorpusChar| if c == prev { key } else { c };
+
+        for a in 0..n {
+            let aa = old_bigrams[self.bi(a, a)];
+            self.chars[a] -= aa;
+            self.chars[key] += aa;
+        }
+
+        self.bigrams.iter_mut().for_each(|c| *c = 0.0);
+        for a in 0..n {
+            for b in 0..n {
+                // Those following another `a` start on the repeat key too
+                let ab = old_bigrams[self.bi(a, b)];
+                let aab = old_trigrams[self.tri(a, a, b)].min(ab);
+                let (i, j) = (self.bi(a, repeated(a, b)), self.bi(key, repeated(a, b)));
+                self.bigrams[i] += ab - aab;
+                self.bigrams[j] += aab;
+            }
+        }
+
+        self.trigrams.iter_mut().for_each(|c| *c = 0.0);
+        for a in 0..n {
+            for b in 0..n {
+                let abb = old_trigrams[self.tri(a, b, b)];
+                let (i, j) = (self.bi(a, b), self.bi(a, key));
+                self.skipgrams[i] -= abb;
+                self.skipgrams[j] += abb;
+                for c in 0..n {
+                    let i = self.tri(a, repeated(a, b), repeated(b, c));
+                    self.trigrams[i] += old_trigrams[self.tri(a, b, c)];
+                }
+            }
+        }
+    }
+}
+
+/// `corpus` with a character for each of `keys`, recounted as if typed with them.
+pub fn apply(corpus: &Corpus, keys: &[OutputKey]) -> Result<Corpus> {
+    if keys.is_empty() {
+        return Ok(corpus.clone());
+    }
+    let mut labels: Vec<char> = vec![];
+    for key in keys {
+        ensure!(
+            !corpus.char_map.contains_key(&key.label) && !labels.contains(&key.label),
+            "key label {:?} is already a character of the corpus or another key",
+            key.label
+        );
+        if let Output::Ngram(a, b) = key.output {
+            ensure!(
+                corpus.char_map.contains_key(&a) && corpus.char_map.contains_key(&b),
+                "ngram key {a}{b} types characters not in the corpus"
+            );
+        }
+        labels.push(key.label);
+    }
+
+    let mut char_list: Vec<Vec<char>> = corpus
+        .char_list
+        .iter()
+        .filter(|chars| !matches!(chars.first(), None | Some('\0')))
+        .cloned()
+        .collect();
+    char_list.extend(labels.iter().map(|c| vec![*c]));
+    let mut out = Corpus::with_char_list(char_list);
+    let map: Vec<CorpusChar> = corpus
+        .char_list
+        .iter()
+        .map(|chars| match chars.first() {
+            None | Some('\0') => 0,
+            Some(c) => out.corpus_char(*c),
+        })
+        .collect();
+    for (a, ma) in map.iter().enumerate() {
+        out.chars[*ma] += corpus.chars[a];
+        for (b, mb) in map.iter().enumerate() {
+            let (i, j) = (corpus.bigram_idx(a, b), out.bigram_idx(*ma, *mb));
+            out.bigrams[j] += corpus.bigrams[i];
+            out.skipgrams[j] += corpus.skipgrams[i];
+            for (c, mc) in map.iter().enumerate() {
+                let i = corpus.trigram_idx(a, b, c);
+                let j = out.trigram_idx(*ma, *mb, *mc);
+                out.trigrams[j] += corpus.trigrams[i];
+            }
+        }
+    }
+
+    let mut counts = Counts::new(&out);
+    for key in keys {
+        let label = out.corpus_char(key.label);
+        match key.output {
+            Output::Ngram(a, b) => counts.ngram(out.corpus_char(a), out.corpus_char(b), label),
+            Output::Repeat => counts.repeat(label),
+        }
+    }
+    let Counts {
+        chars,
+        bigrams,
+        skipgrams,
+        trigrams,
+        ..
+    } = counts;
+    out.chars = rounded(&chars);
+    out.bigrams = rounded(&bigrams);
+    out.skipgrams = rounded(&skipgrams);
+    out.trigrams = rounded(&trigrams);
+    Ok(out)
+}
@@ -0,0 +1,125 @@
+use anyhow::{ensure, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// A result of some generation tsv, with the chosen metrics' values.
+struct Candidate {
+    source: String,
+    name: String,
+    layout: String,
+    values: Vec<f32>,
+}
+
+/// The results of the generation tsv at `path`, with the columns `metrics`.
+fn read_candidates(path: &Path, metrics: &[String]) -> Result<Vec<Candidate>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .context("empty generations file")?
+        .split('\t')
+        .collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| *h == name)
+            .with_context(|| format!("{} has no {name} column", path.display()))
+    };
+    let columns: Result<Vec<usize>> = metrics.iter().map(|m| column(m)).collect();
+    let columns = columns?;
+    let layout = column("layout")?;
+    // Older tsvs have no names
+    let name = header.iter().position(|h| *h == "name");
+    let source = path
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let values: Result<Vec<f32>> = columns
+                .iter()
+                .map(|c| Ok(fields.get(*c).context("missing value")?.parse()?))
+                .collect();
+            Ok(Candidate {
+                source: source.clone(),
+                name: name.and_then(|n| fields.get(n)).unwrap_or(&"").to_string(),
+                layout: fields.get(layout).context("missing layout")?.to_string(),
+                values: values?,
+            })
+        })
+        .collect()
+}
+
+/// Whether `a` is at least as good as `b` on every metric and better on one,
+/// given which metrics are better higher.
+fn dominates(a: &[f32], b: &[f32], higher: &[bool]) -> bool {
+    let oriented = |x: f32, h: bool| if h { -x } else { x };
+    let mut better = false;
+    for ((x, y), h) in a.iter().zip(b).zip(higher) {
+        let (x, y) = (oriented(*x, *h), oriented(*y, *h));
+        if x > y {
+            return false;
+        }
+        better |= x < y;
+    }
+    better
+}
+
+/// Print the results of the generation tsvs at `paths` that no other result
+/// beats on all of `metrics`, sorted by the first metric. Metrics are lower
+/// is better unless in `higher_is_better`; repeated layouts count once.
+pub fn pareto(
+    paths: &[impl AsRef<Path>],
+    metrics: &[String],
+    higher_is_better: &[String],
+) -> Result<()> {
+    ensure!(!metrics.is_empty(), "need at least one metric");
+    let mut candidates = vec![];
+    for path in paths {
+        candidates.extend(read_candidates(path.as_ref(), metrics)?);
+    }
+    let total = candidates.len();
+    candidates.sort_by(|a, b| a.layout.cmp(&b.layout));
+    candidates.dedup_by(|a, b| a.layout == b.layout);
+    let higher: Vec<bool> = metrics
+        .iter()
+        .map(|m| higher_is_better.contains(m))
+        .collect();
+
+    let mut front: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| {
+            !candidates
+                .iter()
+                .any(|o| dominates(&o.values, &c.values, &higher))
+        })
+        .collect();
+    let first = |c: &Candidate| if higher[0] { -c.values[0] } else { c.values[0] };
+    front.sort_by(|a, b| first(a).total_cmp(&first(b)));
+
+    let source_width = front
+        .iter()
+        .map(|c| c.source.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    let name_width = front.iter().map(|c| c.name.len()).max().unwrap_or(0).max(4);
+    print!("{:source_width$}  {:name_width$}", "Source", "Name");
+    for m in metrics {
+        print!("{m:>10}");
+    }
+    println!("  Layout");
+    for c in &front {
+        print!("{:source_width$}  {:name_width$}", c.source, c.name);
+        for v in &c.values {
+            print!("{v:>10.3}");
+        }
+        println!("  {}", c.layout);
+    }
+    println!();
+    println!("{} of {total} results are on the front", front.len());
+    Ok(())
+}
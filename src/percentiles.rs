@@ -0,0 +1,88 @@
+use crate::analysis::{get_metric, layout_matrices, resolve_metrics};
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+use std::fs;
+use std::path::Path;
+
+/// Read the per-metric columns of a csv written by `collect` or `survey`.
+pub fn read_distribution(path: &Path) -> Result<Vec<(String, Vec<f32>)>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let mut lines = text.lines();
+    let header = lines.next().context("empty distribution file")?;
+    // Collect's header ends with a comma, and survey's with a column of the
+    // layouts' names, which isn't a metric even if one is called that too
+    let names = header
+        .strip_suffix(',')
+        .or_else(|| header.strip_suffix("layout"))
+        .with_context(|| format!("{} wasn't written by collect or survey", path.display()))?;
+    let mut columns: Vec<(String, Vec<f32>)> = names
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| (name.to_string(), vec![]))
+        .collect();
+    for line in lines {
+        for ((_, values), field) in columns.iter_mut().zip(line.split(',')) {
+            values.push(
+                field
+                    .parse()
+                    .with_context(|| format!("invalid value {field}"))?,
+            );
+        }
+    }
+    Ok(columns)
+}
+
+/// Print where each layout falls within a distribution of random layouts.
+pub fn percentiles(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    distribution: &Path,
+    higher_is_better: &[String],
+) -> Result<()> {
+    let maximize = resolve_metrics(higher_is_better, &metric_data)?;
+    let columns: Result<Vec<_>> = read_distribution(distribution)?
+        .into_iter()
+        .map(|(name, values)| {
+            get_metric(&name, &metric_data)
+                .with_context(|| format!("invalid metric {name}"))
+                .map(|m| (m, values))
+        })
+        .collect();
+    let columns = columns?;
+
+    let ctx = MetricContext::new(
+        layouts
+            .first()
+            .context("need at least one layout to place")?,
+        metric_data,
+        corpus,
+    )
+    .context("could not produce metric context")?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+    let max: usize = ctx.metrics.iter().map(|m| m.name.len()).max().unwrap();
+
+    for (l, matrix) in layouts.iter().zip(layout_matrices(&ctx, &layouts)?) {
+        let stats = ctx.analyzer.calc_stats(&matrix);
+        println!("{}", l.name);
+        for (m, values) in &columns {
+            let metric = &ctx.metrics[*m];
+            let pc = totals.percentage(stats[*m], metric.ngram_type);
+            let worse = match maximize.contains(m) {
+                true => values.iter().filter(|v| **v < pc).count(),
+                false => values.iter().filter(|v| **v > pc).count(),
+            };
+            println!(
+                "  {:max$} {:>6.2}%  better than {:.1}% of {} random layouts",
+                metric.name,
+                pc,
+                worse as f32 / values.len().max(1) as f32 * 100.0,
+                values.len(),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! Metrics from dynamic libraries, for metrics too niche to add to km_data.
+//!
+//! A plugin is a `cdylib` exporting these C functions:
+//!
+//! ```rust,ignore
+//! use std::ffi::c_char;
+//!
+//! #[repr(C)]
+//! pub struct PluginMetric {
+//!     name: *const c_char,
+//!     short: *const c_char,
+//!     // 0 monogram, 1 bigram, 2 skipgram, 3 trigram
+//!     ngram_type: u8,
+//! }
+//!
+//! #[repr(C)]
+//! pub struct PluginKey {
+//!     x: f32,
+//!     y: f32,
+//!     w: f32,
+//!     h: f32,
+//!     // 0 left pinky through 9 right pinky
+//!     finger: u8,
+//! }
+//!
+//! /// Must return 1, the plugin interface version.
+//! #[no_mangle]
+//! pub extern "C" fn keywhisker_plugin_version() -> u32 { 1 }
+//!
+//! /// The metrics the plugin adds, writing their count to `count`.
+//! #[no_mangle]
+//! pub extern "C" fn keywhisker_plugin_metrics(count: *mut usize) -> *const PluginMetric { .. }
+//!
+//! /// The amount of the plugin's `metric` for pressing `positions` (one for
+//! /// monograms, two for bigrams and skipgrams, three for trigrams) in order.
+//! #[no_mangle]
+//! pub extern "C" fn keywhisker_plugin_amount(
+//!     metric: usize,
+//!     keys: *const PluginKey,
+//!     key_count: usize,
+//!     positions: *const usize,
+//!     len: usize,
+//! ) -> f32 { .. }
+//! ```
+//!
+//! Every combination of positions is asked about, and zero amounts are left out.
+
+use crate::fingers::finger_index;
+
+use anyhow::{bail, ensure, Context, Result};
+use keycat::analysis::{MetricAmount, Nstroke, NstrokeData};
+use keycat::NgramType;
+use keymeow::{Metric, MetricData};
+use libloading::{Library, Symbol};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::path::Path;
+
+const PLUGIN_VERSION: u32 = 1;
+
+#[repr(C)]
+struct PluginMetric {
+    name: *const c_char,
+    short: *const c_char,
+    ngram_type: u8,
+}
+
+#[repr(C)]
+struct PluginKey {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+    finger: u8,
+}
+
+type VersionFn = unsafe extern "C" fn() -> u32;
+type MetricsFn = unsafe extern "C" fn(*mut usize) -> *const PluginMetric;
+type AmountFn = unsafe extern "C" fn(usize, *const PluginKey, usize, *const usize, usize) -> f32;
+
+/// Every ordered choice of `len` positions out of `keys`, repeats included.
+fn position_tuples(keys: usize, len: u32) -> impl Iterator<Item = Vec<usize>> {
+    (0..keys.pow(len)).map(move |mut i| {
+        let mut positions = vec![0; len as usize];
+        for p in positions.iter_mut().rev() {
+            *p = i % keys;
+            i /= keys;
+        }
+        positions
+    })
+}
+
+fn nstroke(positions: &[usize]) -> Nstroke {
+    match *positions {
+        [a] => Nstroke::Monostroke(a),
+        [a, b] => Nstroke::Bistroke([a, b]),
+        [a, b, c] => Nstroke::Tristroke([a, b, c]),
+        _ => unreachable!("nstrokes are one to three keys"),
+    }
+}
+
+/// Load the plugin at `path` and add its metrics to `metric_data`.
+pub fn add_metrics(metric_data: &mut MetricData, path: &Path) -> Result<()> {
+    // Safety: loading runs the library's initializers, which is the point of a plugin
+    let library = unsafe { Library::new(path) }
+        .with_context(|| format!("couldn't load plugin {}", path.display()))?;
+    let symbol_error = || format!("{} isn't a keywhisker plugin", path.display());
+    // Safety: the signatures are the documented plugin interface
+    let (version, metrics, amount_of) = unsafe {
+        let version: Symbol<VersionFn> = library
+            .get(b"keywhisker_plugin_version")
+            .with_context(symbol_error)?;
+        let metrics: Symbol<MetricsFn> = library
+            .get(b"keywhisker_plugin_metrics")
+            .with_context(symbol_error)?;
+        let amount_of: Symbol<AmountFn> = library
+            .get(b"keywhisker_plugin_amount")
+            .with_context(symbol_error)?;
+        (version, metrics, amount_of)
+    };
+    // Safety: the plugin interface's functions take nothing the plugin doesn't own
+    let version = unsafe { version() };
+    ensure!(
+        version == PLUGIN_VERSION,
+        "{} is for plugin interface {version}, not {PLUGIN_VERSION}",
+        path.display()
+    );
+
+    let mut count = 0;
+    // Safety: the plugin returns `count` metrics that live as long as the library
+    let plugin_metrics = unsafe {
+        let first = metrics(&mut count);
+        ensure!(
+            !first.is_null() || count == 0,
+            "{} returned no metrics",
+            path.display()
+        );
+        match count {
+            0 => &[][..],
+            _ => std::slice::from_raw_parts(first, count),
+        }
+    };
+
+    let keys: Vec<PluginKey> = metric_data
+        .keyboard
+        .keys
+        .map
+        .iter()
+        .flatten()
+        .zip(metric_data.keyboard.fingers.map.iter().flatten())
+        .map(|(k, f)| PluginKey {
+            x: k.x,
+            y: k.y,
+            w: k.w,
+            h: k.h,
+            finger: finger_index(f) as u8,
+        })
+        .collect();
+    // Looking strokes up by position beats a linear search per trigram
+    let mut strokes: HashMap<Vec<usize>, usize> = metric_data
+        .strokes
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.nstroke.to_vec(), i))
+        .collect();
+
+    for (i, plugin_metric) in plugin_metrics.iter().enumerate() {
+        // Safety: the names are NUL-terminated strings owned by the library
+        let (name, short) = unsafe {
+            (
+                CStr::from_ptr(plugin_metric.name)
+                    .to_string_lossy()
+                    .into_owned(),
+                CStr::from_ptr(plugin_metric.short)
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        let (ngram_type, len) = match plugin_metric.ngram_type {
+            0 => (NgramType::Monogram, 1),
+            1 => (NgramType::Bigram, 2),
+            2 => (NgramType::Skipgram, 2),
+            3 => (NgramType::Trigram, 3),
+            other => bail!("plugin metric {name} has unknown ngram type {other}"),
+        };
+        ensure!(
+            !metric_data.metrics.iter().any(|m| m.short == short),
+            "plugin metric {short} is already defined"
+        );
+        let metric = metric_data.metrics.len();
+        metric_data.metrics.push(Metric {
+            name,
+            short,
+            ngram_type,
+        });
+
+        for positions in position_tuples(keys.len(), len) {
+            // Safety: both slices outlive the call, which only reads them
+            let value = unsafe {
+                amount_of(
+                    i,
+                    keys.as_ptr(),
+                    keys.len(),
+                    positions.as_ptr(),
+                    positions.len(),
+                )
+            };
+            if value == 0.0 {
+                continue;
+            }
+            let amount = MetricAmount {
+                metric,
+                amount: value,
+            };
+            match strokes.get(&positions) {
+                Some(s) => metric_data.strokes[*s].amounts.push(amount),
+                None => {
+                    strokes.insert(positions.clone(), metric_data.strokes.len());
+                    metric_data.strokes.push(NstrokeData {
+                        nstroke: nstroke(&positions),
+                        amounts: vec![amount],
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! Python bindings, built as the `keywhisker` extension module with the
+//! `python` feature. Metric values come back as numpy arrays so they can go
+//! straight into pandas or matplotlib.
+
+use crate::analysis::{layout_stats, weighted_context};
+use crate::export::load_layout;
+use crate::lookup;
+use crate::optimization::GenerationStrategy;
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use keycat::Corpus as KcCorpus;
+use km_data::Data as KeymeowData;
+use numpy::{PyArray1, PyArray2};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::PathBuf;
+
+/// A corpus loaded from the data directory.
+#[pyclass]
+#[derive(Clone)]
+pub struct Corpus(KcCorpus);
+
+#[pymethods]
+impl Corpus {
+    /// Blend corpora by weight, as `-c name:weight` does.
+    #[staticmethod]
+    fn merge(corpora: Vec<(Corpus, f32)>) -> Result<Self> {
+        let corpora: Vec<(KcCorpus, f32)> = corpora.into_iter().map(|(c, w)| (c.0, w)).collect();
+        Ok(Corpus(crate::corpus::merge(&corpora)?))
+    }
+}
+
+/// The keymeow data directory, downloaded if none is given.
+#[pyclass]
+pub struct Data(KeymeowData);
+
+#[pymethods]
+impl Data {
+    #[new]
+    #[pyo3(signature = (data_dir = None))]
+    fn new(data_dir: Option<PathBuf>) -> Result<Self> {
+        let data = match data_dir {
+            Some(dir) => KeymeowData::from_dir(dir.clone())
+                .with_context(|| format!("couldn't load data from {}", dir.display()))?,
+            None => KeymeowData::with_download()?,
+        };
+        Ok(Data(data))
+    }
+
+    fn corpus(&self, name: &str) -> Result<Corpus> {
+        Ok(Corpus(lookup::corpus(&self.0, name)?))
+    }
+
+    /// Every metric of `keyboard` for each of `layouts`, as a dict of the
+    /// layout names, the metric names, and layouts-by-metrics arrays of raw
+    /// values and percentages.
+    fn stats<'py>(
+        &self,
+        py: Python<'py>,
+        keyboard: &str,
+        corpus: &Corpus,
+        layouts: Vec<String>,
+    ) -> Result<Bound<'py, PyDict>> {
+        let metric_data = lookup::metrics(&self.0, keyboard)?;
+        let layouts: Result<Vec<_>> = layouts
+            .iter()
+            .map(|l| load_layout(&self.0, l, &metric_data.keyboard))
+            .collect();
+        let stats = layout_stats(metric_data, corpus.0.clone(), &layouts?)?;
+
+        let names: Vec<&str> = stats.iter().map(|s| s.name.as_str()).collect();
+        let metrics: Vec<&str> = stats[0].metrics.iter().map(|m| m.name.as_str()).collect();
+        let raw: Vec<Vec<f32>> = stats.iter().map(|s| s.raw()).collect();
+        let percentages: Vec<Vec<f32>> = stats.iter().map(|s| s.percentages()).collect();
+        let dict = PyDict::new_bound(py);
+        dict.set_item("layouts", names)?;
+        dict.set_item("metrics", metrics)?;
+        dict.set_item("raw", PyArray2::from_vec2_bound(py, &raw)?)?;
+        dict.set_item("percentages", PyArray2::from_vec2_bound(py, &percentages)?)?;
+        Ok(dict)
+    }
+
+    /// Generate one layout of `char_set` on `keyboard`, weighting metrics as
+    /// `(name, weight)` pairs. Returns a dict of the layout's characters, its
+    /// score and iterations, and the weighted metrics' percentages.
+    #[pyo3(signature = (
+        keyboard, corpus, metrics, char_set, strategy = "ddako-simulated-annealing", pin = 0
+    ))]
+    fn generate<'py>(
+        &self,
+        py: Python<'py>,
+        keyboard: &str,
+        corpus: &Corpus,
+        metrics: Vec<(String, i16)>,
+        char_set: &str,
+        strategy: &str,
+        pin: usize,
+    ) -> Result<Bound<'py, PyDict>> {
+        let strategy = GenerationStrategy::from_str(strategy, true).map_err(|e| anyhow!(e))?;
+        let metric_data = lookup::metrics(&self.0, keyboard)?;
+        let context = weighted_context(
+            metric_data,
+            corpus.0.clone(),
+            &metrics,
+            char_set,
+            strategy,
+            None,
+        )?
+        .with_swaps(pin, false);
+        let result = context.optimize(0, None, None);
+
+        let names: Vec<&str> = metrics.iter().map(|(name, _)| name.as_str()).collect();
+        let dict = PyDict::new_bound(py);
+        dict.set_item("layout", &result.chars)?;
+        dict.set_item("score", result.score)?;
+        dict.set_item("iterations", result.iterations)?;
+        dict.set_item("metrics", names)?;
+        let percentages = context.percentages(&result.stats);
+        dict.set_item("percentages", PyArray1::from_vec_bound(py, percentages))?;
+        Ok(dict)
+    }
+}
+
+#[pymodule]
+fn keywhisker(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Corpus>()?;
+    m.add_class::<Data>()?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use crate::evaluation::Evaluator;
+use crate::stratify::StratifiedShuffle;
+
+use anyhow::{bail, Context, Error, Result};
+use keycat::{analysis::Analyzer, Layout, Swap};
+use rand::prelude::*;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// A bounded local search `collect` runs on each random layout before
+/// recording it, to sample achievable layouts rather than random ones.
+#[derive(Clone, Copy, Debug)]
+pub enum Refine {
+    /// Try this many random swaps, keeping each one that improves the score
+    Greedy(usize),
+    /// Make up to this many swaps, each the best one there is
+    Steepest(usize),
+}
+
+impl FromStr for Refine {
+    type Err = Error;
+
+    /// `greedy:N` or `steepest:N`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, n) = s
+            .split_once(':')
+            .with_context(|| format!("expected greedy:N or steepest:N but got `{s}`"))?;
+        let n = n.parse().context("invalid refine iterations")?;
+        Ok(match kind {
+            "greedy" => Refine::Greedy(n),
+            "steepest" => Refine::Steepest(n),
+            _ => bail!("unknown refine search {kind} (expected greedy or steepest)"),
+        })
+    }
+}
+
+/// Runs a `Refine` search, scoring layouts with an evaluator and swapping
+/// only what a stratified shuffle allows.
+pub struct Refiner<'a> {
+    search: Refine,
+    analyzer: &'a Analyzer,
+    evaluator: Evaluator,
+    shuffler: &'a StratifiedShuffle,
+    swaps: Vec<Swap>,
+}
+
+impl<'a> Refiner<'a> {
+    pub fn new(
+        search: Refine,
+        analyzer: &'a Analyzer,
+        evaluator: Evaluator,
+        shuffler: &'a StratifiedShuffle,
+    ) -> Self {
+        Refiner {
+            search,
+            analyzer,
+            evaluator,
+            shuffler,
+            swaps: shuffler.swaps(),
+        }
+    }
+
+    /// Improve `layout`, whose stats are `stats`, keeping `stats` up to date.
+    pub fn run(&self, layout: &mut Layout, stats: &mut [f32], rng: &mut impl Rng) {
+        let Refiner {
+            analyzer,
+            evaluator,
+            shuffler,
+            swaps,
+            ..
+        } = self;
+        if swaps.is_empty() {
+            return;
+        }
+        let mut diff = vec![0.0; stats.len()];
+        let mut best = vec![0.0; stats.len()];
+        match self.search {
+            Refine::Greedy(tries) => {
+                for _ in 0..tries {
+                    let swap = swaps.choose(rng).unwrap();
+                    if !shuffler.allows(layout, swap) {
+                        continue;
+                    }
+                    diff.fill(0.0);
+                    analyzer.swap_diff(&mut diff, layout, swap);
+                    if evaluator.improves(stats, &diff) {
+                        layout.swap(swap);
+                        stats.iter_mut().zip(&diff).for_each(|(s, d)| *s += d);
+                    }
+                }
+            }
+            Refine::Steepest(steps) => {
+                for _ in 0..steps {
+                    best.fill(0.0);
+                    let mut best_swap = None;
+                    for swap in swaps.iter().filter(|s| shuffler.allows(layout, s)) {
+                        diff.fill(0.0);
+                        analyzer.swap_diff(&mut diff, layout, swap);
+                        if evaluator.compare_diffs(stats, &diff, &best) == Ordering::Less {
+                            best_swap = Some(swap);
+                            best.copy_from_slice(&diff);
+                        }
+                    }
+                    match best_swap {
+                        Some(swap) if evaluator.improves(stats, &best) => {
+                            layout.swap(swap);
+                            stats.iter_mut().zip(&best).for_each(|(s, d)| *s += d);
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+    }
+}
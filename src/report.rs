@@ -0,0 +1,112 @@
+use crate::analysis::layout_matrices;
+use crate::heatmap::{key_frequencies, key_labels};
+use crate::keyboard::key_positions;
+use crate::svg;
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+use std::fmt::Write;
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:1100px;color:#222}\
+table{border-collapse:collapse}td,th{padding:3px 10px;text-align:right}\
+th:first-child,td:first-child{text-align:left}tr:nth-child(even){background:#f3f3f3}\
+.charts{display:flex;flex-wrap:wrap;gap:1.5em}h3{margin-bottom:.3em}";
+
+/// Render stats, per-metric comparison charts, and frequency heatmaps into one HTML file.
+pub fn report(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    output: &Path,
+) -> Result<()> {
+    let ctx = MetricContext::new(
+        layouts
+            .first()
+            .context("need at least one layout to report on")?,
+        metric_data,
+        corpus,
+    )
+    .context("could not produce metric context")?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+    let matrices = layout_matrices(&ctx, &layouts)?;
+    let stat_lists: Vec<Vec<f32>> = matrices
+        .iter()
+        .map(|matrix| ctx.analyzer.calc_stats(matrix))
+        .collect();
+    let percentages: Vec<Vec<f32>> = stat_lists
+        .iter()
+        .map(|stats| {
+            ctx.metrics
+                .iter()
+                .enumerate()
+                .map(|(i, m)| totals.percentage(stats[i], m.ngram_type))
+                .collect()
+        })
+        .collect();
+
+    let title = layouts
+        .iter()
+        .map(|l| l.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" vs ");
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{STYLE}</style></head><body>\n<h1>{}</h1>\n",
+        svg::escape(&title),
+        svg::escape(&title),
+    );
+
+    html.push_str("<h2>Stats</h2>\n<table><tr><th>Metric</th>");
+    for l in &layouts {
+        let _ = write!(html, "<th>{}</th>", svg::escape(&l.name));
+    }
+    html.push_str("</tr>\n");
+    for (i, m) in ctx.metrics.iter().enumerate() {
+        let _ = write!(html, "<tr><td>{}</td>", svg::escape(&m.name));
+        for pcs in &percentages {
+            let _ = write!(html, "<td>{:.2}%</td>", pcs[i]);
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Comparison</h2>\n<div class=\"charts\">\n");
+    for (i, m) in ctx.metrics.iter().enumerate() {
+        let bars: Vec<(String, f32)> = layouts
+            .iter()
+            .zip(&percentages)
+            .map(|(l, pcs)| (l.name.clone(), pcs[i]))
+            .collect();
+        let _ = writeln!(
+            html,
+            "<div><h3>{}</h3>{}</div>",
+            svg::escape(&m.name),
+            svg::bar_chart(&bars)
+        );
+    }
+    html.push_str("</div>\n");
+
+    let positions = key_positions(&ctx.keyboard);
+    let corpus = &ctx.analyzer.corpus;
+    html.push_str("<h2>Key frequency</h2>\n<div class=\"charts\">\n");
+    for (l, matrix) in layouts.iter().zip(&matrices) {
+        let keys = &matrix.0[..positions.len().min(matrix.0.len())];
+        let labels = key_labels(corpus, keys);
+        let values = key_frequencies(corpus, keys);
+        let _ = writeln!(
+            html,
+            "<div><h3>{}</h3>{}</div>",
+            svg::escape(&l.name),
+            svg::keyboard_heatmap(&positions, &labels, &values)
+        );
+    }
+    html.push_str("</div>\n</body></html>\n");
+
+    if let Some(parent) = output.parent() {
+        create_dir_all(parent).context("couldn't create report directory")?;
+    }
+    write(output, html).context("couldn't write report")?;
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::ddako::simulated_annealing::{Improvement, Snapshot};
+
+use clap::ValueEnum;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+const PLAIN_INTERVAL: Duration = Duration::from_secs(3);
+const JSON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a generation shows its progress.
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ProgressStyle {
+    /// A live dashboard with keyboard controls
+    Tui,
+    /// A status line every few seconds
+    Plain,
+    /// JSON-lines events on stderr
+    Json,
+    None,
+}
+
+/// Hooks an optimizer calls as it runs, for live displays and library users
+/// alike. Every hook does nothing unless implemented.
+pub trait Reporter {
+    /// Called on every evaluated swap, so must be cheap.
+    fn on_evaluation(&mut self) {}
+
+    /// Called once per iteration with where the optimization stands.
+    fn on_iteration(&mut self, _snapshot: &Snapshot) {}
+
+    /// Called whenever a new best layout is found.
+    fn on_improvement(&mut self, _improvement: &Improvement) {}
+
+    /// Called whenever the temperature is set, including while the initial
+    /// temperature is searched for.
+    fn on_temperature_change(&mut self, _temperature: f32, _cooling_interval: f32) {}
+
+    /// Called once the run is over, with its final score.
+    fn on_finish(&mut self, _iterations: u32, _score: f32) {}
+}
+
+/// A status line on stderr every few seconds.
+pub struct PlainReporter {
+    run: u64,
+    last_print: Instant,
+}
+
+impl PlainReporter {
+    pub fn new(run: u64) -> Self {
+        PlainReporter {
+            run,
+            last_print: Instant::now(),
+        }
+    }
+}
+
+impl Reporter for PlainReporter {
+    fn on_iteration(&mut self, snapshot: &Snapshot) {
+        if self.last_print.elapsed() >= PLAIN_INTERVAL {
+            self.last_print = Instant::now();
+            eprintln!(
+                "run {}: iteration {}, best {:.4}, current {:.4}, stays {}/{}",
+                self.run,
+                snapshot.iteration,
+                snapshot.best,
+                snapshot.current,
+                snapshot.stays,
+                snapshot.stopping_point
+            );
+        }
+    }
+}
+
+/// One JSON object per line on stderr, for scripts and job schedulers.
+pub struct JsonReporter {
+    run: u64,
+    last_print: Instant,
+}
+
+impl JsonReporter {
+    pub fn new(run: u64) -> Self {
+        JsonReporter {
+            run,
+            last_print: Instant::now(),
+        }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn on_iteration(&mut self, snapshot: &Snapshot) {
+        if self.last_print.elapsed() >= JSON_INTERVAL {
+            self.last_print = Instant::now();
+            let event = json!({
+                "event": "iteration",
+                "run": self.run,
+                "iteration": snapshot.iteration,
+                "best": snapshot.best,
+                "current": snapshot.current,
+                "stays": snapshot.stays,
+                "stopping_point": snapshot.stopping_point,
+                "temperature": snapshot.temperature,
+                "acceptance_rate": snapshot.acceptance_rate,
+            });
+            eprintln!("{event}");
+        }
+    }
+
+    fn on_finish(&mut self, iterations: u32, score: f32) {
+        let event = json!({
+            "event": "run",
+            "run": self.run,
+            "iterations": iterations,
+            "score": score,
+        });
+        eprintln!("{event}");
+    }
+}
+
+pub struct NullReporter;
+
+impl Reporter for NullReporter {}
@@ -0,0 +1,147 @@
+//! How much layouts' stats depend on the particular sample of text in the
+//! corpus, by re-analyzing them on bootstrap resamples of its ngram counts.
+
+use crate::analysis::{context_stats, resolve_metrics};
+use crate::corpus::is_scaled;
+use crate::summary::Summary;
+
+use anyhow::{bail, Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+/// How the corpus is resampled.
+pub struct Resampling {
+    /// How many resampled corpora to analyze
+    pub samples: usize,
+    /// How many characters of text the corpus stands for, if not its own count
+    pub sample_size: Option<u64>,
+    pub seed: u64,
+}
+
+/// A Poisson draw with mean `mean`: exact for small means, and by its normal
+/// approximation for large ones.
+fn resample(mean: f64, rng: &mut impl Rng) -> f64 {
+    match mean {
+        m if m <= 0.0 => 0.0,
+        m if m < 64.0 => {
+            let limit = (-m).exp();
+            let (mut k, mut p) = (0, rng.gen::<f64>());
+            while p > limit {
+                k += 1;
+                p *= rng.gen::<f64>();
+            }
+            k as f64
+        }
+        m => {
+            let u = rng.gen::<f64>().max(f64::MIN_POSITIVE);
+            let z = (-2.0 * u.ln()).sqrt() * (2.0 * PI * rng.gen::<f64>()).cos();
+            (m + z * m.sqrt()).max(0.0)
+        }
+    }
+}
+
+/// `corpus` with every ngram count redrawn around itself, as if it had been
+/// counted from `scale` times as much text and scaled back. Resampling counts
+/// independently like this approximates resampling the text itself.
+fn perturb(corpus: &Corpus, scale: f64, rng: &mut impl Rng) -> Corpus {
+    let mut perturbed = corpus.clone();
+    for counts in [
+        &mut perturbed.chars,
+        &mut perturbed.bigrams,
+        &mut perturbed.skipgrams,
+        &mut perturbed.trigrams,
+    ] {
+        counts
+            .iter_mut()
+            .for_each(|c| *c = (resample(*c as f64 * scale, rng) / scale).round() as u32);
+    }
+    perturbed
+}
+
+/// Print each layout's `metrics` (or all of them) with a `confidence` interval
+/// from resamples of the corpus. Layouts after the first also get an interval
+/// on their difference from it, taken over the same resamples; a `*` marks
+/// differences whose interval excludes zero.
+///
+/// Blended corpora have no count of text to resample at, so they need a sample
+/// size; without one every interval would collapse to nothing.
+pub fn robustness(
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: &[LayoutData],
+    metrics: &[String],
+    resampling: &Resampling,
+    confidence: f64,
+) -> Result<()> {
+    let chars = corpus.chars.iter().map(|c| *c as f64).sum::<f64>().max(1.0);
+    let scale = match resampling.sample_size {
+        Some(size) => size as f64 / chars,
+        None if is_scaled(&corpus) => bail!(
+            "the corpus is a blend whose counts are rescaled, not counted; give the size of \
+             the text it stands for with --sample-size"
+        ),
+        None => 1.0,
+    };
+    let shown = match metrics {
+        [] => (0..metric_data.metrics.len()).collect(),
+        names => resolve_metrics(names, &metric_data)?,
+    };
+    let first = layouts
+        .first()
+        .context("need at least one layout to test")?;
+    let mut ctx = MetricContext::new(first, metric_data, corpus.clone())
+        .context("could not produce metric context")?;
+    let actual = context_stats(&ctx, layouts)?;
+
+    let mut rng = StdRng::seed_from_u64(resampling.seed);
+    // Percentages by layout, then metric, then sample
+    let mut values = vec![vec![vec![]; shown.len()]; layouts.len()];
+    for _ in 0..resampling.samples {
+        ctx.analyzer.corpus = perturb(&corpus, scale, &mut rng);
+        for (l, stats) in context_stats(&ctx, layouts)?.iter().enumerate() {
+            for (i, m) in shown.iter().enumerate() {
+                values[l][i].push(stats.metrics[*m].percentage as f64);
+            }
+        }
+    }
+
+    let width = shown
+        .iter()
+        .map(|m| ctx.metrics[*m].name.len())
+        .max()
+        .unwrap_or(0);
+    for (l, stats) in actual.iter().enumerate() {
+        println!("{}", stats.name);
+        for (i, m) in shown.iter().enumerate() {
+            let (lo, hi) = Summary::new(&values[l][i]).interval(confidence);
+            print!(
+                "  {:width$}  {:>8.3}%  [{lo:.3}%, {hi:.3}%]",
+                ctx.metrics[*m].name, stats.metrics[*m].percentage
+            );
+            if l > 0 {
+                let delta = stats.metrics[*m].percentage - actual[0].metrics[*m].percentage;
+                let diffs: Vec<f64> = values[l][i]
+                    .iter()
+                    .zip(&values[0][i])
+                    .map(|(a, b)| a - b)
+                    .collect();
+                let (lo, hi) = Summary::new(&diffs).interval(confidence);
+                let mark = if lo > 0.0 || hi < 0.0 { " *" } else { "" };
+                print!(
+                    "  vs {} {delta:+.3}%  [{lo:+.3}%, {hi:+.3}%]{mark}",
+                    actual[0].name
+                );
+            }
+            println!();
+        }
+    }
+    println!();
+    println!(
+        "{}% intervals from {} resamples of the corpus",
+        confidence * 100.0,
+        resampling.samples
+    );
+    Ok(())
+}
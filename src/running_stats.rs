@@ -0,0 +1,62 @@
+use std::ops::Deref;
+use tracing::debug;
+
+/// A layout's stats kept up to date by adding each accepted swap's diff,
+/// instead of recalculating them. Reads as the f32 stats the analyzer and
+/// evaluator use; with the `f64-stats` feature the sums are kept in f64, so
+/// that long runs of small diffs don't drift.
+pub struct RunningStats {
+    stats: Vec<f32>,
+    #[cfg(feature = "f64-stats")]
+    exact: Vec<f64>,
+}
+
+impl RunningStats {
+    pub fn new(stats: Vec<f32>) -> Self {
+        RunningStats {
+            #[cfg(feature = "f64-stats")]
+            exact: stats.iter().map(|s| *s as f64).collect(),
+            stats,
+        }
+    }
+
+    #[cfg(feature = "f64-stats")]
+    pub fn add(&mut self, diff: &[f32]) {
+        for ((exact, stat), d) in self.exact.iter_mut().zip(&mut self.stats).zip(diff) {
+            *exact += *d as f64;
+            *stat = *exact as f32;
+        }
+    }
+
+    #[cfg(not(feature = "f64-stats"))]
+    pub fn add(&mut self, diff: &[f32]) {
+        self.stats.iter_mut().zip(diff).for_each(|(s, d)| *s += d);
+    }
+
+    /// The largest difference between these stats and `fresh` ones calculated
+    /// from scratch, relative to the fresh stat, or absolute when it's near zero.
+    pub fn drift(&self, fresh: &[f32]) -> f32 {
+        self.stats
+            .iter()
+            .zip(fresh)
+            .map(|(s, f)| (s - f).abs() / f.abs().max(1.0))
+            .fold(0.0, f32::max)
+    }
+
+    /// Log how far these stats have drifted from `fresh` ones.
+    pub fn report_drift(&self, fresh: &[f32]) {
+        debug!(
+            drift = self.drift(fresh),
+            f64 = cfg!(feature = "f64-stats"),
+            "accumulated stats against recalculated"
+        );
+    }
+}
+
+impl Deref for RunningStats {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        &self.stats
+    }
+}
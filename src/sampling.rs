@@ -0,0 +1,128 @@
+use crate::evaluation::Evaluator;
+
+use keycat::analysis::{Analyzer, Nstroke};
+use keycat::{Corpus, Layout, NgramType, Swap};
+use rand::prelude::*;
+use std::collections::BTreeMap;
+
+// Layout changes between recalculations of each position's cost, which reads every stroke
+const COST_REFRESH: u32 = 1000;
+
+/// Chooses the swaps a run tries. Uniformly at random without a bias; with
+/// one, that share of choices first picks a position, half by how often the
+/// character on it is typed and half by how much its strokes add to the
+/// score, then one of that position's swaps. Frequent characters and the
+/// worst placed keys are then moved more often than rare punctuation.
+#[derive(Clone)]
+pub struct SwapSampler {
+    // Each position that can be swapped, and the indices of the swaps moving it
+    positions: Vec<(usize, Vec<usize>)>,
+    frequencies: Vec<f64>,
+    // What each position's strokes add to the score, as of the last refresh
+    costs: Vec<f64>,
+    // Running sums of the weights of `positions`, for the current layout
+    cumulative: Vec<f64>,
+    changes: u32,
+    bias: f32,
+}
+
+impl SwapSampler {
+    /// A sampler for `swaps`, which must be told the layout with `update`
+    /// before it picks by anything but chance.
+    pub fn new(swaps: &[Swap], corpus: &Corpus, bias: f32) -> Self {
+        let mut positions: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, swap) in swaps.iter().enumerate() {
+            positions.entry(swap.a).or_default().push(i);
+        }
+        SwapSampler {
+            positions: positions.into_iter().collect(),
+            frequencies: corpus.chars.iter().map(|c| *c as f64).collect(),
+            costs: vec![],
+            cumulative: vec![],
+            changes: 0,
+            bias,
+        }
+    }
+
+    /// Weigh the positions for `layout`, after it's been shuffled or swapped.
+    pub fn update(&mut self, layout: &Layout, analyzer: &Analyzer, evaluator: &Evaluator) {
+        if self.bias <= 0.0 {
+            return;
+        }
+        if self.changes.is_multiple_of(COST_REFRESH) {
+            self.costs = position_costs(analyzer, evaluator, layout);
+        }
+        self.changes = self.changes.wrapping_add(1);
+
+        let frequency = |p: usize| self.frequencies.get(layout.0[p]).copied().unwrap_or(0.0);
+        let cost = |p: usize| self.costs.get(p).copied().unwrap_or(0.0);
+        let frequency_total: f64 = self.positions.iter().map(|(p, _)| frequency(*p)).sum();
+        let cost_total: f64 = self.positions.iter().map(|(p, _)| cost(*p)).sum();
+        let share = |x: f64, total: f64| if total > 0.0 { x / total } else { 0.0 };
+        let mut sum = 0.0;
+        self.cumulative = self
+            .positions
+            .iter()
+            .map(|(p, _)| {
+                sum += share(frequency(*p), frequency_total) + share(cost(*p), cost_total);
+                sum
+            })
+            .collect();
+    }
+
+    /// One of `swaps`, the list the sampler was made from.
+    pub fn choose<'s>(&self, swaps: &'s [Swap], rng: &mut impl Rng) -> &'s Swap {
+        // Without a bias, draw exactly what a uniform choice would, so seeded runs don't change
+        if self.bias > 0.0 && rng.gen::<f32>() < self.bias {
+            if let Some(total) = self.cumulative.last().filter(|t| **t > 0.0) {
+                let target = rng.gen::<f64>() * total;
+                let i = self.cumulative.partition_point(|c| *c <= target);
+                let (_, indices) = &self.positions[i.min(self.positions.len() - 1)];
+                return &swaps[*indices.choose(rng).unwrap()];
+            }
+        }
+        swaps.choose(rng).unwrap()
+    }
+}
+
+/// What the strokes through each position add to the score on `layout`, by
+/// how often they're typed and the weights of the metrics they count for.
+/// Positions that only help the score count as adding nothing.
+fn position_costs(analyzer: &Analyzer, evaluator: &Evaluator, layout: &Layout) -> Vec<f64> {
+    let (data, corpus) = (&analyzer.data, &analyzer.corpus);
+    let mut weights = vec![0.0; data.metrics.len()];
+    for (m, weight) in evaluator.weights() {
+        weights[*m] += *weight as f64;
+    }
+    let c = |p: &usize| layout.0[*p];
+    let count = |stroke: &Nstroke, ngram_type: NgramType| -> f64 {
+        let count = match stroke {
+            Nstroke::Monostroke(a) => corpus.chars[c(a)],
+            Nstroke::Bistroke([a, b]) if ngram_type == NgramType::Skipgram => {
+                corpus.skipgrams[corpus.bigram_idx(c(a), c(b))]
+            }
+            Nstroke::Bistroke([a, b]) => corpus.bigrams[corpus.bigram_idx(c(a), c(b))],
+            Nstroke::Tristroke([a, b, d]) => corpus.trigrams[corpus.trigram_idx(c(a), c(b), c(d))],
+        };
+        count as f64
+    };
+    data.position_strokes
+        .iter()
+        .map(|strokes| {
+            let cost: f64 = strokes
+                .iter()
+                .map(|i| &data.strokes[*i])
+                .flat_map(|s| s.amounts.iter().map(move |a| (s, a)))
+                .map(|(s, a)| {
+                    let weight = weights[a.metric] * a.amount as f64;
+                    if weight == 0.0 {
+                        0.0
+                    } else {
+                        weight * count(&s.nstroke, data.metrics[a.metric])
+                    }
+                })
+                .sum();
+            cost.max(0.0)
+        })
+        .collect()
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use keycat::{analysis::Analyzer, Corpus};
+use keymeow::{MetricContext, MetricData};
+use keywhisker::analysis::{context_stats, layout_from_charset, resolve_weights};
+use keywhisker::cache::filtered_metric_data;
+use keywhisker::evaluation::Evaluator;
+use keywhisker::export::load_layout;
+use keywhisker::optimization::{GenerationStrategy, OptimizationContext};
+use keywhisker::{interrupt, lookup};
+use km_data::Data as KeymeowData;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+use tracing::{info, warn};
+
+// How often idle workers check for Ctrl-C
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// `POST /stats`: every metric for each layout, given by name or as a string of keys.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatsRequest {
+    keyboard: String,
+    corpus: String,
+    layouts: Vec<String>,
+}
+
+/// `POST /generate`: one run of a generation strategy.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenerateRequest {
+    keyboard: String,
+    corpus: String,
+    /// `[name, weight]` pairs
+    metrics: Vec<(String, i16)>,
+    char_set: String,
+    #[serde(default = "default_strategy")]
+    strategy: String,
+    #[serde(default)]
+    pin: usize,
+}
+
+fn default_strategy() -> String {
+    "ddako-simulated-annealing".to_string()
+}
+
+// A keyboard and corpus by name, and a number of positions
+type ContextKey = (String, String, usize);
+// Also the metrics kept, in order
+type AnalyzerKey = (ContextKey, Vec<usize>);
+
+/// What's been loaded so far, kept for later requests.
+struct Cache<'a> {
+    data: &'a KeymeowData,
+    corpora: Mutex<HashMap<String, Arc<Corpus>>>,
+    keyboards: Mutex<HashMap<String, Arc<MetricData>>>,
+    contexts: Mutex<HashMap<ContextKey, Arc<MetricContext>>>,
+    analyzers: Mutex<HashMap<AnalyzerKey, Arc<Analyzer>>>,
+}
+
+/// The cached value for `key`, loading it on a miss. The lock isn't held while
+/// loading, so a slow load doesn't hold up requests for anything else.
+fn cached<K: Eq + Hash, V>(
+    cache: &Mutex<HashMap<K, Arc<V>>>,
+    key: K,
+    load: impl FnOnce() -> Result<V>,
+) -> Result<Arc<V>> {
+    if let Some(value) = cache.lock().unwrap().get(&key) {
+        return Ok(value.clone());
+    }
+    let value = Arc::new(load()?);
+    Ok(cache.lock().unwrap().entry(key).or_insert(value).clone())
+}
+
+impl Cache<'_> {
+    fn corpus(&self, name: &str) -> Result<Arc<Corpus>> {
+        cached(&self.corpora, name.to_string(), || {
+            info!("loading corpus {name}");
+            lookup::corpus(self.data, name)
+        })
+    }
+
+    fn keyboard(&self, name: &str) -> Result<Arc<MetricData>> {
+        cached(&self.keyboards, name.to_string(), || {
+            info!("loading keyboard {name}");
+            lookup::metrics(self.data, name)
+        })
+    }
+
+    fn stats(&self, request: StatsRequest) -> Result<Value> {
+        let metric_data = self.keyboard(&request.keyboard)?;
+        let layouts: Result<Vec<_>> = request
+            .layouts
+            .iter()
+            .map(|l| load_layout(self.data, l, &metric_data.keyboard))
+            .collect();
+        let layouts = layouts?;
+        let first = layouts.first().context("need at least one layout")?;
+        let corpus = self.corpus(&request.corpus)?;
+        // The context is sized to a layout, so each size needs its own
+        let len = MetricContext::layout_matrix(first, &metric_data.keyboard, &corpus)
+            .with_context(|| format!("layout {} incompatible with keyboard", first.name))?
+            .0
+            .len();
+        let key = (request.keyboard.clone(), request.corpus.clone(), len);
+        let ctx = cached(&self.contexts, key, || {
+            MetricContext::new(first, (*metric_data).clone(), (*corpus).clone())
+                .context("could not produce metric context")
+        })?;
+        Ok(serde_json::to_value(context_stats(&ctx, &layouts)?)?)
+    }
+
+    fn generate(&self, request: GenerateRequest) -> Result<Value> {
+        let strategy =
+            GenerationStrategy::from_str(&request.strategy, true).map_err(|e| anyhow!(e))?;
+        let metric_data = self.keyboard(&request.keyboard)?;
+        let corpus = self.corpus(&request.corpus)?;
+        let weights = resolve_weights(&request.metrics, &metric_data)?;
+        let mut used: Vec<usize> = weights.iter().map(|(m, _)| *m).collect();
+        used.sort_unstable();
+        used.dedup();
+        let layout = layout_from_charset(&corpus, &metric_data, &request.char_set);
+        let len = layout.0.len();
+        let key = (request.keyboard.clone(), request.corpus.clone(), len);
+        let analyzer = cached(&self.analyzers, (key, used.clone()), || {
+            let data = filtered_metric_data((*metric_data).clone(), &used, len, None);
+            Ok(Analyzer::from(data, (*corpus).clone()))
+        })?;
+        let keyboard = metric_data.keyboard.clone();
+        let context = OptimizationContext::new(
+            strategy,
+            layout,
+            analyzer,
+            Evaluator::from(weights),
+            keyboard,
+        )
+        .with_swaps(request.pin, false);
+        let result = context.optimize(0, None, None);
+        let metrics: Vec<&str> = request.metrics.iter().map(|(m, _)| m.as_str()).collect();
+        Ok(json!({
+            "layout": result.chars,
+            "score": result.score,
+            "iterations": result.iterations,
+            "metrics": metrics,
+            "percentages": context.percentages(&result.stats),
+        }))
+    }
+
+    /// The response body for `request`, or `None` if nothing's at its URL.
+    fn handle(&self, request: &mut Request) -> Result<Option<Value>> {
+        let names = |map: &HashMap<String, _>| {
+            let mut names: Vec<&String> = map.keys().collect();
+            names.sort();
+            json!(names)
+        };
+        let body = match (request.method(), request.url()) {
+            (Method::Get, "/corpora") => names(&self.data.corpora),
+            (Method::Get, "/keyboards") => names(&self.data.keyboards),
+            (Method::Post, "/stats") => {
+                let body = serde_json::from_reader(request.as_reader())
+                    .context("invalid stats request")?;
+                self.stats(body)?
+            }
+            (Method::Post, "/generate") => {
+                let body = serde_json::from_reader(request.as_reader())
+                    .context("invalid generate request")?;
+                self.generate(body)?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(body))
+    }
+}
+
+/// Answer analysis requests over HTTP on `address` with `threads` workers
+/// until interrupted.
+pub fn serve(data: &KeymeowData, address: &str, threads: u64) -> Result<()> {
+    let server = tiny_http::Server::http(address)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| format!("couldn't listen on {address}"))?;
+    let cache = Cache {
+        data,
+        corpora: Mutex::default(),
+        keyboards: Mutex::default(),
+        contexts: Mutex::default(),
+        analyzers: Mutex::default(),
+    };
+    let json_type: Header = "Content-Type: application/json".parse().unwrap();
+    eprintln!("listening on http://{address}");
+
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                while !interrupt::interrupted() {
+                    let mut request = match server.recv_timeout(POLL_INTERVAL) {
+                        Ok(Some(request)) => request,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            warn!("couldn't receive request: {e}");
+                            break;
+                        }
+                    };
+                    let (status, body) = match cache.handle(&mut request) {
+                        Ok(Some(body)) => (200, body),
+                        Ok(None) => (404, json!({ "error": "not found" })),
+                        Err(e) => (400, json!({ "error": format!("{e:#}") })),
+                    };
+                    info!("{} {} {status}", request.method(), request.url());
+                    let response = Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(json_type.clone());
+                    if let Err(e) = request.respond(response) {
+                        warn!("couldn't send response: {e}");
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::fingers::Hand;
+use crate::keyboard::{hand_positions, home_row, pinned_positions};
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+use keycat::{Corpus, CorpusChar, Layout, Swap};
+use keymeow::Keyboard;
+use rand::prelude::*;
+
+/// A structural constraint on the random layouts `collect` analyzes.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Stratum {
+    /// Put every vowel (aeiou) on the same hand, either one
+    VowelsOneHand,
+    /// Keep the char set's home row characters where they are
+    HomeRow,
+}
+
+/// Shuffles a layout's unpinned keys while keeping to a set of strata.
+pub struct StratifiedShuffle {
+    free: Vec<usize>,
+    // The vowels, and each hand's free keys they may go on
+    vowels: Option<(Vec<CorpusChar>, Vec<Vec<usize>>)>,
+}
+
+impl StratifiedShuffle {
+    /// Shuffle all but the first `pin` positions of `layout` and the `also_pinned`
+    /// ones, keeping to `strata`.
+    pub fn new(
+        corpus: &Corpus,
+        keyboard: &Keyboard,
+        layout: &Layout,
+        pin: usize,
+        also_pinned: &[usize],
+        strata: &[Stratum],
+    ) -> Result<Self> {
+        let mut pinned = pinned_positions(0..layout.0.len(), pin, also_pinned);
+        if strata.contains(&Stratum::HomeRow) {
+            pinned.extend(home_row(keyboard));
+        }
+        let free: Vec<usize> = (0..layout.0.len())
+            .filter(|p| !pinned.contains(p))
+            .collect();
+
+        let vowels = match strata.contains(&Stratum::VowelsOneHand) {
+            true => {
+                let mut vowels: Vec<CorpusChar> =
+                    "aeiou".chars().map(|c| corpus.corpus_char(c)).collect();
+                // Vowels missing from the corpus map to the empty key
+                vowels.retain(|c| *c != 0);
+                let is_vowel = |p: &usize| vowels.contains(&layout.0[*p]);
+                let count = free.iter().filter(|p| is_vowel(p)).count();
+                let mut hands = vec![];
+                for hand in [Hand::Left, Hand::Right] {
+                    let keys = hand_positions(keyboard, hand);
+                    // A vowel pinned to the other hand rules this one out
+                    if pinned.iter().any(|p| is_vowel(p) && !keys.contains(p)) {
+                        continue;
+                    }
+                    let keys: Vec<usize> = keys.into_iter().filter(|p| free.contains(p)).collect();
+                    if keys.len() >= count {
+                        hands.push(keys);
+                    }
+                }
+                ensure!(!hands.is_empty(), "no hand can hold all the vowels");
+                Some((vowels, hands))
+            }
+            false => None,
+        };
+        Ok(StratifiedShuffle { free, vowels })
+    }
+
+    /// Every swap of two unpinned positions, in both orders.
+    pub fn swaps(&self) -> Vec<Swap> {
+        let free = &self.free;
+        free.iter()
+            .flat_map(|a| {
+                free.iter()
+                    .filter(move |b| *b != a)
+                    .map(move |b| Swap::new(*a, *b))
+            })
+            .collect()
+    }
+
+    /// Whether `swap` keeps `layout` within the strata.
+    pub fn allows(&self, layout: &Layout, swap: &Swap) -> bool {
+        let Some((vowels, hands)) = &self.vowels else {
+            return true;
+        };
+        let (a, b) = (
+            vowels.contains(&layout.0[swap.a]),
+            vowels.contains(&layout.0[swap.b]),
+        );
+        // A vowel may only move within its hand
+        let (from, to) = match (a, b) {
+            (true, false) => (swap.a, swap.b),
+            (false, true) => (swap.b, swap.a),
+            _ => return true,
+        };
+        hands
+            .iter()
+            .any(|keys| keys.contains(&from) && keys.contains(&to))
+    }
+
+    pub fn shuffle(&self, layout: &mut Layout, rng: &mut impl Rng) {
+        let mut chars: Vec<CorpusChar> = self.free.iter().map(|p| layout.0[*p]).collect();
+        let mut positions = self.free.clone();
+        chars.shuffle(rng);
+        positions.shuffle(rng);
+        if let Some((vowels, hands)) = &self.vowels {
+            // Vowels first, onto keys of the chosen hand, then everything else
+            chars.sort_by_key(|c| !vowels.contains(c));
+            let count = chars.iter().filter(|c| vowels.contains(c)).count();
+            let mut hand = hands.choose(rng).unwrap().clone();
+            hand.shuffle(rng);
+            hand.truncate(count);
+            positions.retain(|p| !hand.contains(p));
+            hand.append(&mut positions);
+            positions = hand;
+        }
+        for (p, c) in positions.into_iter().zip(chars) {
+            layout.0[p] = c;
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::percentiles::read_distribution;
+
+use anyhow::{ensure, Result};
+use std::path::Path;
+
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The spread of a non-empty sample, for every command that reports one.
+pub struct Summary {
+    pub mean: f64,
+    /// The population standard deviation
+    pub std_dev: f64,
+    sorted: Vec<f64>,
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        let mean = mean(values);
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Summary {
+            mean,
+            std_dev: variance.sqrt(),
+            sorted,
+        }
+    }
+
+    pub fn min(&self) -> f64 {
+        self.sorted[0]
+    }
+
+    pub fn max(&self) -> f64 {
+        self.sorted[self.sorted.len() - 1]
+    }
+
+    /// The `p`th percentile, interpolating between neighbours.
+    pub fn percentile(&self, p: f64) -> f64 {
+        let sorted = &self.sorted;
+        let rank = p / 100.0 * (sorted.len() - 1) as f64;
+        let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+        sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+    }
+
+    /// The central `confidence` interval.
+    pub fn interval(&self, confidence: f64) -> (f64, f64) {
+        let tail = (1.0 - confidence) / 2.0 * 100.0;
+        (self.percentile(tail), self.percentile(100.0 - tail))
+    }
+}
+
+/// Pearson correlation of two equally long columns, or 0 if either is constant.
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let (mut var_a, mut var_b) = (0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    match var_a * var_b {
+        v if v > 0.0 => covariance / v.sqrt(),
+        _ => 0.0,
+    }
+}
+
+/// Print each metric's mean, standard deviation, range and percentiles in a
+/// csv written by `collect`, then the correlation between every two metrics.
+pub fn summarize(path: &Path) -> Result<()> {
+    let columns: Vec<(String, Vec<f64>)> = read_distribution(path)?
+        .into_iter()
+        .map(|(name, values)| (name, values.into_iter().map(f64::from).collect()))
+        .collect();
+    let rows = columns.first().map_or(0, |(_, values)| values.len());
+    ensure!(rows > 0, "{} has no rows", path.display());
+    let width = columns
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+
+    println!("{rows} layouts");
+    print!(
+        "{:width$}{:>9}{:>9}{:>9}",
+        "Metric", "Mean", "Std dev", "Min"
+    );
+    for p in PERCENTILES {
+        print!("{:>9}", format!("p{p}"));
+    }
+    println!("{:>9}", "Max");
+    for (name, values) in &columns {
+        let summary = Summary::new(values);
+        let Summary { mean, std_dev, .. } = summary;
+        print!(
+            "{name:width$}{mean:>9.3}{std_dev:>9.3}{:>9.3}",
+            summary.min()
+        );
+        for p in PERCENTILES {
+            print!("{:>9.3}", summary.percentile(p));
+        }
+        println!("{:>9.3}", summary.max());
+    }
+
+    println!();
+    println!("Correlations");
+    print!("{:width$} ", "");
+    for (name, _) in &columns {
+        print!("{name:>width$} ");
+    }
+    println!();
+    for (name, a) in &columns {
+        print!("{name:width$} ");
+        for (_, b) in &columns {
+            print!("{:>width$.3} ", correlation(a, b));
+        }
+        println!();
+    }
+    Ok(())
+}
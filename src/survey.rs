@@ -0,0 +1,72 @@
+use crate::analysis::{csv_field, get_metric};
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Analyze each of `layouts` that fits the keyboard, writing a csv like
+/// `collect`'s with a row per layout: each metric's percentage, then the
+/// layout's name in a last `layout` column.
+pub fn survey(
+    metric_names: &[String],
+    metric_data: MetricData,
+    corpus: Corpus,
+    layouts: Vec<LayoutData>,
+    output: &Path,
+) -> Result<()> {
+    let metrics: Result<Vec<usize>> = metric_names
+        .iter()
+        .map(|name| {
+            get_metric(name, &metric_data).with_context(|| format!("invalid metric {name}"))
+        })
+        .collect();
+    let metrics = metrics?;
+    let first = layouts
+        .iter()
+        .find(|l| MetricContext::layout_matrix(l, &metric_data.keyboard, &corpus).is_some())
+        .context("no layouts fit this keyboard")?;
+    let ctx = MetricContext::new(first, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let totals = ctx.layout.totals(&ctx.analyzer.corpus);
+
+    let mut writer: Box<dyn Write> = if output == Path::new("-") {
+        Box::new(std::io::stdout().lock())
+    } else {
+        if let Some(parent) = output.parent() {
+            create_dir_all(parent).context("couldn't create data directory")?;
+        }
+        Box::new(BufWriter::new(
+            File::create(output).context("couldn't create data file")?,
+        ))
+    };
+    let header: String = metric_names.iter().map(|m| format!("{m},")).collect();
+    writeln!(writer, "{header}layout")?;
+
+    let mut rows = 0;
+    for l in &layouts {
+        let Some(matrix) = MetricContext::layout_matrix(l, &ctx.keyboard, &ctx.analyzer.corpus)
+        else {
+            warn!("skipping {}: incompatible with keyboard", l.name);
+            continue;
+        };
+        let stats = ctx.analyzer.calc_stats(&matrix);
+        for m in &metrics {
+            write!(
+                writer,
+                "{},",
+                totals.percentage(stats[*m], ctx.metrics[*m].ngram_type)
+            )?;
+        }
+        writeln!(writer, "{}", csv_field(&l.name))?;
+        rows += 1;
+    }
+    writer.flush()?;
+    if output != Path::new("-") {
+        eprintln!("Wrote {rows} layouts to {}", output.display());
+    }
+    Ok(())
+}
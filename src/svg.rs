@@ -0,0 +1,79 @@
+use std::fmt::Write;
+
+const UNIT: f32 = 48.0;
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// White-to-red fill for a value in 0..=1.
+pub fn heat_color(t: f32) -> String {
+    let t = t.clamp(0.0, 1.0);
+    format!("hsl(0, 85%, {:.0}%)", 100.0 - 45.0 * t)
+}
+
+/// Keys drawn at their physical positions, filled by `values` relative to the largest one.
+pub fn keyboard_heatmap(positions: &[(f32, f32)], labels: &[String], values: &[f32]) -> String {
+    let width = positions.iter().map(|(x, _)| x + 1.0).fold(0.0, f32::max) * UNIT;
+    let height = positions.iter().map(|(_, y)| y + 1.0).fold(0.0, f32::max) * UNIT;
+    let max = values.iter().copied().fold(0.0, f32::max);
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" font-family="monospace">"#
+    );
+    for (i, (x, y)) in positions.iter().enumerate() {
+        let value = values.get(i).copied().unwrap_or(0.0);
+        let t = if max > 0.0 { value / max } else { 0.0 };
+        let label = labels.get(i).map_or("", String::as_str);
+        let _ = write!(
+            svg,
+            r#"<g><title>{title} {value:.2}%</title><rect x="{rx}" y="{ry}" width="{w}" height="{w}" rx="4" fill="{fill}" stroke="dimgray"/><text x="{tx}" y="{ty}" text-anchor="middle" font-size="18">{title}</text></g>"#,
+            title = escape(label),
+            rx = x * UNIT + 2.0,
+            ry = y * UNIT + 2.0,
+            w = UNIT - 4.0,
+            fill = heat_color(t),
+            tx = x * UNIT + UNIT / 2.0,
+            ty = y * UNIT + UNIT / 2.0 + 6.0,
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Horizontal bars, one per (label, value), scaled to the largest value.
+pub fn bar_chart(bars: &[(String, f32)]) -> String {
+    let label_width = 160.0;
+    let bar_width = 320.0;
+    let row = 22.0;
+    let max = bars.iter().map(|(_, v)| *v).fold(0.0, f32::max);
+    let height = row * bars.len() as f32;
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{height}" font-family="monospace" font-size="13">"#,
+        label_width + bar_width + 80.0
+    );
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let y = i as f32 * row;
+        let w = if max > 0.0 {
+            bar_width * value / max
+        } else {
+            0.0
+        };
+        let _ = write!(
+            svg,
+            r#"<text x="{lx}" y="{ty}" text-anchor="end">{label}</text><rect x="{label_width}" y="{ry}" width="{w}" height="{h}" fill="steelblue"/><text x="{vx}" y="{ty}">{value:.2}%</text>"#,
+            lx = label_width - 6.0,
+            ty = y + row - 7.0,
+            label = escape(label),
+            ry = y + 3.0,
+            h = row - 6.0,
+            vx = label_width + w + 4.0,
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,85 @@
+use crate::analysis::get_metric;
+
+use anyhow::{bail, Context, Result};
+use keymeow::MetricData;
+use std::fmt::Write;
+
+pub enum Segment {
+    Text(String),
+    Name,
+    Metric {
+        index: usize,
+        precision: Option<usize>,
+    },
+}
+
+/// Parse a template like `{name}\t{sfb:.2}`, where placeholders are `name` or a
+/// metric name/short name with an optional `:.N` precision. `{{`/`}}` escape braces.
+pub fn parse(template: &str, metric_data: &MetricData) -> Result<Vec<Segment>> {
+    let template = template.replace("\\t", "\t").replace("\\n", "\n");
+    let mut segments = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                let (key, spec) = match placeholder.split_once(':') {
+                    Some((key, spec)) => (key, Some(spec)),
+                    None => (placeholder.as_str(), None),
+                };
+                let precision = spec
+                    .map(|spec| {
+                        spec.strip_prefix('.')
+                            .and_then(|p| p.parse().ok())
+                            .with_context(|| format!("invalid format spec `{spec}`"))
+                    })
+                    .transpose()?;
+                segments.push(match key {
+                    "name" => Segment::Name,
+                    metric => Segment::Metric {
+                        index: get_metric(metric, metric_data)
+                            .with_context(|| format!("invalid metric {metric}"))?,
+                        precision,
+                    },
+                });
+            }
+            '}' => bail!("unmatched `}}` in template"),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+pub fn render(segments: &[Segment], name: &str, percentages: &[f32]) -> String {
+    let mut output = String::new();
+    for segment in segments {
+        let _ = match segment {
+            Segment::Text(text) => write!(output, "{text}"),
+            Segment::Name => write!(output, "{name}"),
+            Segment::Metric {
+                index,
+                precision: Some(p),
+            } => write!(output, "{:.*}", p, percentages[*index]),
+            Segment::Metric {
+                index,
+                precision: None,
+            } => write!(output, "{}", percentages[*index]),
+        };
+    }
+    output
+}
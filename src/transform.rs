@@ -0,0 +1,188 @@
+use crate::blank;
+use crate::export::{layout_chars, layout_data, load_layout};
+use crate::fingers::finger_index;
+use crate::keyboard::mirror_positions;
+use crate::naming::home_row_name;
+
+use anyhow::{bail, ensure, Context, Error, Result};
+use keymeow::{Keyboard, LayoutData};
+use km_data::Data as KeymeowData;
+use std::fmt;
+use std::iter::once;
+use std::str::FromStr;
+
+/// A rearrangement of a layout's keys, as a permutation of keyboard positions.
+#[derive(Clone, Copy, Debug)]
+pub enum Transform {
+    /// Flip the layout left to right
+    Mirror,
+    /// Shift the left hand's bottom row one key left, wrapping its outer key to the inside
+    Angle,
+    /// Shift the right hand one key right, wrapping its outer column to the inside
+    Wide,
+    /// Swap two rows of the keyboard's key map, which must be the same length
+    SwapRows(usize, usize),
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+
+    /// `mirror`, `angle`, `wide` or `swap-rows=A,B`.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "mirror" => Transform::Mirror,
+            "angle" => Transform::Angle,
+            "wide" => Transform::Wide,
+            _ => match s.strip_prefix("swap-rows=").and_then(|r| r.split_once(',')) {
+                Some((a, b)) => Transform::SwapRows(
+                    a.trim().parse().context("invalid row")?,
+                    b.trim().parse().context("invalid row")?,
+                ),
+                None => {
+                    bail!("unknown transform {s} (expected mirror, angle, wide or swap-rows=A,B)")
+                }
+            },
+        })
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transform::Mirror => write!(f, "mirror"),
+            Transform::Angle => write!(f, "angle"),
+            Transform::Wide => write!(f, "wide"),
+            Transform::SwapRows(a, b) => write!(f, "swap-rows={a},{b}"),
+        }
+    }
+}
+
+/// The key map's positions, row by row.
+fn rows(keyboard: &Keyboard) -> Vec<Vec<usize>> {
+    let mut next = 0;
+    keyboard
+        .keys
+        .map
+        .iter()
+        .map(|row| {
+            next += row.len();
+            (next - row.len()..next).collect()
+        })
+        .collect()
+}
+
+/// The keys of `row` typed by the fingers in `fingers`, left to right.
+fn hand(keyboard: &Keyboard, row: &[usize], fingers: &[usize]) -> Vec<usize> {
+    let finger_of: Vec<usize> = keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .map(finger_index)
+        .collect();
+    let keys: Vec<_> = keyboard.keys.map.iter().flatten().collect();
+    let mut hand: Vec<usize> = row
+        .iter()
+        .copied()
+        .filter(|p| fingers.contains(&finger_of[*p]))
+        .collect();
+    hand.sort_by(|a, b| keys[*a].x.total_cmp(&keys[*b].x));
+    hand
+}
+
+impl Transform {
+    /// For each of a layout's `len` positions, the position its key comes from.
+    /// Combo slots are left where they are.
+    pub fn permutation(&self, keyboard: &Keyboard, len: usize) -> Result<Vec<usize>> {
+        let mut from: Vec<usize> = (0..len).collect();
+        match *self {
+            Transform::Mirror => {
+                from = mirror_positions(keyboard, len)
+                    .context("the keyboard isn't symmetrical, so can't be mirrored")?;
+            }
+            Transform::Angle => {
+                // The bottom row the left hand's fingers reach, thumbs aside
+                let keys = rows(keyboard)
+                    .iter()
+                    .rev()
+                    .map(|row| hand(keyboard, row, &[0, 1, 2, 3]))
+                    .find(|keys| keys.len() > 1)
+                    .context("the keyboard has no left-hand row to angle")?;
+                for (i, p) in keys.iter().enumerate() {
+                    from[*p] = keys[(i + 1) % keys.len()];
+                }
+            }
+            Transform::Wide => {
+                for row in rows(keyboard) {
+                    let keys = hand(keyboard, &row, &[6, 7, 8, 9]);
+                    for (i, p) in keys.iter().enumerate() {
+                        from[*p] = keys[(i + keys.len() - 1) % keys.len()];
+                    }
+                }
+            }
+            Transform::SwapRows(a, b) => {
+                let rows = rows(keyboard);
+                let (Some(row_a), Some(row_b)) = (rows.get(a), rows.get(b)) else {
+                    bail!("the keyboard only has {} rows", rows.len());
+                };
+                ensure!(
+                    row_a.len() == row_b.len(),
+                    "rows {a} and {b} have different lengths ({} and {})",
+                    row_a.len(),
+                    row_b.len()
+                );
+                for (pa, pb) in row_a.iter().zip(row_b) {
+                    from.swap(*pa, *pb);
+                }
+            }
+        }
+        Ok(from)
+    }
+}
+
+/// Apply `transforms` in order to a layout's characters, given in position order.
+pub fn apply(transforms: &[Transform], keyboard: &Keyboard, chars: &[char]) -> Result<Vec<char>> {
+    let mut chars = chars.to_vec();
+    for transform in transforms {
+        let from = transform.permutation(keyboard, chars.len())?;
+        chars = from.iter().map(|p| chars[*p]).collect();
+    }
+    Ok(chars)
+}
+
+/// The characters of `layout`, a known name or raw characters, with
+/// `transforms` applied, written as any command takes a layout.
+pub fn transformed_chars(
+    keymeow: &KeymeowData,
+    layout: &str,
+    keyboard: &Keyboard,
+    transforms: &[Transform],
+) -> Result<String> {
+    let chars = apply(
+        transforms,
+        keyboard,
+        &layout_chars(keymeow, layout, keyboard)?,
+    )?;
+    Ok(chars.iter().map(|c| blank::show(*c)).collect())
+}
+
+/// `layout` with `transforms` applied, named after both, e.g. `qwerty+angle`.
+pub fn load_transformed(
+    keymeow: &KeymeowData,
+    layout: &str,
+    keyboard: &Keyboard,
+    transforms: &[Transform],
+) -> Result<LayoutData> {
+    if transforms.is_empty() {
+        return load_layout(keymeow, layout, keyboard);
+    }
+    let chars = transformed_chars(keymeow, layout, keyboard, transforms)?;
+    let base = match keymeow.layouts.contains_key(layout) {
+        true => layout.to_string(),
+        false => home_row_name(keyboard, &layout.chars().collect::<Vec<_>>()),
+    };
+    let name: Vec<String> = once(base)
+        .chain(transforms.iter().map(Transform::to_string))
+        .collect();
+    Ok(layout_data(&chars, keyboard, &name.join("+"), false))
+}
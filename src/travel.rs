@@ -0,0 +1,100 @@
+use crate::analysis::add_stroke_amount;
+use crate::fingers::finger_index;
+
+use keycat::analysis::Nstroke;
+use keycat::NgramType;
+use keymeow::{Keyboard, Metric, MetricData};
+
+/// Center of every non-combo key, in layout order, in key units.
+fn key_centers(keyboard: &Keyboard) -> Vec<(f32, f32)> {
+    keyboard
+        .keys
+        .map
+        .iter()
+        .flatten()
+        .map(|k| (k.x + k.w / 2.0, k.y + k.h / 2.0))
+        .collect()
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// The home key of each position's finger: the key nearest that finger's other
+/// keys, preferring the one furthest from the middle of the board on ties.
+pub fn home_keys(keyboard: &Keyboard) -> Vec<usize> {
+    let centers = key_centers(keyboard);
+    let fingers: Vec<usize> = keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .map(finger_index)
+        .collect();
+    let middle = centers.iter().map(|c| c.0).sum::<f32>() / centers.len().max(1) as f32;
+
+    let mut homes = vec![0; centers.len()];
+    for finger in 0..10 {
+        let keys: Vec<usize> = (0..centers.len())
+            .filter(|k| fingers[*k] == finger)
+            .collect();
+        // Rounded so that mirror-image keys tie
+        let rank = |k: &usize| {
+            let spread: f32 = keys
+                .iter()
+                .map(|o| distance(centers[*k], centers[*o]))
+                .sum();
+            let outward = (centers[*k].0 - middle).abs();
+            (
+                (spread * 1000.0).round() as i64,
+                -(outward * 1000.0).round() as i64,
+            )
+        };
+        let Some(home) = keys.iter().copied().min_by_key(rank) else {
+            continue;
+        };
+        for k in keys {
+            homes[k] = home;
+        }
+    }
+    homes
+}
+
+/// Add "Home Distance", how far each press is from its finger's home key, and
+/// "SF Travel", how far a finger moves between the keys of a same-finger bigram.
+pub fn add_metrics(metric_data: &mut MetricData) {
+    let centers = key_centers(&metric_data.keyboard);
+    let homes = home_keys(&metric_data.keyboard);
+    let fingers: Vec<usize> = metric_data
+        .keyboard
+        .fingers
+        .map
+        .iter()
+        .flatten()
+        .map(finger_index)
+        .collect();
+
+    let home = metric_data.metrics.len();
+    metric_data.metrics.push(Metric {
+        name: "Home Distance".to_string(),
+        short: "home".to_string(),
+        ngram_type: NgramType::Monogram,
+    });
+    let sf_travel = metric_data.metrics.len();
+    metric_data.metrics.push(Metric {
+        name: "SF Travel".to_string(),
+        short: "sftravel".to_string(),
+        ngram_type: NgramType::Bigram,
+    });
+
+    for a in 0..centers.len() {
+        let to_home = distance(centers[a], centers[homes[a]]);
+        if to_home > 0.0 {
+            add_stroke_amount(metric_data, Nstroke::Monostroke(a), home, to_home);
+        }
+        for b in (0..centers.len()).filter(|b| *b != a && fingers[*b] == fingers[a]) {
+            let travel = distance(centers[a], centers[b]);
+            add_stroke_amount(metric_data, Nstroke::Bistroke([a, b]), sf_travel, travel);
+        }
+    }
+}
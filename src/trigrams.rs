@@ -0,0 +1,75 @@
+use crate::analysis::layout_matrices;
+use crate::fingers::finger_index;
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{LayoutData, MetricContext, MetricData};
+
+const BUCKETS: [&str; 7] = [
+    "Inroll",
+    "Outroll",
+    "Alternate",
+    "Onehand",
+    "Redirect",
+    "Same finger",
+    "Other",
+];
+
+/// Bucket a trigram by the fingers (0 = left pinky .. 9 = right pinky) typing it.
+fn classify(a: usize, b: usize, c: usize) -> usize {
+    let hand = |f: usize| f >= 5;
+    // Towards the thumbs on either hand
+    let inward = |x: usize, y: usize| match hand(x) {
+        false => y > x,
+        true => y < x,
+    };
+    match (hand(a) == hand(b), hand(b) == hand(c)) {
+        _ if a == b || b == c => 5,
+        (false, false) => 2,
+        (true, false) if inward(a, b) => 0,
+        (false, true) if inward(b, c) => 0,
+        (true, false) | (false, true) => 1,
+        (true, true) if inward(a, b) == inward(b, c) => 3,
+        (true, true) => 4,
+    }
+}
+
+/// Print the share of the corpus' trigrams falling into each pattern on `layout`.
+pub fn trigram_summary(metric_data: MetricData, corpus: Corpus, layout: LayoutData) -> Result<()> {
+    let ctx = MetricContext::new(&layout, metric_data, corpus)
+        .context("could not produce metric context")?;
+    let matrix = layout_matrices(&ctx, &[layout])?.remove(0);
+    let corpus = &ctx.analyzer.corpus;
+
+    let mut fingers: Vec<Option<usize>> = vec![None; corpus.char_list.len()];
+    for (c, finger) in matrix
+        .0
+        .iter()
+        .zip(ctx.keyboard.fingers.map.iter().flatten())
+    {
+        if *c != 0 {
+            fingers[*c] = Some(finger_index(finger));
+        }
+    }
+
+    let mut counts = [0.0_f64; BUCKETS.len()];
+    let len = corpus.char_list.len();
+    for a in 1..len {
+        for b in 1..len {
+            for c in 1..len {
+                let count = corpus.trigrams[corpus.trigram_idx(a, b, c)] as f64;
+                let bucket = match (fingers[a], fingers[b], fingers[c]) {
+                    (Some(fa), Some(fb), Some(fc)) => classify(fa, fb, fc),
+                    _ => BUCKETS.len() - 1,
+                };
+                counts[bucket] += count;
+            }
+        }
+    }
+
+    let total: f64 = counts.iter().sum::<f64>().max(1.0);
+    for (name, count) in BUCKETS.iter().zip(counts) {
+        println!("{name:<12}{:>7.2}%", count / total * 100.0);
+    }
+    Ok(())
+}
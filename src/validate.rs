@@ -0,0 +1,149 @@
+//! Re-scoring generated layouts on a corpus they weren't optimized on, to
+//! catch ones that learned the quirks of their corpus rather than the language.
+
+use crate::analysis::{context_stats, resolve_weights};
+use crate::evaluation::Evaluator;
+use crate::export::layout_data;
+use crate::naming::{home_row_name, Names};
+
+use anyhow::{Context, Result};
+use keycat::Corpus;
+use keymeow::{Keyboard, LayoutData, MetricContext, MetricData};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Layouts from generated characters, named after their home rows. Only the
+/// keys are kept, so layers and combos aren't scored.
+pub fn generated_layouts<'a>(
+    keyboard: &Keyboard,
+    results: impl IntoIterator<Item = &'a str>,
+) -> Vec<LayoutData> {
+    let keys = keyboard.keys.map.iter().flatten().count();
+    let mut names = Names::default();
+    results
+        .into_iter()
+        .map(|chars| {
+            let base: String = chars.chars().take(keys).collect();
+            let name = names.unique(home_row_name(keyboard, &base.chars().collect::<Vec<_>>()));
+            layout_data(&base, keyboard, &name, false)
+        })
+        .collect()
+}
+
+/// The characters of the best `count` results of the generation tsv at `path`.
+pub fn best_results(path: &Path, count: usize) -> Result<Vec<String>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("couldn't read {}", path.display()))?;
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines
+        .next()
+        .context("empty generations file")?
+        .split('\t')
+        .collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| *h == name)
+            .with_context(|| format!("{} has no {name} column", path.display()))
+    };
+    let (score, layout) = (column("score")?, column("layout")?);
+    let results: Result<Vec<(f32, String)>> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let value = |c: usize| fields.get(c).context("missing column");
+            Ok((value(score)?.parse()?, value(layout)?.to_string()))
+        })
+        .collect();
+    let mut results = results?;
+    results.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(results
+        .into_iter()
+        .take(count)
+        .map(|(_, chars)| chars)
+        .collect())
+}
+
+/// Score `layouts` by `metrics` on the `train` corpus they were optimized on
+/// and the held-out `test` one, and write each one's change in score and
+/// per-metric percentages, with its rank on each as `train>test`. Scores are
+/// weighted sums of percentages, so they compare across corpora; a layout
+/// whose score worsens by more than `tolerance` of itself is flagged as overfit.
+pub fn validate(
+    metrics: &[(String, i16)],
+    metric_data: MetricData,
+    train: Corpus,
+    test: Corpus,
+    layouts: &[LayoutData],
+    tolerance: f32,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let weights = resolve_weights(metrics, &metric_data)?;
+    let evaluator = Evaluator::from(weights.clone());
+    let first = layouts.first().context("no layouts to validate")?;
+    let context = |corpus| {
+        MetricContext::new(first, metric_data.clone(), corpus)
+            .context("could not produce metric context")
+    };
+    let train = context_stats(&context(train)?, layouts)?;
+    let test = context_stats(&context(test)?, layouts)?;
+    let train: Vec<Vec<f32>> = train.iter().map(|s| s.percentages()).collect();
+    let test: Vec<Vec<f32>> = test.iter().map(|s| s.percentages()).collect();
+    let train_scores: Vec<f32> = train.iter().map(|p| evaluator.eval(p)).collect();
+    let test_scores: Vec<f32> = test.iter().map(|p| evaluator.eval(p)).collect();
+    let ranks = |scores: &[f32]| {
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|a, b| scores[*a].total_cmp(&scores[*b]));
+        let mut ranks = vec![0; scores.len()];
+        for (rank, i) in order.into_iter().enumerate() {
+            ranks[i] = rank + 1;
+        }
+        ranks
+    };
+    let (train_ranks, test_ranks) = (ranks(&train_scores), ranks(&test_scores));
+
+    let width = layouts
+        .iter()
+        .map(|l| l.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    write!(
+        output,
+        "{:>5}  {:width$}{:>10}{:>10}{:>10}",
+        "rank", "name", "train", "test", "delta"
+    )?;
+    for (name, _) in metrics {
+        write!(output, "{name:>10}")?;
+    }
+    writeln!(output)?;
+    let mut overfit = 0;
+    for (i, layout) in layouts.iter().enumerate() {
+        let (before, after) = (train_scores[i], test_scores[i]);
+        let rank = format!("{}>{}", train_ranks[i], test_ranks[i]);
+        write!(
+            output,
+            "{rank:>5}  {:width$}{before:>10.4}{after:>10.4}{:>+10.4}",
+            layout.name,
+            after - before
+        )?;
+        for (m, _) in &weights {
+            write!(output, "{:>+9.3}%", test[i][*m] - train[i][*m])?;
+        }
+        if after - before > tolerance * before.abs() {
+            overfit += 1;
+            write!(output, "  overfit")?;
+        }
+        writeln!(output)?;
+    }
+    if overfit > 0 {
+        writeln!(
+            output,
+            "{overfit} of {} layouts score over {}% worse on the held-out corpus",
+            layouts.len(),
+            tolerance * 100.0
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! A JavaScript API for the `wasm` feature, built with e.g.
+//! `wasm-pack build -- --features wasm`. A browser has no data directory, so
+//! metric data, corpora and layouts are passed in as the objects keymeow and
+//! keycat serialize to.
+
+use crate::analysis::{layout_stats, weighted_context};
+use crate::optimization::GenerationStrategy;
+
+use clap::ValueEnum;
+use keymeow::LayoutData;
+use serde::Serialize;
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+fn js_error(e: anyhow::Error) -> JsError {
+    JsError::new(&format!("{e:#}"))
+}
+
+/// Plain objects rather than the `Map`s serde-wasm-bindgen makes by default.
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsError> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// Every metric for each layout, as `{name, metrics: [{name, raw, percentage}]}` objects.
+#[wasm_bindgen]
+pub fn stats(metric_data: JsValue, corpus: JsValue, layouts: JsValue) -> Result<JsValue, JsError> {
+    let layouts: Vec<LayoutData> = serde_wasm_bindgen::from_value(layouts)?;
+    let stats = layout_stats(
+        serde_wasm_bindgen::from_value(metric_data)?,
+        serde_wasm_bindgen::from_value(corpus)?,
+        &layouts,
+    )
+    .map_err(js_error)?;
+    to_js(&stats)
+}
+
+/// Generate one layout of `char_set`, weighting metrics as `[name, weight]`
+/// pairs. Returns the layout's characters, its score and iterations, and the
+/// weighted metrics' percentages.
+#[wasm_bindgen]
+pub fn generate(
+    metric_data: JsValue,
+    corpus: JsValue,
+    metrics: JsValue,
+    char_set: &str,
+    strategy: &str,
+    pin: usize,
+) -> Result<JsValue, JsError> {
+    let strategy = GenerationStrategy::from_str(strategy, true).map_err(|e| JsError::new(&e))?;
+    let metrics: Vec<(String, i16)> = serde_wasm_bindgen::from_value(metrics)?;
+    let context = weighted_context(
+        serde_wasm_bindgen::from_value(metric_data)?,
+        serde_wasm_bindgen::from_value(corpus)?,
+        &metrics,
+        char_set,
+        strategy,
+        None,
+    )
+    .map_err(js_error)?
+    .with_swaps(pin, false);
+    let result = context.optimize(0, None, None);
+    to_js(&json!({
+        "layout": result.chars,
+        "score": result.score,
+        "iterations": result.iterations,
+        "metrics": metrics.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        "percentages": context.percentages(&result.stats),
+    }))
+}